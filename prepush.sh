@@ -1,3 +1,4 @@
 cargo fmt --all -- --check
 cargo clippy --all-targets -- -D clippy::all
-cargo test
\ No newline at end of file
+cargo test
+cargo build --lib --no-default-features
\ No newline at end of file
@@ -14,7 +14,69 @@ pub struct DebugOp {
     pub original_line: String,
     pub line_num: usize,
     pub processed_line: String,
+    #[serde(deserialize_with = "hex_bytes::deserialize")]
+    #[cfg_attr(
+        feature = "hex_debug_bytes",
+        serde(serialize_with = "hex_bytes::serialize")
+    )]
     pub bytes: Vec<u8>,
+    ///True for `.db`/`.dw` bytes, which aren't an instruction
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub is_data: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+///Op bytes as a hex string, e.g. `"0A0B"`, reading also accepts an array of numbers
+mod hex_bytes {
+    use serde::de::{Error, SeqAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    #[cfg_attr(not(feature = "hex_debug_bytes"), allow(dead_code))]
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let text: String = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        serializer.serialize_str(&text)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_any(BytesVisitor)
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            write!(formatter, "an array of bytes or a hex string")
+        }
+
+        fn visit_str<E: Error>(self, value: &str) -> Result<Vec<u8>, E> {
+            if !value.len().is_multiple_of(2) {
+                return Err(E::custom(format!("Odd number of hex digits: {}", value)));
+            }
+            (0..value.len())
+                .step_by(2)
+                .map(|idx| {
+                    value
+                        .get(idx..idx + 2)
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                        .ok_or_else(|| E::custom(format!("Invalid hex bytes: {}", value)))
+                })
+                .collect()
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+            let mut bytes = vec![];
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -24,6 +86,8 @@ pub struct DebugString {
     content: String,
     original_line: String,
     pub line_num: usize,
+    ///Index of the `=` in `original_line`
+    pub column: usize,
     pub usage: Vec<DebugUsage>,
 }
 
@@ -31,15 +95,17 @@ pub struct DebugString {
 pub struct DebugData {
     addr: u16,
     pub(crate) key: String,
-    content: Vec<Vec<u8>>,
+    pub(crate) content: Vec<Vec<u8>>,
     original_line: String,
     pub line_num: usize,
+    ///Index of the `=` in `original_line`
+    pub column: usize,
     pub usage: Vec<DebugUsage>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct DebugLabel {
-    byte: u16,
+    pub(crate) byte: u16,
     pub(crate) name: String,
     original_line: String,
     line_num: usize,
@@ -80,6 +146,51 @@ impl DebugModel {
             .find(|op| op.line_num == line)
             .map(|op| op.byte_addr)
     }
+
+    //If more than one name shares an address the first alphabetically is returned
+    pub fn string_key_for_addr(&self, addr: u16) -> Option<&str> {
+        self.strings
+            .iter()
+            .filter(|string| string.addr == addr)
+            .map(|string| string.key.as_str())
+            .min()
+    }
+
+    pub fn data_key_for_addr(&self, addr: u16) -> Option<&str> {
+        self.data
+            .iter()
+            .filter(|data| data.addr == addr)
+            .map(|data| data.key.as_str())
+            .min()
+    }
+
+    ///Address in the data section that `LD <addr_reg> key array byte` would load, see language.md
+    ///Returns None if the key doesn't exist or the array is out of range (the device would error)
+    pub fn data_addr(&self, key: &str, array: u8, byte: u8) -> Option<u16> {
+        let data = self.data.iter().find(|data| data.key == key)?;
+        if array as usize > data.content.len() {
+            return None;
+        }
+        let mut offset = 0;
+        if array > 0 {
+            offset += 1 + data.content.len() as u16;
+            for bytes in data.content.iter().take(array as usize - 1) {
+                offset += bytes.len() as u16;
+            }
+        }
+        Some(data.addr + offset + byte as u16)
+    }
+
+    pub fn labels_for_byte(&self, byte: u16) -> Vec<&str> {
+        let mut names = self
+            .labels
+            .iter()
+            .filter(|label| label.byte == byte)
+            .map(|label| label.name.as_str())
+            .collect::<Vec<&str>>();
+        names.sort_unstable();
+        names
+    }
 }
 
 impl DebugOp {
@@ -96,6 +207,7 @@ impl DebugOp {
             line_num,
             processed_line,
             bytes,
+            is_data: false,
         }
     }
 }
@@ -107,6 +219,7 @@ impl DebugString {
         content: String,
         original_line: String,
         line_num: usize,
+        column: usize,
     ) -> Self {
         DebugString {
             addr,
@@ -114,6 +227,7 @@ impl DebugString {
             content,
             original_line,
             line_num,
+            column,
             usage: vec![],
         }
     }
@@ -126,6 +240,7 @@ impl DebugData {
         content: Vec<Vec<u8>>,
         original_line: String,
         line_num: usize,
+        column: usize,
     ) -> Self {
         DebugData {
             addr,
@@ -133,6 +248,7 @@ impl DebugData {
             content,
             original_line,
             line_num,
+            column,
             usage: vec![],
         }
     }
@@ -159,3 +275,45 @@ impl DebugUsage {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn op() -> DebugOp {
+        DebugOp::new(
+            3,
+            String::from("cpy d0 xA"),
+            4,
+            String::from("cpy d0 xA"),
+            vec![0x0A, 0x0B, 0xFF],
+        )
+    }
+
+    #[test]
+    fn test_hex_bytes() {
+        let mut json = vec![];
+        hex_bytes::serialize(&op().bytes, &mut serde_json::Serializer::new(&mut json)).unwrap();
+        assert_eq!(String::from_utf8(json).unwrap(), "\"0A0BFF\"");
+
+        let text = "{\"byte_addr\":3,\"original_line\":\"cpy d0 xA\",\"line_num\":4,\"processed_line\":\"cpy d0 xA\",\"bytes\":";
+        assert_eq!(
+            serde_json::from_str::<DebugOp>(&format!("{}\"0a0BFF\"}}", text)).unwrap(),
+            op()
+        );
+        assert_eq!(
+            serde_json::from_str::<DebugOp>(&format!("{}[10,11,255]}}", text)).unwrap(),
+            op()
+        );
+        assert!(serde_json::from_str::<DebugOp>(&format!("{}\"0A0\"}}", text)).is_err());
+        assert!(serde_json::from_str::<DebugOp>(&format!("{}\"0G\"}}", text)).is_err());
+
+        let json = serde_json::to_string(&op()).unwrap();
+        if cfg!(feature = "hex_debug_bytes") {
+            assert!(json.contains("\"bytes\":\"0A0BFF\""), "{}", json);
+        } else {
+            assert!(json.contains("\"bytes\":[10,11,255]"), "{}", json);
+        }
+        assert_eq!(serde_json::from_str::<DebugOp>(&json).unwrap(), op());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::assembler::debug_model::DebugModel;
+use anyhow::{Error, Result};
+use std::cmp::Ordering;
+use std::io::Write;
+
+const PACK_HEADER: &[u8; 4] = b"TDDP";
+const PACK_VERSION: u8 = 1;
+///Byte address (u16) followed by line number (u32), both big endian
+const ENTRY_LEN: usize = 6;
+
+///Writes the byte address and line number of every op
+///Entries are fixed size and sorted by byte address so the pack can be searched without parsing it
+///
+///Layout: `TDDP <version> <entry count: u16> [<byte addr: u16> <line num: u32>]`
+pub fn write_debug_pack<W: Write>(model: &DebugModel, writer: &mut W) -> Result<()> {
+    if model.ops.len() > u16::MAX as usize {
+        return Err(Error::msg(format!(
+            "Too many ops for debug pack: {}, max {}",
+            model.ops.len(),
+            u16::MAX
+        )));
+    }
+    let mut entries = model
+        .ops
+        .iter()
+        .map(|op| (op.byte_addr, op.line_num))
+        .collect::<Vec<(u16, usize)>>();
+    entries.sort_unstable();
+
+    let mut output = Vec::with_capacity(7 + entries.len() * ENTRY_LEN);
+    output.extend_from_slice(PACK_HEADER);
+    output.push(PACK_VERSION);
+    output.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+    for (byte_addr, line_num) in entries {
+        if line_num > u32::MAX as usize {
+            return Err(Error::msg(format!(
+                "Line number too large for debug pack: {}",
+                line_num
+            )));
+        }
+        output.extend_from_slice(&byte_addr.to_be_bytes());
+        output.extend_from_slice(&(line_num as u32).to_be_bytes());
+    }
+    writer.write_all(&output)?;
+    Ok(())
+}
+
+///Reads a pack written by `write_debug_pack` without copying it
+pub struct DebugPack<'a> {
+    entries: &'a [u8],
+}
+
+impl<'a> DebugPack<'a> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
+        if bytes.len() < 7 || &bytes[..4] != PACK_HEADER {
+            return Err(Error::msg("Not a debug pack"));
+        }
+        if bytes[4] != PACK_VERSION {
+            return Err(Error::msg(format!(
+                "Unsupported debug pack version: {}",
+                bytes[4]
+            )));
+        }
+        let count = u16::from_be_bytes([bytes[5], bytes[6]]) as usize;
+        let entries = &bytes[7..];
+        if entries.len() != count * ENTRY_LEN {
+            return Err(Error::msg(format!(
+                "Debug pack has {} bytes of entries, expected {}",
+                entries.len(),
+                count * ENTRY_LEN
+            )));
+        }
+        Ok(DebugPack { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() / ENTRY_LEN
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    ///Line of the op starting at `byte`
+    pub fn line_for_byte(&self, byte: u16) -> Option<usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            let entry = &self.entries[mid * ENTRY_LEN..(mid + 1) * ENTRY_LEN];
+            let addr = u16::from_be_bytes([entry[0], entry[1]]);
+            match addr.cmp(&byte) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => {
+                    return Some(
+                        u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]) as usize
+                    )
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_str;
+
+    #[test]
+    fn test_round_trip() {
+        let (_, model) = assemble_str(
+            "Test Prog\n1.0\n.ops\nCPY D0 10\n\n# comment\nCPY D2 xF\nADD D0 D2",
+            false,
+        )
+        .unwrap();
+        let mut bytes = vec![];
+        write_debug_pack(&model, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 7 + 3 * ENTRY_LEN);
+
+        let pack = DebugPack::from_bytes(&bytes).unwrap();
+        assert_eq!(pack.len(), 3);
+        for op in &model.ops {
+            assert_eq!(pack.line_for_byte(op.byte_addr), Some(op.line_num));
+        }
+        assert_eq!(pack.line_for_byte(0), Some(4));
+        assert_eq!(pack.line_for_byte(3), Some(7));
+        assert_eq!(pack.line_for_byte(6), Some(8));
+        assert_eq!(pack.line_for_byte(1), None);
+        assert_eq!(pack.line_for_byte(9), None);
+
+        assert!(DebugPack::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(DebugPack::from_bytes(b"TDDX\x01\x00\x00").is_err());
+    }
+}
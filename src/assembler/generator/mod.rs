@@ -2,23 +2,67 @@ use crate::assembler::debug_model::{
     DebugData, DebugLabel, DebugModel, DebugOp, DebugString, DebugUsage,
 };
 use crate::assembler::program_model::{
-    AddressReplacement, DataModel, LabelModel, OpModel, ProgramModel, StringModel,
+    AddressReplacement, DataModel, Definition, LabelModel, OpModel, ProgramModel, StringModel,
 };
-use crate::constants::hardware::{MAX_DATA_BYTES, MAX_STRING_BYTES};
-use crate::constants::system::{PRG_VERSION, TAPE_HEADER_1, TAPE_HEADER_2};
-use crate::constants::{get_addr_byte_offset, get_byte_count};
+use crate::common::Crc32;
+use crate::constants::hardware::{MAX_DATA_BYTES, MAX_STRING_BYTES, MAX_STRING_LEN};
+use crate::constants::system::{
+    PRG_VERSION, PRG_VERSION_RELOCATABLE, TAPE_HEADER_1, TAPE_HEADER_2,
+};
+use crate::constants::{addr_to_bytes, get_addr_byte_offset, patch_addr, Address};
+use crate::language::parser::params::Param;
+use crate::tape_reader::{relocation_bytes, Relocation, Section};
 use anyhow::{Error, Result};
 use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::io::Write;
 
+/// The model isn't checked, e.g. a program with no ops is allowed, call `ProgramModel::validate` first to reject it
 pub fn generate_byte_code(program_model: ProgramModel) -> Result<(Vec<u8>, DebugModel)> {
+    let mut output = vec![];
+    let debug_model = generate_byte_code_to(program_model, &mut output)?;
+    Ok((output, debug_model))
+}
+
+/// Same as `generate_byte_code` but writes the bytes to `writer`
+/// The header and ops are built in memory so addresses can be updated, strings and data are written directly
+pub fn generate_byte_code_to<W: Write>(
+    program_model: ProgramModel,
+    writer: &mut W,
+) -> Result<DebugModel> {
+    //Name and version are prefixed with a single byte length
+    for (field, value) in [
+        ("name", &program_model.name),
+        ("version", &program_model.version),
+    ] {
+        if value.len() > MAX_STRING_LEN {
+            return Err(Error::msg(format!(
+                "Program {} is {} bytes, max {}",
+                field,
+                value.len(),
+                MAX_STRING_LEN
+            )));
+        }
+    }
+
     //Write header
-    //0xFD A0 01 <name len> <name> <ver len> <ver>
+    //0xFD A0 03 <name len> <name> <ver len> <ver> <entry>
     let mut output = vec![TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION];
     let mut debug_model = DebugModel::default();
     output.push(program_model.name.len() as u8);
     output.extend_from_slice(program_model.name.as_bytes());
     output.push(program_model.version.len() as u8);
     output.extend_from_slice(program_model.version.as_bytes());
+    let entry_byte = output.len(); //written once label addresses are known
+    output.extend_from_slice(&addr_to_bytes(0));
+
+    let entry_label = program_model.entry.clone().or_else(|| {
+        program_model
+            .labels
+            .get("main")
+            .filter(|label| label.definition.is_some())
+            .map(|label| label.key.clone())
+    });
 
     let op_byte_start = output.len() + 2; //+2 for op byte count written once len is known
 
@@ -26,7 +70,8 @@ pub fn generate_byte_code(program_model: ProgramModel) -> Result<(Vec<u8>, Debug
     let (string_bytes, string_addresses) =
         generate_string_bytes(program_model.strings, &mut debug_model)?;
 
-    let (data_bytes, data_addresses) = generate_data_bytes(program_model.data, &mut debug_model)?;
+    let (mut data_bytes, data_addresses) =
+        generate_data_bytes(program_model.data, &mut debug_model)?;
 
     //Generate and write op bytes
     let ops_output = generate_ops_bytes(
@@ -35,12 +80,37 @@ pub fn generate_byte_code(program_model: ProgramModel) -> Result<(Vec<u8>, Debug
         program_model.labels,
         &mut debug_model,
         string_addresses,
-        data_addresses,
+        data_addresses.clone(),
     )?;
 
-    output.extend_from_slice(&(ops_output.bytes.len() as u16).to_be_bytes());
+    let jump_table_relocations = write_jump_tables(
+        &mut data_bytes,
+        &mut debug_model,
+        program_model.jump_tables,
+        &data_addresses,
+        &ops_output.label_addresses,
+    );
+
+    output.extend_from_slice(&section_len("Ops", ops_output.bytes.len())?);
     output.extend_from_slice(&ops_output.bytes);
 
+    let mut relocations = ops_output.relocations;
+    for offsets in ops_output.label_targets.values() {
+        relocations.extend(offsets.iter().map(|offset| {
+            Relocation::new(Section::Ops, Section::Ops, offset - op_byte_start as u16)
+        }));
+    }
+    relocations.sort_unstable_by_key(|relocation| relocation.high);
+    relocations.extend(jump_table_relocations);
+
+    if let Some(label) = entry_label {
+        let addr = ops_output
+            .label_addresses
+            .get(&label)
+            .ok_or_else(|| Error::msg(format!("Entry point label {} has no definition", label)))?;
+        patch_addr(&mut output, entry_byte, *addr);
+    }
+
     //Now all label positions are known, update addresses
     output = update_addresses(
         output,
@@ -50,12 +120,82 @@ pub fn generate_byte_code(program_model: ProgramModel) -> Result<(Vec<u8>, Debug
         &mut debug_model,
     );
 
-    //Write string len, string bytes and data bytes
-    output.extend_from_slice(&(string_bytes.len() as u16).to_be_bytes());
-    output.extend_from_slice(&string_bytes);
-    output.extend_from_slice(&data_bytes);
+    //Write string len, string bytes, the relocation table if relocatable and data bytes,
+    //then the checksum of everything before it
+    let (relocation_len, relocation_bytes) = match program_model.relocatable {
+        true => {
+            output[2] = PRG_VERSION_RELOCATABLE;
+            let bytes = relocation_bytes(&relocations);
+            (section_len("Relocations", bytes.len())?.to_vec(), bytes)
+        }
+        false => (vec![], vec![]),
+    };
+    let mut crc = Crc32::new();
+    let string_len = section_len("Strings", string_bytes.len())?;
+    for bytes in [
+        &output[..],
+        &string_len,
+        &string_bytes,
+        &relocation_len,
+        &relocation_bytes,
+        &data_bytes,
+    ] {
+        crc.update(bytes);
+        writer.write_all(bytes)?;
+    }
+    writer.write_all(&crc.finish().to_be_bytes())?;
 
-    Ok((output, debug_model))
+    Ok(debug_model)
+}
+
+///Writes the label addresses into the `.jumptable` data blocks, high bytes in the first array and low bytes in the second
+///Returns the relocations for the written addresses, sorted by offset
+fn write_jump_tables(
+    data_bytes: &mut [u8],
+    debug: &mut DebugModel,
+    jump_tables: HashMap<String, Vec<String>>,
+    data_addresses: &HashMap<String, Address>,
+    label_addresses: &HashMap<String, Address>,
+) -> Vec<Relocation> {
+    let mut relocations = vec![];
+    for (key, labels) in jump_tables {
+        let start = data_addresses[&key] as usize + 3;
+        let debug_data = debug.data.iter_mut().find(|datum| datum.key == key);
+        let mut interpretation = vec![vec![], vec![]];
+        for (idx, label) in labels.iter().enumerate() {
+            //Undefined labels have already been reported by `generate_ops_bytes`
+            let [high, low] = addr_to_bytes(label_addresses[label]);
+            data_bytes[start + idx] = high;
+            data_bytes[start + labels.len() + idx] = low;
+            relocations.push(Relocation {
+                location: Section::Data,
+                target: Section::Ops,
+                high: (start + idx) as u16,
+                low: (start + labels.len() + idx) as u16,
+            });
+            interpretation[0].push(high);
+            interpretation[1].push(low);
+        }
+        if let Some(debug_data) = debug_data {
+            debug_data.content = interpretation;
+        }
+    }
+    relocations.sort_unstable_by_key(|relocation| relocation.high);
+    relocations
+}
+
+///Length prefix for a section, errors instead of truncating if it doesn't fit in a u16
+fn section_len(name: &str, len: usize) -> Result<[u8; 2]> {
+    u16::try_from(len)
+        .map(|len| len.to_be_bytes())
+        .map_err(|_| {
+            Error::msg(format!(
+                "{} section is {} bytes, max {}",
+                name,
+                len,
+                u16::MAX
+            ))
+        })
 }
 
 /// Replace placeholder address bytes with actual values
@@ -66,23 +206,23 @@ pub fn generate_byte_code(program_model: ProgramModel) -> Result<(Vec<u8>, Debug
 fn update_addresses(
     mut bytes: Vec<u8>,
     targets: HashMap<String, Vec<u16>>,
-    sources: HashMap<String, u16>,
+    sources: HashMap<String, Address>,
     op_byte_start: usize,
     debug: &mut DebugModel,
 ) -> Vec<u8> {
+    //Sorted so a missing op always panics on the same key
+    let mut sources = sources.into_iter().collect::<Vec<(String, Address)>>();
+    sources.sort_unstable();
     for (key, source) in sources {
         if let Some(op_offsets) = targets.get(&key) {
             for offset in op_offsets {
-                let addr = source.to_be_bytes();
-                bytes[*offset as usize] = addr[0];
-                bytes[(*offset + 1) as usize] = addr[1];
+                patch_addr(&mut bytes, *offset as usize, source);
                 let op_offset = *offset - (op_byte_start as u16);
                 let debug_op = debug
                     .ops
                     .iter_mut()
                     .find(|op| {
-                        op.byte_addr < op_offset
-                            && op_offset < op.byte_addr + get_byte_count(op.bytes[0]) as u16
+                        op.byte_addr < op_offset && op_offset < op.byte_addr + op.bytes.len() as u16
                     })
                     .unwrap_or_else(|| {
                         panic!(
@@ -91,8 +231,7 @@ fn update_addresses(
                         )
                     });
                 let local_offset = op_offset - debug_op.byte_addr;
-                debug_op.bytes[local_offset as usize] = addr[0];
-                debug_op.bytes[(local_offset + 1) as usize] = addr[1];
+                patch_addr(&mut debug_op.bytes, local_offset as usize, source);
             }
         }
     }
@@ -103,7 +242,9 @@ fn update_addresses(
 struct OpsOutput {
     bytes: Vec<u8>,
     label_targets: HashMap<String, Vec<u16>>,
-    label_addresses: HashMap<String, u16>,
+    label_addresses: HashMap<String, Address>,
+    ///String and data addresses, label offsets are in `label_targets`
+    relocations: Vec<Relocation>,
 }
 
 fn generate_ops_bytes(
@@ -111,27 +252,19 @@ fn generate_ops_bytes(
     offset: usize,
     labels: HashMap<String, LabelModel>,
     debug: &mut DebugModel,
-    string_addresses: HashMap<String, u16>,
-    data_addresses: HashMap<String, u16>,
+    string_addresses: HashMap<String, Address>,
+    data_addresses: HashMap<String, Address>,
 ) -> Result<OpsOutput> {
-    let mut labels: BTreeMap<usize, LabelModel> = convert_label_map_to_linenum(labels);
+    let mut labels = convert_label_map_to_linenum(labels)?;
     let mut output = OpsOutput::default();
     for op in ops {
-        if !labels.is_empty() {
-            let lbl = labels.values().next().unwrap();
-            let lbl_line_num = lbl.definition.as_ref().unwrap().line_num;
-            if lbl_line_num <= op.line_num {
-                let original_line = lbl.definition.as_ref().unwrap().original_line.clone();
-                debug.labels.push(DebugLabel::new(
-                    output.bytes.len() as u16,
-                    lbl.key.clone(),
-                    original_line,
-                    lbl_line_num,
-                ));
-                output
-                    .label_addresses
-                    .insert(lbl.key.clone(), output.bytes.len() as u16);
-                labels.remove(&lbl_line_num);
+        //All labels defined since the last op point at this op
+        while let Some(entry) = labels.first_entry() {
+            if *entry.key() > op.line_num {
+                break;
+            }
+            for (key, definition) in entry.remove() {
+                define_label(&mut output, debug, key, definition);
             }
         }
         let (mut bytes, replacement) = op.to_bytes();
@@ -152,6 +285,14 @@ fn generate_ops_bytes(
                         .push((output.bytes.len() + param_offset + offset) as u16);
                 }
                 AddressReplacement::Str(key) => {
+                    let addr = string_addresses.get(&key).ok_or_else(|| {
+                        Error::msg(format!(
+                            "Undefined string `{}` used on line {}: {}",
+                            key,
+                            op.line_num,
+                            op.original_line.trim()
+                        ))
+                    })?;
                     debug
                         .strings
                         .iter_mut()
@@ -168,19 +309,22 @@ fn generate_ops_bytes(
                             param_offset as u8,
                             op.line_num,
                         ));
-                    let addr = string_addresses
-                        .get(&key)
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "Unknown string '{}' found in generation on line {} (e2)",
-                                key, op.line_num
-                            )
-                        })
-                        .to_be_bytes();
-                    bytes[param_offset] = addr[0];
-                    bytes[param_offset + 1] = addr[1];
+                    patch_addr(&mut bytes, param_offset, *addr);
+                    output.relocations.push(Relocation::new(
+                        Section::Ops,
+                        Section::Strings,
+                        (output.bytes.len() + param_offset) as u16,
+                    ));
                 }
                 AddressReplacement::Data(key) => {
+                    let addr = data_addresses.get(&key).ok_or_else(|| {
+                        Error::msg(format!(
+                            "Undefined data `{}` used on line {}: {}",
+                            key,
+                            op.line_num,
+                            op.original_line.trim()
+                        ))
+                    })?;
                     debug
                         .data
                         .iter_mut()
@@ -197,37 +341,61 @@ fn generate_ops_bytes(
                             param_offset as u8,
                             op.line_num,
                         ));
-                    let addr = data_addresses
-                        .get(&key)
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "Unknown data '{}' found in generation on line {} (e2)",
-                                key, op.line_num
-                            )
-                        })
-                        .to_be_bytes();
-                    bytes[param_offset] = addr[0];
-                    bytes[param_offset + 1] = addr[1];
+                    patch_addr(&mut bytes, param_offset, *addr);
+                    output.relocations.push(Relocation::new(
+                        Section::Ops,
+                        Section::Data,
+                        (output.bytes.len() + param_offset) as u16,
+                    ));
                 }
             };
         }
-        debug.ops.push(DebugOp::new(
+        let mut debug_op = DebugOp::new(
             output.bytes.len() as u16,
             op.original_line.clone(),
             op.line_num,
             op.after_processing.clone(),
             bytes.clone(),
-        ));
+        );
+        debug_op.is_data = op.is_inline_data();
+        debug.ops.push(debug_op);
         output.bytes.extend_from_slice(&bytes);
     }
 
+    //Labels after the last op point at the end of the ops
+    for (key, definition) in labels.into_values().flatten() {
+        define_label(&mut output, debug, key, definition);
+    }
+
+    let undefined = ops
+        .iter()
+        .flat_map(|op| {
+            op.params.iter().filter_map(move |param| match param {
+                Param::Label(key) | Param::LabelAddr(key) => Some((key, op)),
+                _ => None,
+            })
+        })
+        .filter(|(key, _)| !output.label_addresses.contains_key(*key))
+        .map(|(key, op)| {
+            format!(
+                "Undefined label `{}` used on line {}: {}",
+                key,
+                op.line_num,
+                op.original_line.trim()
+            )
+        })
+        .collect::<Vec<String>>();
+    if !undefined.is_empty() {
+        return Err(Error::msg(undefined.join("\n")));
+    }
+
     Ok(output)
 }
 
 fn generate_data_bytes(
     data: HashMap<String, DataModel>,
     debug: &mut DebugModel,
-) -> Result<(Vec<u8>, HashMap<String, u16>)> {
+) -> Result<(Vec<u8>, HashMap<String, Address>)> {
     let mut output = vec![];
     let mut addresses = HashMap::new();
     let mut list: Vec<(String, DataModel)> = data.into_iter().collect();
@@ -249,6 +417,7 @@ fn generate_data_bytes(
             data_model.interpretation,
             data_model.definition.original_line.clone(),
             data_model.definition.line_num,
+            definition_column(&data_model.definition.original_line),
         ));
         output.extend_from_slice(&data_model.content);
     }
@@ -259,19 +428,21 @@ fn generate_data_bytes(
 fn generate_string_bytes(
     strings: HashMap<String, StringModel>,
     debug: &mut DebugModel,
-) -> Result<(Vec<u8>, HashMap<String, u16>)> {
+) -> Result<(Vec<u8>, HashMap<String, Address>)> {
     let mut output = vec![];
     let mut addresses = HashMap::new();
     let mut list: Vec<(String, StringModel)> = strings.into_iter().collect();
     list.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
     for (key, string_model) in list {
-        if (output.len() + string_model.content.len()) > MAX_STRING_BYTES {
+        //+1 for the length byte
+        let len = output.len() + 1 + string_model.content.len();
+        if len > MAX_STRING_BYTES {
             return Err(Error::msg(format!(
                 "Too many strings at `{}` on line {}, max {} bytes but is at least {} bytes",
                 string_model.definition.original_line,
                 string_model.definition.line_num,
                 MAX_STRING_BYTES,
-                output.len() + string_model.content.len()
+                len
             )));
         }
         addresses.insert(key.clone(), output.len() as u16);
@@ -281,6 +452,7 @@ fn generate_string_bytes(
             string_model.content.clone(),
             string_model.definition.original_line.clone(),
             string_model.definition.line_num,
+            definition_column(&string_model.definition.original_line),
         ));
         output.push(string_model.content.len() as u8);
         output.extend_from_slice(string_model.content.as_bytes());
@@ -289,23 +461,66 @@ fn generate_string_bytes(
     Ok((output, addresses))
 }
 
+fn definition_column(line: &str) -> usize {
+    line.find('=').unwrap_or(0)
+}
+
+fn define_label(
+    output: &mut OpsOutput,
+    debug: &mut DebugModel,
+    key: String,
+    definition: Definition,
+) {
+    debug.labels.push(DebugLabel::new(
+        output.bytes.len() as u16,
+        key.clone(),
+        definition.original_line,
+        definition.line_num,
+    ));
+    output
+        .label_addresses
+        .insert(key, output.bytes.len() as u16);
+}
+
 fn convert_label_map_to_linenum(
     labels: HashMap<String, LabelModel>,
-) -> BTreeMap<usize, LabelModel> {
-    labels
-        .into_iter()
-        .map(|(_, model)| (model.definition.as_ref().unwrap().line_num, model))
-        .collect()
+) -> Result<BTreeMap<usize, Vec<(String, Definition)>>> {
+    let mut output: BTreeMap<usize, Vec<(String, Definition)>> = BTreeMap::new();
+    let mut undefined = vec![];
+    for model in labels.into_values() {
+        match model.definition {
+            Some(definition) => output
+                .entry(definition.line_num)
+                .or_default()
+                .push((model.key, definition)),
+            None => undefined.push(model.key),
+        }
+    }
+    if !undefined.is_empty() {
+        undefined.sort();
+        return Err(Error::msg(format!(
+            "Labels have no definition: {}",
+            undefined.join(", ")
+        )));
+    }
+    //Labels sharing a line are defined in name order
+    for labels in output.values_mut() {
+        labels.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+    }
+    Ok(output)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::assembler::parser::{generate_program_model, parse_data};
     use crate::constants::code::{
-        ADD_REG_REG, CPY_REG_REG, INC_REG, LD_AREG_DATA_VAL_REG, PRTS_STR,
+        ADD_REG_REG, CALL_ADDR, CPY_AREG_ADDR, CPY_REG_REG, HALT, INC_REG, JMP_ADDR,
+        LD_AREG_DATA_VAL_REG, PRTS_STR,
     };
     use crate::constants::hardware::*;
     use crate::language::parser::params::Param;
+    use crate::tape_reader::decode_safe;
 
     #[test]
     #[rustfmt::skip]
@@ -389,6 +604,73 @@ mod test {
         assert_eq!(expected.get("b"), sources.get("b"));
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn test_gen_ascii_data_bytes() {
+        let mut program_model = ProgramModel::new(String::new(), String::new());
+        parse_data(&mut program_model, "a=[[1,2]]", 0).unwrap();
+        parse_data(&mut program_model, "b=\"hello\"", 1).unwrap();
+        parse_data(&mut program_model, "c=[[3]]", 2).unwrap();
+
+        let (bytes, sources) = generate_data_bytes(program_model.data, &mut DebugModel::default()).unwrap();
+
+        assert_eq!(bytes, vec![
+            1, 2, 1, 2,
+            104, 101, 108, 108, 111,
+            1, 1, 3
+        ]);
+        assert_eq!(sources.get("a"), Some(&0));
+        assert_eq!(sources.get("b"), Some(&4));
+        assert_eq!(sources.get("c"), Some(&9));
+    }
+
+    #[test]
+    fn test_gen_ascii_data_too_large() {
+        let mut program_model = ProgramModel::new(String::new(), String::new());
+        let line = format!("a=\"{}\"", "x".repeat(MAX_DATA_BYTES + 1));
+        parse_data(&mut program_model, &line, 0).unwrap();
+
+        assert!(generate_data_bytes(program_model.data, &mut DebugModel::default()).is_err());
+    }
+
+    #[test]
+    fn test_gen_strings_near_limit() {
+        //Each string is 256 bytes including the length byte
+        let make_model = |count: usize, last_len: usize| {
+            let mut model = ProgramModel::new(String::from("a"), String::from("b"));
+            for idx in 0..count {
+                let content = "x".repeat(if idx == count - 1 {
+                    last_len
+                } else {
+                    MAX_STRING_LEN
+                });
+                let key = format!("s{:03}", idx);
+                model.strings.insert(
+                    key.clone(),
+                    StringModel::new(key, content, String::new(), idx),
+                );
+            }
+            model
+        };
+
+        let (bytes, _) = generate_byte_code(make_model(256, 254)).unwrap();
+        assert_eq!(bytes.len() - 4 - 2, 11 + MAX_STRING_BYTES);
+
+        assert_eq!(
+            generate_byte_code(make_model(256, 255))
+                .unwrap_err()
+                .to_string(),
+            "Too many strings at `` on line 255, max 65535 bytes but is at least 65536 bytes"
+        );
+        assert_eq!(
+            section_len("Strings", MAX_STRING_BYTES + 1)
+                .unwrap_err()
+                .to_string(),
+            "Strings section is 65536 bytes, max 65535"
+        );
+        assert_eq!(section_len("Ops", 258).unwrap(), [1, 2]);
+    }
+
     mod generate_ops {
         use super::*;
         use crate::constants::code::LD_AREG_DATA_REG_VAL;
@@ -403,8 +685,8 @@ mod test {
             data_addresses.insert(String::from("bar"), 0_u16);
             let mut debug = DebugModel::new(
                 vec![],
-                vec![DebugString::new(0, String::from("foo"), String::new(), String::new(), 0)],
-                vec![DebugData::new(0, String::from("bar"), vec![], String::new(), 0)],
+                vec![DebugString::new(0, String::from("foo"), String::new(), String::new(), 0, 0)],
+                vec![DebugData::new(0, String::from("bar"), vec![], String::new(), 0, 0)],
                 vec![]
             );
 
@@ -438,12 +720,13 @@ mod test {
         let (bytes, _) = generate_byte_code(model).unwrap();
 
         assert_eq!(
-            bytes,
+            &bytes[..bytes.len() - 4],
             vec![
                 TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
                 9, 84, 101, 115, 116, 32, 80, 114, 111, 103,
                 3, 49, 46, 48,
                 0, 0,
+                0, 0,
                 0, 0
             ]
         )
@@ -461,11 +744,12 @@ mod test {
         let (bytes, _) = generate_byte_code(model).unwrap();
 
         assert_eq!(
-            bytes,
+            &bytes[..bytes.len() - 4],
             vec![
                 TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
                 1, 97,
                 1, 98,
+                0, 0,
                 0, 8,
                 INC_REG, REG_D0,
                 CPY_REG_REG, REG_D1, REG_D0,
@@ -489,11 +773,12 @@ mod test {
         let (bytes, _) = generate_byte_code(model).unwrap();
 
         assert_eq!(
-            bytes,
+            &bytes[..bytes.len() - 4],
             vec![
                 TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
                 1, 97,
                 1, 98,
+                0, 0,
                 0, 5,
                 INC_REG, REG_D0,
                 PRTS_STR, 0, 4,
@@ -519,11 +804,12 @@ mod test {
         let (bytes, _) = generate_byte_code(model).unwrap();
 
         assert_eq!(
-            bytes,
+            &bytes[..bytes.len() - 4],
             vec![
                 TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
                 1, 97,
                 1, 98,
+                0, 0,
                 0, 11,
                 ADD_REG_REG, REG_D0, REG_D1,
                 INC_REG, REG_ACC,
@@ -551,11 +837,12 @@ mod test {
         let (bytes, model) = generate_byte_code(model).unwrap();
 
         assert_eq!(
-            bytes,
+            &bytes[..bytes.len() - 4],
             vec![
                 TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
                 1, 97,
                 1, 98,
+                0, 0,
                 0, 14,
                 ADD_REG_REG, REG_D0, REG_D1,
                 INC_REG, REG_ACC,
@@ -567,11 +854,17 @@ mod test {
             ]
         );
 
-        let mut debug_str = DebugString::new(0, String::from("abc"), String::from("foo"), String::new(), 0);
-        let mut debug_data = DebugData::new(0, String::from("dk1"), vec![vec![10, 11], vec![50, 51], vec![97, 98, 99, 100]], String::new(), 0);
+        let mut debug_str = DebugString::new(0, String::from("abc"), String::from("foo"), String::new(), 0, 0);
+        let mut debug_data = DebugData::new(0, String::from("dk1"), vec![vec![10, 11], vec![50, 51], vec![97, 98, 99, 100]], String::new(), 0, 0);
         debug_str.usage.push(DebugUsage::new(11, 1, 3));
         debug_data.usage.push(DebugUsage::new(5, 2, 1));
 
+        assert_eq!(model.data_addr("dk1", 2, 0), Some(6));
+        assert_eq!(model.data_addr("dk1", 2, 1), Some(7));
+        assert_eq!(model.data_addr("dk1", 0, 3), Some(3));
+        assert_eq!(model.data_addr("dk1", 1, 0), Some(4));
+        assert_eq!(model.data_addr("dk1", 4, 0), None);
+        assert_eq!(model.data_addr("dk2", 0, 0), None);
         assert_eq!(
             model,
             DebugModel::new(
@@ -586,4 +879,405 @@ mod test {
                 vec![])
         );
     }
+
+    #[rustfmt::skip]
+    fn make_strings_and_data_model() -> ProgramModel {
+        let mut model = ProgramModel::new(String::from("a"), String::from("b"));
+
+        model.strings.insert(String::from("abc"), StringModel::new(String::from("abc"), String::from("foo"), String::new(), 0));
+        model.data.insert(String::from("dk1"), DataModel::new(String::new(), vec![1, 2, 10, 11], vec![vec![10, 11]], String::new(), 0));
+
+        model.ops.push(OpModel::new(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(REG_A0), Param::DataKey(String::from("dk1")), Param::Number(0), Param::DataReg(REG_D3)], String::new(), String::from("ld a0 dk1 0 d3"), 0));
+        model.ops.push(OpModel::new(PRTS_STR, vec![Param::StrKey(String::from("abc"))], String::new(), String::from("prts abc"), 1));
+        model
+    }
+
+    #[test]
+    fn test_relocations() {
+        assert_eq!(ops_relocations(make_strings_and_data_model()), vec![2, 7]);
+
+        let model = parse(&[
+            "test",
+            "1",
+            ".strings",
+            "abc=foo",
+            ".ops",
+            "start: inc d0",
+            "jmp start",
+            "prts abc",
+            "call start",
+        ]);
+        assert_eq!(ops_relocations(model), vec![3, 6, 9]);
+    }
+
+    #[test]
+    fn test_inline_data() {
+        let (_, debug) = assemble(&[
+            "test",
+            "1",
+            ".ops",
+            "jmp end",
+            "table: .db 1 2 'a'",
+            ".dw @1000 @x10",
+            "end: cpy a0 &table",
+            "halt",
+        ]);
+        let ops = debug
+            .ops
+            .iter()
+            .map(|op| (op.byte_addr, op.bytes.clone(), op.is_data))
+            .collect::<Vec<(u16, Vec<u8>, bool)>>();
+        assert_eq!(
+            ops,
+            vec![
+                (0, vec![JMP_ADDR, 0, 10], false),
+                (3, vec![1, 2, 97], true),
+                (6, vec![0x03, 0xE8, 0, 0x10], true),
+                (10, vec![CPY_AREG_ADDR, REG_A0, 0, 3], false),
+                (14, vec![HALT], false),
+            ]
+        );
+        assert_eq!(debug.labels_for_byte(3), vec!["table"]);
+        assert_eq!(debug.labels_for_byte(10), vec!["end"]);
+        let model = parse(&[
+            "test",
+            "1",
+            ".ops",
+            "jmp end",
+            "table: .db 1 2 'a'",
+            ".dw @1000 @x10",
+            "end: cpy a0 &table",
+            "halt",
+        ]);
+        assert_eq!(ops_relocations(model), vec![1, 12]);
+    }
+
+    #[test]
+    fn test_generate_to_writer() {
+        let (expected_bytes, expected_debug) =
+            generate_byte_code(make_strings_and_data_model()).unwrap();
+
+        let mut bytes: Vec<u8> = vec![];
+        let debug = generate_byte_code_to(make_strings_and_data_model(), &mut bytes).unwrap();
+
+        assert_eq!(bytes, expected_bytes);
+        assert_eq!(debug, expected_debug);
+    }
+
+    #[test]
+    fn test_relocation_table() {
+        let (bytes, _) = generate_byte_code(make_strings_and_data_model()).unwrap();
+        assert_eq!(bytes[2], PRG_VERSION);
+        assert_eq!(decode_safe(&bytes).unwrap().relocations, None);
+
+        let mut model = make_strings_and_data_model();
+        model.relocatable = true;
+        let (bytes, _) = generate_byte_code(model).unwrap();
+        assert_eq!(bytes[2], PRG_VERSION_RELOCATABLE);
+        let program = decode_safe(&bytes).unwrap();
+        assert_eq!(
+            program.relocations,
+            Some(vec![
+                Relocation::new(Section::Ops, Section::Data, 2),
+                Relocation::new(Section::Ops, Section::Strings, 7),
+            ])
+        );
+        assert_eq!(program.data, vec![1, 2, 10, 11]);
+
+        //Label addresses written into jump table data are listed too
+        let input = [
+            "test",
+            "1",
+            ".ops",
+            ".jumptable d1 first second",
+            "first: halt",
+            "second: halt",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        let mut model = generate_program_model(input, false, false).unwrap();
+        model.relocatable = true;
+        let (bytes, debug) = generate_byte_code(model).unwrap();
+        let program = decode_safe(&bytes).unwrap();
+        let relocations = program.relocations.unwrap();
+        let (data, ops): (Vec<Relocation>, Vec<Relocation>) = relocations
+            .into_iter()
+            .partition(|relocation| relocation.location == Section::Data);
+        assert_eq!(
+            data,
+            vec![
+                Relocation {
+                    location: Section::Data,
+                    target: Section::Ops,
+                    high: 3,
+                    low: 5
+                },
+                Relocation {
+                    location: Section::Data,
+                    target: Section::Ops,
+                    high: 4,
+                    low: 6
+                },
+            ]
+        );
+        assert_eq!(
+            ops.iter()
+                .map(|relocation| (relocation.high, relocation.target))
+                .collect::<Vec<(u16, Section)>>(),
+            vec![
+                (4, Section::Ops),
+                (7, Section::Ops),
+                (11, Section::Data),
+                (22, Section::Data)
+            ]
+        );
+        let first = debug
+            .labels
+            .iter()
+            .find(|label| label.name == "first")
+            .unwrap();
+        assert_eq!(
+            program.data[3..7],
+            [0, 0, first.byte as u8, first.byte as u8 + 1]
+        );
+    }
+
+    fn parse(lines: &[&str]) -> ProgramModel {
+        let input = lines.iter().map(|line| line.to_string()).collect();
+        let model = generate_program_model(input, false, false).unwrap();
+        model.validate().unwrap();
+        model
+    }
+
+    fn assemble(lines: &[&str]) -> (Vec<u8>, DebugModel) {
+        generate_byte_code(parse(lines)).unwrap()
+    }
+
+    ///Offsets of the addresses in the ops listed in the relocation table
+    fn ops_relocations(mut model: ProgramModel) -> Vec<u16> {
+        model.relocatable = true;
+        let (bytes, _) = generate_byte_code(model).unwrap();
+        decode_safe(&bytes)
+            .unwrap()
+            .relocations
+            .unwrap()
+            .iter()
+            .filter(|relocation| relocation.location == Section::Ops)
+            .map(|relocation| relocation.high)
+            .collect()
+    }
+
+    #[test]
+    fn test_labels_sharing_an_op() {
+        let (_, debug) = assemble(&[
+            "test",
+            "1",
+            ".ops",
+            "inc d0",
+            "first: second: inc d1",
+            "third:",
+            "fourth:",
+            "inc d2",
+            "jmp first",
+            "jmp second",
+            "jmp third",
+            "jmp fourth",
+        ]);
+
+        assert_eq!(debug.labels_for_byte(2), vec!["first", "second"]);
+        assert_eq!(debug.labels_for_byte(4), vec!["fourth", "third"]);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_three_labels_on_one_line() {
+        let (bytes, debug) = assemble(&[
+            "test",
+            "1",
+            ".ops",
+            "inc d0",
+            "gamma: alpha: beta: inc d1",
+            "jmp alpha",
+            "jmp beta",
+            "jmp gamma",
+        ]);
+
+        assert_eq!(
+            debug.labels.iter().map(|label| label.name.as_str()).collect::<Vec<&str>>(),
+            vec!["alpha", "beta", "gamma"]
+        );
+        assert_eq!(&bytes[18..27], &[JMP_ADDR, 0, 2, JMP_ADDR, 0, 2, JMP_ADDR, 0, 2]);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_trailing_label() {
+        let (bytes, debug) = assemble(&["test", "1", ".ops", "jmp end", "inc d0", "end:"]);
+
+        assert_eq!(debug.labels_for_byte(5), vec!["end"]);
+        assert_eq!(&bytes[14..19], &[JMP_ADDR, 0, 5, INC_REG, REG_D0]);
+    }
+
+    #[test]
+    fn test_empty_strings() {
+        let (bytes, debug) = assemble(&[
+            "test",
+            "1",
+            ".strings",
+            "blank=",
+            "spaces=   ",
+            ".ops",
+            "prts blank",
+            "prts spaces",
+        ]);
+
+        assert_eq!(debug.string_key_for_addr(0), Some("blank"));
+        assert_eq!(debug.string_key_for_addr(1), Some("spaces"));
+        //ops, then strings length and each string's length prefix
+        assert_eq!(&bytes[14..20], &[PRTS_STR, 0, 0, PRTS_STR, 0, 1]);
+        assert_eq!(&bytes[20..24], &[0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn test_definition_columns() {
+        let (_, debug) = assemble(&[
+            "test",
+            "1",
+            ".strings",
+            "    greeting = Hello",
+            ".data",
+            "  nums=[[1]]",
+            ".ops",
+            "prts greeting",
+            "ld a0 nums 0 0",
+        ]);
+
+        assert_eq!(debug.strings[0].column, 13);
+        assert_eq!(debug.data[0].column, 6);
+    }
+
+    #[test]
+    fn test_label_alias() {
+        let (bytes, debug) = assemble(&[
+            "test",
+            "1",
+            ".ops",
+            "inc d0",
+            "start: inc d1",
+            "alias again = start",
+            "jmp start",
+            "jmp again",
+        ]);
+
+        assert_eq!(debug.labels_for_byte(2), vec!["again", "start"]);
+        assert_eq!(&bytes[18..21], &[JMP_ADDR, 0, 2]);
+        assert_eq!(&bytes[21..24], &[JMP_ADDR, 0, 2]);
+    }
+
+    #[test]
+    fn test_alias_undefined_label() {
+        let input = ["test", "1", ".ops", "alias again = start", "start: inc d0"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert!(generate_program_model(input, false, false).is_err());
+    }
+
+    #[test]
+    fn test_header_length() {
+        let model = ProgramModel::new("a".repeat(255), "b".repeat(255));
+        let (bytes, _) = generate_byte_code(model).unwrap();
+        assert_eq!(bytes[3], 255);
+        assert_eq!(bytes[259], 255);
+
+        let mut bytes = vec![];
+        let model = ProgramModel::new("a".repeat(256), String::from("1"));
+        assert!(generate_byte_code_to(model, &mut bytes).is_err());
+        assert!(bytes.is_empty());
+
+        let model = ProgramModel::new(String::from("a"), "1".repeat(256));
+        assert!(generate_byte_code(model).is_err());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_undefined_symbols() {
+        let mut model = ProgramModel::new(String::from("a"), String::from("b"));
+        model.ops.push(OpModel::new(INC_REG, vec![Param::DataReg(REG_D0)], String::new(), String::from("inc d0"), 3));
+        model.ops.push(OpModel::new(CALL_ADDR, vec![Param::Label(String::from("missing"))], String::new(), String::from("call missing"), 4));
+        let err = generate_byte_code(model).unwrap_err().to_string();
+        assert!(err.contains("`missing`"), "{}", err);
+        assert!(err.contains("line 4"), "{}", err);
+
+        let mut model = ProgramModel::new(String::from("a"), String::from("b"));
+        model.ops.push(OpModel::new(PRTS_STR, vec![Param::StrKey(String::from("nostr"))], String::new(), String::from("prts nostr"), 5));
+        let err = generate_byte_code(model).unwrap_err().to_string();
+        assert!(err.contains("`nostr`"), "{}", err);
+        assert!(err.contains("line 5"), "{}", err);
+
+        let mut model = ProgramModel::new(String::from("a"), String::from("b"));
+        model.ops.push(OpModel::new(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(REG_A0), Param::DataKey(String::from("nodata")), Param::Number(0), Param::DataReg(REG_D3)], String::new(), String::from("ld a0 nodata 0 d3"), 6));
+        let err = generate_byte_code(model).unwrap_err().to_string();
+        assert!(err.contains("`nodata`"), "{}", err);
+        assert!(err.contains("line 6"), "{}", err);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_label_without_definition() {
+        let mut model = ProgramModel::new(String::from("a"), String::from("b"));
+        model.ops.push(OpModel::new(JMP_ADDR, vec![Param::Label(String::from("nowhere"))], String::new(), String::from("jmp nowhere"), 3));
+        model.labels.insert(String::from("nowhere"), LabelModel::new(String::from("nowhere"), None, vec![]));
+        let err = generate_byte_code(model).unwrap_err().to_string();
+        assert_eq!(err, "Labels have no definition: nowhere");
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_builder() {
+        let model = ProgramModel::builder("a", "b")
+            .string("abc", "foo")
+            .data("dk1", vec![vec![10, 11]])
+            .op(LD_AREG_DATA_VAL_REG, vec![Param::AddrReg(REG_A0), Param::DataKey(String::from("dk1")), Param::Number(0), Param::DataReg(REG_D3)])
+            .op(PRTS_STR, vec![Param::StrKey(String::from("abc"))])
+            .build();
+
+        let (expected, _) = generate_byte_code(make_strings_and_data_model()).unwrap();
+        let (bytes, _) = generate_byte_code(model).unwrap();
+
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_builder_labels() {
+        let model = ProgramModel::builder("a", "b")
+            .op(JMP_ADDR, vec![Param::Label(String::from("end"))])
+            .label("start")
+            .op(INC_REG, vec![Param::DataReg(REG_D0)])
+            .op(JMP_ADDR, vec![Param::Label(String::from("start"))])
+            .label("end")
+            .op(HALT, vec![])
+            .build();
+
+        model.validate().unwrap();
+        let (bytes, _) = generate_byte_code(model).unwrap();
+
+        assert_eq!(
+            &bytes[..bytes.len() - 4],
+            vec![
+                TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
+                1, 97,
+                1, 98,
+                0, 0,
+                0, 9,
+                JMP_ADDR, 0, 8,
+                INC_REG, REG_D0,
+                JMP_ADDR, 0, 3,
+                HALT,
+                0, 0
+            ]
+        );
+    }
 }
@@ -0,0 +1,255 @@
+use crate::assembler::program_model::{OpModel, ProgramModel};
+use crate::constants::code::*;
+use crate::constants::hardware::{reg_name, REG_ACC};
+use crate::constants::is_jump_op;
+use crate::language::parser::params::Param;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum WarningKind {
+    UnusedLabel,
+    UnusedString,
+    UnusedData,
+    DeadWrite,
+    Unreachable,
+    LongString,
+}
+
+///Strings longer than this are warned about by default, they can still be up to 255 bytes
+pub const DEFAULT_STRING_WARN_LEN: usize = 200;
+
+///Something that doesn't stop the program assembling but is probably a mistake
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+    ///Line the warning is about, for dead writes this is the overwriting line
+    pub line_num: usize,
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, message: String, line_num: usize) -> Self {
+        Warning {
+            kind,
+            message,
+            line_num,
+        }
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Finds data registers that are written to and then overwritten without being read in between
+/// This is a straight line analysis, any label, jump, call or op with unclear register use resets it
+pub fn dead_register_writes(program_model: &ProgramModel) -> Vec<Warning> {
+    let labelled = labelled_ops(program_model);
+    let mut warnings = vec![];
+    let mut pending: HashMap<u8, &OpModel> = HashMap::new();
+    for (idx, op) in program_model.ops.iter().enumerate() {
+        if labelled.contains(&idx) {
+            pending.clear();
+        }
+        match register_effects(op) {
+            None => pending.clear(),
+            Some((reads, writes)) => {
+                for reg in reads {
+                    pending.remove(&reg);
+                }
+                for reg in writes {
+                    if let Some(previous) = pending.insert(reg, op) {
+                        warnings.push(Warning::new(
+                            WarningKind::DeadWrite,
+                            format!(
+                                "{} written on line {} but never read before being overwritten on line {}",
+                                reg_name(reg),
+                                previous.line_num,
+                                op.line_num
+                            ),
+                            op.line_num,
+                        ));
+                    }
+                }
+            }
+        }
+        if is_jump_op(op.opcode) {
+            pending.clear();
+        }
+    }
+    warnings
+}
+
+/// Finds ops directly after a HALT that have no label, so can't be jumped to
+/// Only the first op of each unreachable run is reported
+pub fn unreachable_ops(program_model: &ProgramModel) -> Vec<Warning> {
+    let labelled = labelled_ops(program_model);
+    program_model
+        .ops
+        .windows(2)
+        .enumerate()
+        .filter(|(idx, pair)| {
+            pair[0].opcode == HALT && !pair[1].is_inline_data() && !labelled.contains(&(idx + 1))
+        })
+        .map(|(_, pair)| {
+            Warning::new(
+                WarningKind::Unreachable,
+                format!(
+                    "Unreachable op on line {} after HALT on line {}: {}",
+                    pair[1].line_num,
+                    pair[0].line_num,
+                    pair[1].original_line.trim()
+                ),
+                pair[1].line_num,
+            )
+        })
+        .collect()
+}
+
+/// Finds strings longer than `max_len` bytes, these are often a runaway line or accidental concatenation
+pub fn long_strings(program_model: &ProgramModel, max_len: usize) -> Vec<Warning> {
+    let mut warnings = program_model
+        .strings
+        .values()
+        .filter(|model| model.content.len() > max_len)
+        .map(|model| {
+            Warning::new(
+                WarningKind::LongString,
+                format!(
+                    "String {} is {} bytes, more than the warning limit of {}",
+                    model.key,
+                    model.content.len(),
+                    max_len
+                ),
+                model.definition.line_num,
+            )
+        })
+        .collect::<Vec<Warning>>();
+    warnings.sort_by_key(|warning| warning.line_num);
+    warnings
+}
+
+/// Indexes of ops that have at least one label pointing at them
+fn labelled_ops(program_model: &ProgramModel) -> HashSet<usize> {
+    program_model
+        .labels
+        .values()
+        .filter_map(|label| label.definition.as_ref())
+        .filter_map(|def| {
+            program_model
+                .ops
+                .iter()
+                .position(|op| op.line_num >= def.line_num)
+        })
+        .collect()
+}
+
+/// Returns the data registers read and written by `op`, or None if it's unknown
+fn register_effects(op: &OpModel) -> Option<(Vec<u8>, Vec<u8>)> {
+    let regs = op
+        .params
+        .iter()
+        .map(|param| match param {
+            Param::DataReg(reg) => Some(*reg),
+            _ => None,
+        })
+        .collect::<Vec<Option<u8>>>();
+    let all_regs = regs.iter().flatten().copied().collect::<Vec<u8>>();
+    let first = regs.first().copied().flatten();
+    let second = regs.get(1).copied().flatten();
+
+    let effects = match op.opcode {
+        NOP | PRTLN | PRTS_STR | PRTLN_STR | PRT_VAL | PRTC_VAL | PUSH_VAL | HALT | DUP | SWAP
+        | RDSP_AREG | ENTER_VAL | LEAVE => (vec![], vec![]),
+        ADD_REG_REG | ADD_REG_VAL | ADD_REG_AREG | SUB_REG_REG | SUB_REG_VAL | SUB_REG_AREG
+        | AND_REG_REG | AND_REG_VAL | AND_REG_AREG | OR_REG_REG | OR_REG_VAL | OR_REG_AREG
+        | XOR_REG_REG | XOR_REG_VAL | XOR_REG_AREG | NOT_REG | ROL_REG_VAL | ROR_REG_VAL
+        | NEG_REG | ABS_REG | MIN_REG_REG | MAX_REG_REG | SMIN_REG_REG | SMAX_REG_REG
+        | CMP_REG_REG | CMP_REG_VAL | CMP_REG_AREG | CMP_AREG_AREG | CMP_AREG_ADDR
+        | CMP_AREG_REG_REG | CMP_REG_REG_AREG => (all_regs, vec![REG_ACC]),
+        CPY_REG_VAL | CPY_REG_AREG | POP_REG | RAND_REG | INPUT_REG | RDTICK_REG | ARG_REG_VAL
+        | LDF_REG_VAL | CLR_REG => (vec![], first.into_iter().collect()),
+        CPY_REG_REG | ARG_REG_REG => (second.into_iter().collect(), first.into_iter().collect()),
+        CPY_REG_REG_AREG | LDA_REG_ADDR | LDSTR_AREG_REG_STR => (vec![], all_regs),
+        CPY_AREG_REG_REG | CPY_AREG_ADDR | CPY_AREG_AREG | PRT_REG | PRTC_REG | PUSH_REG
+        | SEED_REG | STF_REG_VAL | ASSERT_REG_VAL | STA_ADDR_REG | LD_AREG_DATA_REG_REG
+        | LD_AREG_DATA_REG_VAL | LD_AREG_DATA_VAL_REG | LD_AREG_DATA_VAL_VAL => (all_regs, vec![]),
+        INC_REG | DEC_REG | SWP_REG_REG | SWP_AREG_AREG => (all_regs.clone(), all_regs),
+        MEMR_ADDR | MEMR_AREG => (vec![], vec![REG_ACC]),
+        MEMW_ADDR | MEMW_AREG => (vec![REG_ACC], vec![]),
+        JE_ADDR | JE_AREG | JNE_ADDR | JNE_AREG | JL_ADDR | JL_AREG | JG_ADDR | JG_AREG => {
+            (vec![REG_ACC], vec![])
+        }
+        _ => return None,
+    };
+    Some(effects)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::parser::generate_program_model;
+
+    fn lint(src: &str) -> Vec<String> {
+        let input = src.lines().map(|line| line.to_owned()).collect();
+        dead_register_writes(&generate_program_model(input, false, false).unwrap())
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_dead_write() {
+        let warnings = lint("Test\n1\n.ops\ncpy d2 1\ncpy d3 2\ncpy d2 3\nprt d3\nprt d2");
+
+        assert_eq!(
+            warnings,
+            vec![String::from(
+                "D2 written on line 4 but never read before being overwritten on line 6"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let input = "Test\n1\n.ops\ncpy d0 1\nhalt\nprt d0\nprtln\nend: prt d0\nhalt"
+            .lines()
+            .map(|line| line.to_owned())
+            .collect();
+        let warnings = unreachable_ops(&generate_program_model(input, false, false).unwrap());
+        assert_eq!(
+            warnings,
+            vec![Warning::new(
+                WarningKind::Unreachable,
+                String::from("Unreachable op on line 6 after HALT on line 5: prt d0"),
+                6
+            )]
+        );
+
+        let input = "Test\n1\n.ops\njmp end\nhalt\nend: halt\nhalt #twice"
+            .lines()
+            .map(|line| line.to_owned())
+            .collect();
+        let warnings = unreachable_ops(&generate_program_model(input, false, false).unwrap());
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].line_num, 7);
+    }
+
+    #[test]
+    fn test_resets() {
+        assert!(lint("Test\n1\n.ops\ncpy d2 1\nloop: cpy d2 3\nprt d2").is_empty());
+        assert!(
+            lint("Test\n1\n.ops\ncpy d2 1\ncall routine\ncpy d2 3\nprt d2\nroutine: ret")
+                .is_empty()
+        );
+        assert!(lint("Test\n1\n.ops\ncpy d2 1\nfopen 0\ncpy d2 3\nprt d2").is_empty());
+        assert!(lint("Test\n1\n.ops\ncpy d2 1\ninc d2\nprt d2\ncpy d2 3\nprt d2").is_empty());
+        assert_eq!(
+            lint("Test\n1\n.ops\nadd d0 d1\ncmp d0 d1\nje end\nend: halt").len(),
+            1
+        );
+    }
+}
@@ -1,27 +1,45 @@
 pub mod debug_model;
+pub mod debug_pack;
 mod generator;
+pub mod lint;
+pub mod optimiser;
 pub mod parser;
 pub mod program_model;
 
+use crate::assembler::debug_model::DebugModel;
 use crate::assembler::generator::generate_byte_code;
-use crate::assembler::parser::generate_program_model;
-use crate::common::{read_lines, reset_cursor};
+use crate::assembler::lint::{
+    dead_register_writes, long_strings, unreachable_ops, Warning, DEFAULT_STRING_WARN_LEN,
+};
+use crate::assembler::optimiser::optimise_tail_calls;
+use crate::assembler::parser::{
+    generate_program_model_recovering, generate_program_model_with_options,
+};
+use crate::assembler::program_model::ProgramModel;
+use crate::common::{read_lines, reset_cursor, split_lines};
 use crate::constants::code::{DIVDERS, KEYWORDS, MNEMONICS, REGISTERS};
-use anyhow::{Error, Result};
+use anyhow::{Context, Error, Result};
 use lazy_static::lazy_static;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
 
-pub fn start(basm: &str, build_debug: bool, debug: bool) -> Result<()> {
+pub fn start(
+    basm: &str,
+    build_debug: bool,
+    debug: bool,
+    listing: bool,
+    options: &AssembleOptions,
+) -> Result<()> {
     let path = PathBuf::from(basm);
 
-    let (output_file_name, build_file_name, debug_file_name) =
+    let (output_file_name, build_file_name, debug_file_name, listing_file_name) =
         if let Some(output_file_stem) = path.file_stem() {
             (
                 format!("{}.tape", output_file_stem.to_string_lossy()),
                 format!("{}.build.json", output_file_stem.to_string_lossy()),
                 format!("{}.debug", output_file_stem.to_string_lossy()),
+                format!("{}.lst", output_file_stem.to_string_lossy()),
             )
         } else {
             eprintln!("Error parsing file name");
@@ -29,6 +47,7 @@ pub fn start(basm: &str, build_debug: bool, debug: bool) -> Result<()> {
                 String::from("output.tape"),
                 String::from("output.build.json"),
                 String::from("output.debug"),
+                String::from("output.lst"),
             )
         };
     let mut output_file_path = PathBuf::from(path.parent().unwrap());
@@ -44,7 +63,18 @@ pub fn start(basm: &str, build_debug: bool, debug: bool) -> Result<()> {
         false => None,
     };
 
-    let bytes = assemble(read_lines(basm)?, build_file, debug_file)?;
+    let listing_file = match listing {
+        true => Some(listing_file_name),
+        false => None,
+    };
+
+    let bytes = assemble_files(
+        read_lines(basm)?,
+        build_file,
+        debug_file,
+        listing_file,
+        options,
+    )?;
 
     let path = output_file_path.to_string_lossy().to_string();
     match File::create(output_file_path) {
@@ -64,24 +94,232 @@ pub fn start(basm: &str, build_debug: bool, debug: bool) -> Result<()> {
     Ok(())
 }
 
-fn assemble(
+/// Settings for `assemble`, the default is the same as `assemble_str` with `keep_whitespace` false
+#[derive(Debug, Clone)]
+pub struct AssembleOptions {
+    /// If true string content is not trimmed
+    pub keep_whitespace: bool,
+    /// If true any operand that could be an address or a label must be an `@address` or a label defined in the program
+    pub strict: bool,
+    /// If true CALLs directly followed by RET are replaced with JMP
+    pub optimise: bool,
+    /// `.ifdef` blocks for these names are included
+    pub defines: Vec<String>,
+    /// Strings longer than this many bytes are warned about
+    pub string_warn_len: usize,
+    /// If true the tape includes a relocation table so it can be loaded as an overlay
+    pub relocatable: bool,
+}
+
+impl Default for AssembleOptions {
+    fn default() -> Self {
+        AssembleOptions {
+            keep_whitespace: false,
+            strict: false,
+            optimise: false,
+            defines: vec![],
+            string_warn_len: DEFAULT_STRING_WARN_LEN,
+            relocatable: false,
+        }
+    }
+}
+
+pub struct AssembleResult {
+    pub bytes: Vec<u8>,
+    pub debug: DebugModel,
+    ///Unused symbols, dead writes, unreachable ops and long strings
+    pub warnings: Vec<Warning>,
+    ///Number of tail calls replaced, always 0 unless `optimise` is set
+    pub tail_calls: usize,
+}
+
+/// Assembles `src` without touching the filesystem
+pub fn assemble(src: &str, options: &AssembleOptions) -> Result<AssembleResult> {
+    let program_model = generate_program_model_with_options(split_lines(src), options)?;
+    assemble_model(program_model, options)
+}
+
+/// Assembles `src` without touching the filesystem
+/// * `keep_whitespace`: If true string content is not trimmed
+pub fn assemble_str(src: &str, keep_whitespace: bool) -> Result<(Vec<u8>, DebugModel)> {
+    let result = assemble_with_warnings(src, keep_whitespace)?;
+    Ok((result.bytes, result.debug))
+}
+
+/// Same as `assemble_str` but also returns any warnings
+pub fn assemble_with_warnings(src: &str, keep_whitespace: bool) -> Result<AssembleResult> {
+    let options = AssembleOptions {
+        keep_whitespace,
+        ..AssembleOptions::default()
+    };
+    assemble(src, &options)
+}
+
+fn assemble_model(
+    mut program_model: ProgramModel,
+    options: &AssembleOptions,
+) -> Result<AssembleResult> {
+    program_model.validate()?;
+    let warnings = collect_warnings(&program_model, options.string_warn_len);
+    let tail_calls = match options.optimise {
+        true => optimise_tail_calls(&mut program_model),
+        false => 0,
+    };
+    program_model.relocatable = options.relocatable;
+    let (bytes, debug) = generate_byte_code(program_model)?;
+    Ok(AssembleResult {
+        bytes,
+        debug,
+        warnings,
+        tail_calls,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Severity {
+    ///The program won't assemble
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    ///None if the problem isn't on one line, e.g. the ops are too long
+    pub line_num: Option<usize>,
+}
+
+impl Diagnostic {
+    fn error(message: String, line_num: Option<usize>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            line_num,
+        }
+    }
+}
+
+impl From<Warning> for Diagnostic {
+    fn from(warning: Warning) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            message: warning.message,
+            line_num: Some(warning.line_num),
+        }
+    }
+}
+
+/// Checks `src` the same as `assemble` with `options` but returns every problem found instead of stopping at the first
+/// A line with an error is skipped so using a string, data or constant defined on it may cause more errors
+/// Diagnostics are sorted by line, ones without a line are first
+pub fn validate(src: &str, options: &AssembleOptions) -> Vec<Diagnostic> {
+    let (mut program_model, line_errors) =
+        match generate_program_model_recovering(split_lines(src), options) {
+            Ok(result) => result,
+            Err(err) => return vec![Diagnostic::error(format!("{:#}", err), None)],
+        };
+    let mut diagnostics = line_errors
+        .into_iter()
+        .map(|(line_num, err)| Diagnostic::error(format!("{:#}", err), Some(line_num)))
+        .collect::<Vec<Diagnostic>>();
+    diagnostics.extend(program_model.diagnostics());
+    diagnostics.extend(
+        collect_warnings(&program_model, options.string_warn_len)
+            .into_iter()
+            .map(Diagnostic::from),
+    );
+    //Section sizes are only checked while generating
+    if diagnostics
+        .iter()
+        .all(|diagnostic| diagnostic.severity != Severity::Error)
+    {
+        program_model.relocatable = options.relocatable;
+        if let Err(err) = generate_byte_code(program_model) {
+            diagnostics.push(Diagnostic::error(format!("{:#}", err), None));
+        }
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line_num);
+    diagnostics
+}
+
+fn collect_warnings(program_model: &ProgramModel, string_warn_len: usize) -> Vec<Warning> {
+    let mut warnings = program_model.unused_symbols();
+    warnings.extend(dead_register_writes(program_model));
+    warnings.extend(unreachable_ops(program_model));
+    warnings.extend(long_strings(program_model, string_warn_len));
+    warnings
+}
+
+/// Assembles each source independently, an error in one doesn't stop the others
+/// Results are in the same order as `sources` and errors include the path
+pub fn assemble_many(sources: &[(PathBuf, String)]) -> Vec<Result<(Vec<u8>, DebugModel)>> {
+    sources
+        .iter()
+        .map(|(path, src)| {
+            assemble_str(src, false).context(format!("assembling {}", path.display()))
+        })
+        .collect()
+}
+
+fn assemble_files(
     input: Vec<String>,
     build_file: Option<String>,
     debug_file: Option<String>,
+    listing_file: Option<String>,
+    options: &AssembleOptions,
 ) -> Result<Vec<u8>> {
-    let program_model = generate_program_model(input)?;
+    let program_model = generate_program_model_with_options(input, options)?;
     if let Some(path) = build_file {
         println!("Writing intermediate/interpretation stage to {}", path);
         std::fs::write(path, serde_json::to_string(&program_model)?)?;
     }
-    program_model.validate()?;
-    let (bytes, debug) = generate_byte_code(program_model)?;
+    let result = assemble_model(program_model, options)?;
+    for warning in &result.warnings {
+        println!("{}", warning);
+    }
+    if options.optimise {
+        println!("Replaced {} tail calls", result.tail_calls);
+    }
     if let Some(path) = debug_file {
         println!("Writing debug data to {}", path);
-        std::fs::write(path, serde_json::to_string(&debug)?)?;
+        std::fs::write(path, serde_json::to_string(&result.debug)?)?;
+    }
+    if let Some(path) = listing_file {
+        println!("Writing listing to {}", path);
+        std::fs::write(path, generate_listing(&result.debug))?;
     }
 
-    Ok(bytes)
+    Ok(result.bytes)
+}
+
+///Each op as `<addr> <bytes> <source line>`, with addresses and bytes in hex
+pub fn generate_listing(model: &DebugModel) -> String {
+    let width = model
+        .ops
+        .iter()
+        .map(|op| (op.bytes.len() * 3).saturating_sub(1))
+        .max()
+        .unwrap_or(0);
+    model
+        .ops
+        .iter()
+        .map(|op| {
+            let bytes = op
+                .bytes
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<Vec<String>>()
+                .join(" ");
+            format!(
+                "{:04X}  {:<width$} {}\n",
+                op.byte_addr,
+                bytes,
+                op.original_line.trim(),
+                width = width
+            )
+        })
+        .collect()
 }
 
 lazy_static! {
@@ -102,6 +340,8 @@ const FORMAT_ERROR: &str = r#"Invalid BASM file, expected format:
 
 Program name must be between 1 and 20 ASCII characters and be the first line
 Program version must between 1 and 10 ASCII characters and be the second line
+Alternatively the name and version can be set with `.name "<name>"` and `.version "<version>"`
+at the start of the file, if either is missing "Untitled" and "1" are used
 
 Blank lines are ok from the third line onwards
 Case matters for section dividers (.strings, .data and .ops)
@@ -118,12 +358,18 @@ See language document for ops
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::assembler::lint::WarningKind;
+    use crate::assembler::parser::generate_program_model;
     use crate::constants::code::{
-        ADD_REG_REG, ARG_REG_VAL, CALL_ADDR, CMP_REG_REG, CPY_REG_AREG, CPY_REG_VAL, HALT, JE_ADDR,
-        LD_AREG_DATA_VAL_VAL, PRTC_VAL, PRTLN, PRTS_STR, PRT_REG, PUSH_REG, RET,
+        ADD_REG_REG, ARG_REG_VAL, CALL_ADDR, CMP_REG_REG, CPY_REG_AREG, CPY_REG_VAL, HALT, INC_REG,
+        JE_ADDR, JMP_ADDR, LD_AREG_DATA_VAL_VAL, PRTC_VAL, PRTLN, PRTS_STR, PRT_REG, PUSH_REG, RET,
     };
     use crate::constants::hardware::{REG_A0, REG_ACC, REG_D0, REG_D1, REG_D2};
     use crate::constants::system::*;
+    use crate::device::comm::Output;
+    use crate::device::internals::{Device, RunResult};
+    use crate::tape_reader::Program;
+    use std::convert::TryFrom;
 
     #[test]
     #[rustfmt::skip]
@@ -136,13 +382,14 @@ mod test {
             "CPY D2 xF",
             "ADD D0 D2",
         ].iter().map(|str| str.to_string()).collect();
-        let bytes = assemble(program, None, None).unwrap();
+        let bytes = assemble_files(program, None, None, None, &AssembleOptions::default()).unwrap();
         
-        assert_eq!(bytes,
+        assert_eq!(&bytes[..bytes.len() - 4],
            vec![
             TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
             9, 84, 101, 115, 116, 32, 80, 114, 111, 103,
             3, 49, 46, 48,
+            0, 0,
             0, 9,
             CPY_REG_VAL, REG_D0, 10,
             CPY_REG_VAL, REG_D2, 15,
@@ -151,20 +398,447 @@ mod test {
         ]);
     }
 
+    #[test]
+    fn test_line_endings_and_bom() {
+        let program = "Test Prog\n1.0\n.strings\nmsg=hi\n.ops\nCPY D0 10\nprts msg\n";
+        let (expected, _) = assemble_str(program, false).unwrap();
+
+        let crlf = program.replace('\n', "\r\n");
+        let cr = program.replace('\n', "\r");
+        let bom = format!("\u{feff}{}", program);
+        let bom_crlf = format!("\u{feff}{}", crlf);
+        for src in [crlf, cr, bom, bom_crlf] {
+            assert_eq!(assemble_str(&src, false).unwrap().0, expected, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_listing() {
+        let (_, debug) =
+            assemble_str("test\n1\n.ops\ncpy d0 10\nloop: inc d0\njmp loop", false).unwrap();
+
+        assert_eq!(
+            generate_listing(&debug),
+            format!(
+                "0000  {:02X} {:02X} 0A cpy d0 10\n0003  {:02X} {:02X}    loop: inc d0\n0005  {:02X} 00 03 jmp loop\n",
+                CPY_REG_VAL, REG_D0, INC_REG, REG_D0, JMP_ADDR
+            )
+        );
+    }
+
+    #[test]
+    fn test_section_order() {
+        let canonical = assemble_str(
+            "test\n1\n.strings\ngreeting=hi\n.data\nnums=[[1,2]]\n.ops\nprts greeting\nld a0 nums 0 1",
+            false,
+        )
+        .unwrap();
+        let data_first = assemble_str(
+            "test\n1\n.data\nnums=[[1,2]]\n.strings\ngreeting=hi\n.ops\nprts greeting\nld a0 nums 0 1",
+            false,
+        )
+        .unwrap();
+        let ops_first = assemble_str(
+            "test\n1\n.ops\nprts greeting\nld a0 nums 0 1\n.data\nnums=[[1,2]]\n.strings\ngreeting=hi",
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(data_first.0, canonical.0);
+        assert_eq!(ops_first.0, canonical.0);
+        assert!(assemble_str("test\n1\n.ops\nhalt\n.ops\nnop", false).is_err());
+        assert!(assemble_str("test\n1\n.code\nhalt", false).is_err());
+    }
+
+    #[test]
+    fn test_jump_table() {
+        let run = |reg: &str, idx: u8| {
+            let src = format!("test\n1\n.ops\n.jumptable {} zero one two #dispatch\nprtc '?'\nhalt\nzero: prtc '0'\nhalt\none: prtc '1'\nhalt\ntwo: prtc '2'\nhalt", reg);
+            let (bytes, _) = assemble_str(&src, false).unwrap();
+            let program = Program::try_from(bytes.as_slice()).unwrap();
+            let mut device = Device::new(program.ops, vec![], program.data, vec![]);
+            device.data_reg = [10, 11, 12, 13];
+            match reg {
+                "acc" => device.acc = idx,
+                _ => device.data_reg[1] = idx,
+            }
+            while device.step(true) == RunResult::Pause {}
+            let output = device
+                .output
+                .iter()
+                .filter_map(|output| match output {
+                    Output::OutputStd(text) => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<String>();
+            (output, device)
+        };
+
+        for (idx, expected) in [(0, "0"), (1, "1"), (2, "2"), (3, "?"), (255, "?")] {
+            for reg in ["d1", "acc"] {
+                let (output, device) = run(reg, idx);
+                assert_eq!(output, expected, "{} {}", reg, idx);
+                let index = if reg == "acc" { 11 } else { idx };
+                assert_eq!(device.data_reg, [10, index, 12, 13], "{} {}", reg, idx);
+                assert_eq!(device.dump().sp, 65535, "{} {}", reg, idx);
+            }
+        }
+
+        assert!(assemble_str("test\n1\n.ops\n.jumptable d0\nhalt", false).is_err());
+        assert!(assemble_str("test\n1\n.ops\n.jumptable d0 missing\nhalt", false).is_err());
+        assert!(assemble_str(
+            "test\n1\n.data\njumptable_6=[[1]]\n.ops\n.jumptable d0 a\na: halt",
+            false
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_entry_point() {
+        let entry = |src: &str| {
+            let (bytes, _) = assemble_str(src, false).unwrap();
+            Program::try_from(bytes.as_slice()).unwrap().entry
+        };
+        assert_eq!(entry("test\n1\n.ops\ninc d0\nhalt"), 0);
+        assert_eq!(entry("test\n1\n.ops\n.entry go\ninc d0\ngo: halt"), 2);
+        assert_eq!(entry("test\n1\n.ops\ninc d0\nmain: halt"), 2);
+        assert_eq!(
+            entry("test\n1\n.ops\n.entry go # start\nmain: inc d0\ngo: halt"),
+            2
+        );
+        //`start` is often a loop label so only `main` is used by default
+        assert_eq!(entry("test\n1\n.ops\ninc d0\nstart: halt"), 0);
+
+        assert!(assemble_str("test\n1\n.ops\n.entry missing\nhalt", false).is_err());
+        assert!(assemble_str("test\n1\n.ops\n.entry a b\na: halt", false).is_err());
+        assert!(assemble_str("test\n1\n.ops\n.entry a\n.entry a\na: halt", false).is_err());
+    }
+
+    #[test]
+    fn test_address_of_label() {
+        let src = "test\n1\n.ops\nnop\nloop: inc d0\ncpy a0 &loop\ncpy a1 loop\nhalt";
+        let (bytes, model) = assemble_str(src, false).unwrap();
+        let program = Program::try_from(bytes.as_slice()).unwrap();
+        let loop_addr = model
+            .labels
+            .iter()
+            .find(|lbl| lbl.name == "loop")
+            .unwrap()
+            .byte;
+        assert_eq!(loop_addr, 1);
+
+        let mut device = Device::new(program.ops, vec![], vec![], vec![]);
+        while device.step(true) == RunResult::Pause {}
+        assert_eq!(device.addr_reg[0], loop_addr);
+        assert_eq!(device.addr_reg[1], loop_addr);
+
+        let err = assemble_str("test\n1\n.ops\ncpy a0 &missing\nhalt", false).unwrap_err();
+        assert!(err.to_string().contains("missing"), "{}", err);
+    }
+
+    #[test]
+    fn test_warnings() {
+        let result = assemble_with_warnings(
+            "test\n1\n.strings\nused=a\nunused=b\n.ops\nprts used",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            result.warnings,
+            vec![Warning::new(
+                WarningKind::UnusedString,
+                String::from("String unused is never used"),
+                5
+            )]
+        );
+        assert_eq!(
+            result.bytes,
+            assemble_str(
+                "test\n1\n.strings\nused=a\nunused=b\n.ops\nprts used",
+                false
+            )
+            .unwrap()
+            .0
+        );
+
+        let result = assemble_with_warnings(
+            "test\n1\n.data\nnums=[[1]]\n.ops\nstart: cpy d0 1\ncpy d0 2\nprt d0",
+            false,
+        )
+        .unwrap();
+        let warnings = result
+            .warnings
+            .iter()
+            .map(|warning| (warning.kind, warning.line_num))
+            .collect::<Vec<(WarningKind, usize)>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (WarningKind::UnusedData, 4),
+                (WarningKind::UnusedLabel, 6),
+                (WarningKind::DeadWrite, 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_string_warning() {
+        let src = format!(
+            "test\n1\n.strings\nshort=hi\nlong={}\n.ops\nprts short\nprts long",
+            "a".repeat(210)
+        );
+        let result = assemble_with_warnings(&src, false).unwrap();
+        assert_eq!(
+            result.warnings,
+            vec![Warning::new(
+                WarningKind::LongString,
+                String::from("String long is 210 bytes, more than the warning limit of 200"),
+                5
+            )]
+        );
+        assert_eq!(result.bytes, assemble_str(&src, false).unwrap().0);
+
+        let options = |string_warn_len| AssembleOptions {
+            string_warn_len,
+            ..AssembleOptions::default()
+        };
+        let result = assemble(&src, &options(255)).unwrap();
+        assert!(result.warnings.is_empty());
+        let result = assemble(&src, &options(1)).unwrap();
+        assert_eq!(result.warnings.len(), 2);
+
+        let src = src.replace(&"a".repeat(210), &"a".repeat(256));
+        assert!(assemble_with_warnings(&src, false).is_err());
+    }
+
+    #[test]
+    fn test_assemble_options_combine() {
+        let src = "test\n1\n.strings\n.ifdef DEBUG\nmsg=debug build\n.else\nmsg=hi\n.endif\n.ops\nprts msg\nhalt";
+        let options = AssembleOptions {
+            defines: vec![String::from("DEBUG")],
+            string_warn_len: 5,
+            ..AssembleOptions::default()
+        };
+        let result = assemble(src, &options).unwrap();
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|warning| (warning.kind, warning.line_num))
+                .collect::<Vec<(WarningKind, usize)>>(),
+            vec![(WarningKind::LongString, 5)]
+        );
+        assert!(assemble(src, &AssembleOptions::default())
+            .unwrap()
+            .warnings
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unreachable_warning() {
+        let result =
+            assemble_with_warnings("test\n1\n.ops\nprtc 'a'\nhalt\nprtc 'b'\nhalt", false).unwrap();
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].kind, WarningKind::Unreachable);
+        assert_eq!(result.warnings[0].line_num, 6);
+    }
+
+    #[test]
+    fn test_validate() {
+        let options = AssembleOptions::default();
+        let src = format!(
+            "test\n1\n.strings\ngreeting=Hello\nlong={}\n.ops\nprts greeting\njmp nowhere\nhalt",
+            "a".repeat(300)
+        );
+        let diagnostics = validate(&src, &options);
+        assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].line_num, Some(5));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert!(diagnostics[0]
+            .message
+            .ends_with("on line 5 is too long, max 255 chars"));
+        assert_eq!(
+            diagnostics[1],
+            Diagnostic::error(String::from("Label nowhere is never defined"), Some(8))
+        );
+
+        let diagnostics = validate("test\n1\n.ops\nprts a\nprts b\nld a0 c 0 0\nhalt", &options);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.line_num)
+                .collect::<Vec<Option<usize>>>(),
+            vec![Some(4), Some(5), Some(6)]
+        );
+
+        let diagnostics = validate("test\n1\n.ops\nprtc 'a'\nhalt\nprtc 'b'\nhalt", &options);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].line_num, Some(6));
+
+        assert_eq!(validate("test\n1\n.ops\nprtc 'a'\nhalt", &options), vec![]);
+        assert_eq!(
+            validate("test\n1", &options),
+            vec![Diagnostic::error(String::from(FORMAT_ERROR), None)]
+        );
+
+        //Options are applied the same as when assembling
+        let src = "test\n1\n.strings\ngreeting=Hello\n.ops\nprts greeting\njmp nowhere\nhalt";
+        assert_eq!(validate(src, &options).len(), 1);
+        let options = AssembleOptions {
+            strict: true,
+            string_warn_len: 3,
+            ..AssembleOptions::default()
+        };
+        let diagnostics = validate(src, &options);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| (diagnostic.severity, diagnostic.line_num))
+                .collect::<Vec<(Severity, Option<usize>)>>(),
+            vec![
+                (Severity::Warning, Some(4)),
+                (Severity::Error, Some(7)),
+                (Severity::Error, Some(7))
+            ],
+            "{:?}",
+            diagnostics
+        );
+        assert!(diagnostics[1]
+            .message
+            .starts_with("`nowhere` on line 7 is not an address or a defined label"));
+    }
+
+    #[test]
+    fn test_strict_assembly() {
+        let strict = AssembleOptions {
+            strict: true,
+            ..AssembleOptions::default()
+        };
+
+        //A bare token that's a label defined later assembles the same in both modes
+        let src = "test\n1\n.ops\njmp end\ncall end\nend: halt";
+        let lenient = assemble(src, &AssembleOptions::default()).unwrap();
+        assert_eq!(assemble(src, &strict).unwrap().bytes, lenient.bytes);
+
+        //Otherwise both fail, strict mode errors while parsing and points out it may be an address
+        let src = "test\n1\n.ops\njmp 100\nhalt";
+        let error = assemble(src, &AssembleOptions::default()).err().unwrap();
+        assert_eq!(error.root_cause().to_string(), "Label 100 is never defined");
+        let error = assemble(src, &strict).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "`100` on line 4 is not an address or a defined label (addresses must start with @): jmp 100"
+        );
+    }
+
+    #[test]
+    fn test_deterministic_output() {
+        let src = "Program\n1\n.strings\nzeta=Last\nalpha=First\nmid=Middle\nunused=Never\n.data\nztable=[[1,2],[3]]\natable=[[4]]\nbuf=[0; 4]\n.ops\nconst start 4\nb: a: c: cpy d0 start\nprts alpha\nprts zeta\nprtln mid\nld a0 ztable 1 0\nld a1 atable 0 0\ncpy d1 sizeof(ztable)\nloop: inc d0\ncmp d0 10\njne loop\ncall func\njmp end\nfunc: ret\nz: y: end: halt";
+        let (bytes, debug) = assemble_str(src, false).unwrap();
+        let first_model = generate_program_model(split_lines(src), false, false).unwrap();
+        for _ in 0..10 {
+            let (other_bytes, other_debug) = assemble_str(src, false).unwrap();
+            assert_eq!(bytes, other_bytes);
+            assert_eq!(
+                serde_json::to_string(&debug).unwrap(),
+                serde_json::to_string(&other_debug).unwrap()
+            );
+            let model = generate_program_model(split_lines(src), false, false).unwrap();
+            assert_eq!(
+                serde_json::to_string(&first_model).unwrap(),
+                serde_json::to_string(&model).unwrap()
+            );
+            assert_eq!(
+                collect_warnings(&first_model, DEFAULT_STRING_WARN_LEN),
+                collect_warnings(&model, DEFAULT_STRING_WARN_LEN)
+            );
+        }
+    }
+
+    #[test]
+    fn test_assemble_many() {
+        let sources = vec![
+            (
+                PathBuf::from("good.basm"),
+                String::from("good\n1\n.ops\nprtc 'a'\nhalt"),
+            ),
+            (
+                PathBuf::from("bad.basm"),
+                String::from("bad\n1\n.ops\njmp nowhere"),
+            ),
+            (
+                PathBuf::from("also_good.basm"),
+                String::from("also\n1\n.ops\nhalt"),
+            ),
+        ];
+        let results = assemble_many(&sources);
+        assert_eq!(results.len(), 3);
+
+        let (bytes, _) = results[0].as_ref().unwrap();
+        assert_eq!(bytes, &assemble_str(&sources[0].1, false).unwrap().0);
+        let err = results[1].as_ref().unwrap_err();
+        assert_eq!(err.to_string(), "assembling bad.basm");
+        assert!(
+            err.root_cause().to_string().contains("nowhere"),
+            "{:?}",
+            err
+        );
+        assert!(results[2].is_ok());
+
+        assert!(assemble_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_empty_ops() {
+        let src = "test\n1\n.strings\nmsg=hi\n.ops\n# nothing here";
+
+        //Without validation (e.g. for tools building tapes) no ops is allowed
+        let program_model = generate_program_model(split_lines(src), false, false).unwrap();
+        assert!(generate_byte_code(program_model).is_ok());
+
+        let err = assemble_str(src, false).unwrap_err();
+        assert!(err.to_string().starts_with("No ops found"), "{}", err);
+        assert!(assemble_files(
+            split_lines(src),
+            None,
+            None,
+            None,
+            &AssembleOptions::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_keys_missing_from_empty_sections() {
+        let err = assemble_str("test\n1\n.strings\n.ops\nprts k", false).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            "String key k used on prts k, line 5 but was never defined"
+        );
+
+        let err = assemble_str("test\n1\n.data\n.ops\nld a0 k 0 0", false).unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            "Data key k used on ld a0 k 0 0, line 5 but was never defined"
+        );
+    }
+
     #[test]
     #[rustfmt::skip]
     fn test_full_program() {
-        let program = "Math Test\n1\n.strings\npass=PASS\nfail=FAIL\n.data\nvalues=[[1,2]]\n.strings\nplus=+\n.ops\nld a0 values 1 0\ncpy d0 a0\nprt d0\nprts plus\nprt d0\nprtc '='\nadd d0 d0\npush acc\nld a0 values 1 1\ncpy d0 a0\npush d0\nprt d0\ncall assert_eq\nhalt\nassert_eq:\narg d0 1\narg d1 2\ncmp d0 d1\nprtc ' '\nje assert_pass\nprts fail\nje done\nassert_pass:prts pass\ndone: \nprtln\nret\n"
+        let program = "Math Test\n1\n.strings\npass=PASS\nfail=FAIL\nplus=+\n.data\nvalues=[[1,2]]\n.ops\nld a0 values 1 0\ncpy d0 a0\nprt d0\nprts plus\nprt d0\nprtc '='\nadd d0 d0\npush acc\nld a0 values 1 1\ncpy d0 a0\npush d0\nprt d0\ncall assert_eq\nhalt\nassert_eq:\narg d0 1\narg d1 2\ncmp d0 d1\nprtc ' '\nje assert_pass\nprts fail\nje done\nassert_pass:prts pass\ndone: \nprtln\nret\n"
             .lines()
             .map(|s| s.to_owned())
             .collect::<Vec<String>>();
         
-        let bytes  = assemble(program, None, None).unwrap();
+        let bytes  = assemble_files(program, None, None, None, &AssembleOptions::default()).unwrap();
         
-        assert_eq!(bytes, vec![
+        assert_eq!(&bytes[..bytes.len() - 4], vec![
             TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
             9, 77, 97, 116, 104, 32, 84, 101, 115, 116,
             1, 49,
+            0, 0,
             0, 65,
             LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 1, 0,
             CPY_REG_AREG, REG_D0, REG_A0,
@@ -197,4 +871,73 @@ mod test {
             1, 2, 1, 2
         ]);
     }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_assemble_str() {
+        let src = "Str Test\n1\n.strings\ngreeting=  Hello  \n.data\nnums=[[1,2]]\n.ops\nld a0 nums 0 1\nprts greeting\nhalt\n";
+
+        let (bytes, debug) = assemble_str(src, false).unwrap();
+        assert_eq!(&bytes[..bytes.len() - 4], vec![
+            TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
+            8, 83, 116, 114, 32, 84, 101, 115, 116,
+            1, 49,
+            0, 0,
+            0, 10,
+            LD_AREG_DATA_VAL_VAL, REG_A0, 0, 0, 0, 1,
+            PRTS_STR, 0, 0,
+            HALT,
+            0, 6,
+            5, 72, 101, 108, 108, 111,
+            1, 2, 1, 2
+        ]);
+        assert_eq!(debug.ops.len(), 3);
+        assert_eq!(debug.ops[1].byte_addr, 6);
+        assert_eq!(debug.ops[1].original_line, String::from("prts greeting"));
+        assert_eq!(debug.ops[1].line_num, 9);
+        assert_eq!(debug.data[0].key, String::from("nums"));
+
+        let (bytes, _) = assemble_str(src, true).unwrap();
+        assert_eq!(&bytes[28..40], &[0, 10, 9, 32, 32, 72, 101, 108, 108, 111, 32, 32]);
+    }
+
+    #[test]
+    fn test_constant_number_forms() {
+        let src = "Const Test\n1\n.ops\nconst space ' '\nconst flag b10000000\ncpy d0 space\ncpy d1 flag\n";
+
+        let (bytes, _) = assemble_str(src, false).unwrap();
+        assert_eq!(
+            &bytes[bytes.len() - 12..bytes.len() - 6],
+            &[CPY_REG_VAL, REG_D0, 32, CPY_REG_VAL, REG_D1, 128]
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_header_directives() {
+        let (bytes, _) = assemble_str(".name \"My Prog\"\n.version \"1.2\"\n.ops\nhalt", false).unwrap();
+        assert_eq!(&bytes[..15], &[
+            TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
+            7, 77, 121, 32, 80, 114, 111, 103,
+            3, 49, 46, 50,
+        ]);
+
+        let (bytes, debug) = assemble_str(".version 2\n# comment\n\n.ops\nhalt", false).unwrap();
+        assert_eq!(&bytes[..15], &[
+            TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION,
+            8, 85, 110, 116, 105, 116, 108, 101, 100,
+            1, 50,
+            0,
+        ]);
+        assert_eq!(debug.ops[0].line_num, 5);
+
+        assert!(assemble_str(&format!(".name \"{}\"\n.ops\nhalt", "a".repeat(256)), false).is_err());
+        assert!(assemble_str(".name a\n.name b\n.ops\nhalt", false).is_err());
+
+        let (bytes, debug) = assemble_str("# My program\n\n.name \"My Prog\"\n.ops\nhalt", false).unwrap();
+        assert_eq!(&bytes[3..11], &[7, 77, 121, 32, 80, 114, 111, 103]);
+        assert_eq!(debug.ops[0].line_num, 5);
+        let (bytes, _) = assemble_str("  \n# header\n.ops\nhalt", false).unwrap();
+        assert_eq!(&bytes[3..12], &[8, 85, 110, 116, 105, 116, 108, 101, 100]);
+    }
 }
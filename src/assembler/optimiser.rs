@@ -0,0 +1,128 @@
+use crate::assembler::program_model::{OpModel, ProgramModel};
+use crate::constants::code::{CALL_ADDR, CALL_AREG, JMP_ADDR, JMP_AREG, RET};
+
+/// Replaces `CALL x` directly followed by `RET` with `JMP x` so the called routine returns straight
+/// to the caller, reusing the current stack frame
+/// The `RET` is left in place (JMP and CALL are the same size) so no addresses change
+/// CALLs are not replaced if a label is on the `RET`, as it may be reached without the CALL
+/// Returns the number of CALLs replaced
+pub fn optimise_tail_calls(program_model: &mut ProgramModel) -> usize {
+    let mut count = 0;
+    for idx in 1..program_model.ops.len() {
+        let call = &program_model.ops[idx - 1];
+        let ret = &program_model.ops[idx];
+        if ret.opcode != RET || has_label_between(program_model, call, ret) {
+            continue;
+        }
+        let opcode = match call.opcode {
+            CALL_ADDR => JMP_ADDR,
+            CALL_AREG => JMP_AREG,
+            _ => continue,
+        };
+        let call = &mut program_model.ops[idx - 1];
+        call.opcode = opcode;
+        call.after_processing = call
+            .after_processing
+            .split_whitespace()
+            .enumerate()
+            .map(|(i, word)| if i == 0 { "jmp" } else { word })
+            .collect::<Vec<&str>>()
+            .join(" ");
+        count += 1;
+    }
+    count
+}
+
+/// Labels are assigned to the first op on or after their line, so a label defined after `first`
+/// but on or before `second` points at `second`
+fn has_label_between(program_model: &ProgramModel, first: &OpModel, second: &OpModel) -> bool {
+    program_model.labels.values().any(|label| {
+        label
+            .definition
+            .as_ref()
+            .is_some_and(|def| def.line_num > first.line_num && def.line_num <= second.line_num)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::generator::generate_byte_code;
+    use crate::assembler::parser::generate_program_model;
+    use crate::device::comm::Output;
+    use crate::device::internals::{Device, RunResult};
+    use crate::language::parser::params::Param;
+    use crate::tape_reader::parse_tape;
+
+    const PROGRAM: &str = "Tail\n1\n.ops\ncpy d0 5\ncall count\nprt d1\nhalt\ncount:\ncmp d0 0\nje done\ndec d0\ninc d1\ncall count\nret\ndone:ret";
+
+    fn make_model() -> ProgramModel {
+        let input = PROGRAM.lines().map(|line| line.to_owned()).collect();
+        generate_program_model(input, false, false).unwrap()
+    }
+
+    fn run(program_model: ProgramModel) -> (String, u16) {
+        let (bytes, _) = generate_byte_code(program_model).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+        let mut device = Device::new(tape.ops, tape.strings, tape.data, vec![]);
+        let mut lowest_sp = device.dump().sp;
+        loop {
+            match device.step(true) {
+                RunResult::Pause => lowest_sp = lowest_sp.min(device.dump().sp),
+                RunResult::Halt => break,
+                result => panic!("Unexpected result {:?}", result),
+            }
+        }
+        let output = device
+            .output
+            .iter()
+            .map(|output| match output {
+                Output::OutputStd(text) => text.clone(),
+                _ => String::new(),
+            })
+            .collect();
+        (output, lowest_sp)
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let mut model = make_model();
+
+        assert_eq!(optimise_tail_calls(&mut model), 1);
+
+        assert_eq!(model.ops[1].opcode, CALL_ADDR);
+        assert_eq!(model.ops[8].opcode, JMP_ADDR);
+        assert_eq!(
+            model.ops[8].params,
+            vec![Param::Label(String::from("count"))]
+        );
+        assert_eq!(model.ops[8].after_processing, String::from("jmp count"));
+        assert_eq!(model.ops[9].opcode, RET);
+        assert_eq!(model.ops[10].opcode, RET);
+    }
+
+    #[test]
+    fn test_label_on_ret() {
+        let input = "Tail\n1\n.ops\ncall routine\nend:ret\nroutine:ret"
+            .lines()
+            .map(|line| line.to_owned())
+            .collect();
+        let mut model = generate_program_model(input, false, false).unwrap();
+
+        assert_eq!(optimise_tail_calls(&mut model), 0);
+        assert_eq!(model.ops[0].opcode, CALL_ADDR);
+    }
+
+    #[test]
+    fn test_behaviour_preserved() {
+        let (expected_output, expected_sp) = run(make_model());
+
+        let mut model = make_model();
+        optimise_tail_calls(&mut model);
+        let (output, sp) = run(model);
+
+        assert_eq!(output, expected_output);
+        assert_eq!(output, String::from("5"));
+        assert!(sp > expected_sp, "{} <= {}", sp, expected_sp);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::constants::hardware::{MAX_DATA_ARRAY_COUNT, MAX_DATA_ARRAY_LEN};
+use crate::language::parser::params::char_literal_error;
 use anyhow::{Context, Error, Result};
 
 #[derive(Debug)]
@@ -166,6 +167,9 @@ impl DataParser {
             && self.current_content.starts_with('\'')
             && self.current_content.ends_with('\'')
         {
+            if let Some(err) = char_literal_error(&self.current_content) {
+                return Err(Error::msg(format!("{} at char {} (e315)", err, chr_idx)));
+            }
             self.value_mode = ValueMode::None;
             self.current_array
                 .push(self.current_content.chars().nth(1).unwrap() as u8);
@@ -367,14 +371,14 @@ impl DataParser {
 
 fn validate_content(content: &str) -> Result<String> {
     let trimmed = content.trim();
-    return if trimmed.starts_with('[') && trimmed.ends_with(']') {
+    if trimmed.starts_with('[') && trimmed.ends_with(']') {
         let mut chars = trimmed.chars();
         chars.next();
         chars.next_back();
         Ok(chars.collect::<String>().trim().to_owned())
     } else {
         Err(Error::msg("Invalid data definition, data must be made of an array with arrays or strings inside.\ne.g. [[1,2],['a','b'],\"ex\"]  (e400)"))
-    };
+    }
 }
 
 #[cfg(test)]
@@ -819,5 +823,21 @@ mod test {
                 expect_error_hex(i);
             }
         }
+
+        #[test]
+        fn test_non_ascii_char() {
+            for (input, chr) in [("[['é']]", "é"), ("[['a', '😀']]", "😀")] {
+                let mut parser = DataParser::new();
+                let error = format!("{:#}", parser.run(input).unwrap_err());
+                assert!(
+                    error.contains(&format!(
+                        "char literals must be a single ASCII character; '{}' is not ASCII",
+                        chr
+                    )),
+                    "{}",
+                    error
+                );
+            }
+        }
     }
 }
@@ -1,20 +1,27 @@
 use anyhow::{Context, Error, Result};
 use lazy_static::lazy_static;
+use regex::{Captures, Regex};
 
 use crate::assembler::parser::data_parser::DataParser;
 use crate::assembler::program_model::{
-    ConstantModel, DataModel, Definition, LabelModel, OpModel, ProgramModel, StringModel, Usage,
+    ConstantModel, DataModel, Definition, LabelModel, OpModel, ProgramModel, StringModel,
+    UndefinedKey, Usage,
 };
-use crate::assembler::FORMAT_ERROR;
-use crate::constants::code::{DIVDERS, KEYWORDS, MNEMONICS, REGISTERS};
-use crate::constants::hardware::MAX_STRING_LEN;
-use crate::language::parse_line;
-use crate::language::parser::params::Param;
-use std::collections::HashMap;
+use crate::assembler::{AssembleOptions, FORMAT_ERROR};
+use crate::constants::code::{
+    DIVDERS, KEYWORDS, LD_AREG_DATA_VAL_REG, LD_AREG_DATA_VAL_VAL, MNEMONICS, REGISTERS,
+};
+use crate::constants::hardware::{MAX_DATA_ARRAY_LEN, MAX_DATA_BYTES, MAX_STRING_LEN};
+use crate::language::parser::params::{parse_addr, parse_number, Param};
+use crate::language::{parse_line, split_line};
+use std::collections::{HashMap, HashSet};
 
 mod data_parser;
 
-#[derive(Debug, Eq, PartialEq)]
+const DEFAULT_NAME: &str = "Untitled";
+const DEFAULT_VERSION: &str = "1";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum ParseMode {
     Header,
     Strings,
@@ -22,113 +29,534 @@ pub enum ParseMode {
     Ops,
 }
 
-pub fn generate_program_model(input: Vec<String>) -> Result<ProgramModel> {
-    if input.len() < 4 {
-        return Err(Error::msg(FORMAT_ERROR));
+impl ParseMode {
+    ///Name of the section, as used in its divider
+    fn name(&self) -> &'static str {
+        match self {
+            ParseMode::Header => "header",
+            ParseMode::Strings => "strings",
+            ParseMode::Data => "data",
+            ParseMode::Ops => "ops",
+        }
     }
-    let mut iter = input.into_iter();
-    let name = ProgramModel::validate_name(
-        iter.next()
-            .context(format!("Program name missing\n\n{}", FORMAT_ERROR))?,
-    )?;
-    let version = ProgramModel::validate_version(
-        iter.next()
-            .context(format!("Program version missing\n\n{}", FORMAT_ERROR))?,
-    )?;
+}
+
+/// * `keep_whitespace`: If true string content is not trimmed
+/// * `strict`: If true any operand that could be an address or a label must be an `@address` or a label defined in the program
+pub fn generate_program_model(
+    input: Vec<String>,
+    keep_whitespace: bool,
+    strict: bool,
+) -> Result<ProgramModel> {
+    let options = AssembleOptions {
+        keep_whitespace,
+        strict,
+        ..AssembleOptions::default()
+    };
+    generate_program_model_with_options(input, &options)
+}
+
+///Same as `generate_program_model` but uses `keep_whitespace`, `strict` and `defines` from `options`
+pub fn generate_program_model_with_options(
+    input: Vec<String>,
+    options: &AssembleOptions,
+) -> Result<ProgramModel> {
+    build_program_model(input, options, OnError::Stop).map(|(model, _)| model)
+}
+
+///Same as `generate_program_model` but an error on a line doesn't stop parsing, that line is skipped
+///and the error is returned with its line number. Only header errors are returned as `Err`
+pub fn generate_program_model_recovering(
+    input: Vec<String>,
+    options: &AssembleOptions,
+) -> Result<(ProgramModel, Vec<(usize, Error)>)> {
+    build_program_model(input, options, OnError::Recover)
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum OnError {
+    ///Return the first error
+    Stop,
+    ///Skip the line and keep parsing, errors are returned with their line numbers
+    Recover,
+}
+
+fn build_program_model(
+    input: Vec<String>,
+    options: &AssembleOptions,
+    on_error: OnError,
+) -> Result<(ProgramModel, Vec<(usize, Error)>)> {
+    let recover = on_error == OnError::Recover;
+    let keep_whitespace = options.keep_whitespace;
+    let mut line_errors = vec![];
+    let mut handle = |result: Result<()>, line_num: usize, line: &str| -> Result<()> {
+        match result {
+            Err(err) if recover => {
+                line_errors.push((line_num, err));
+                Ok(())
+            }
+            result => result.context(line.to_string()),
+        }
+    };
+    //Directive headers can start with comments or blank lines, the classic header can't as the first line is the name
+    let uses_directives = input
+        .iter()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.starts_with('.'))
+        .unwrap_or(false);
+    let (name, version, header_len) = if uses_directives {
+        parse_header_directives(&input)?
+    } else {
+        if input.len() < 4 {
+            return Err(Error::msg(FORMAT_ERROR));
+        }
+        let name = ProgramModel::validate_name(input[0].clone())?;
+        let version = ProgramModel::validate_version(input[1].clone())?;
+        (name, version, 2)
+    };
+    let mut input = input;
+    apply_conditionals(&mut input, header_len, &options.defines)?;
+    join_continued_lines(&mut input, header_len);
     let mut program_model = ProgramModel::new(name, version);
     let mut parse_mode = ParseMode::Header;
+    let mut seen = vec![];
+    let mut op_lines = vec![];
 
-    for (idx, line) in iter.enumerate() {
-        let line_num = idx + 3;
+    for (idx, line) in input.into_iter().enumerate().skip(header_len) {
+        let line_num = idx + 1;
         let trimmed = line.trim();
         if !trimmed.starts_with('#') && !trimmed.is_empty() {
-            match trimmed {
-                ".strings" => {
-                    if parse_mode == ParseMode::Ops {
-                        return Err(Error::msg(format!("Unexpected .strings divider at line {}, all data and strings must be defined before .ops", line_num)));
-                    } else {
-                        parse_mode = ParseMode::Strings;
-                    }
+            let divider = match parse_divider(trimmed, line_num) {
+                Ok(divider) => divider,
+                Err(err) => {
+                    handle(Err(err), line_num, &line)?;
+                    continue;
                 }
-                ".data" => {
-                    if parse_mode == ParseMode::Ops {
-                        return Err(Error::msg(format!("Unexpected .data divider at line {}, all data and strings must be defined before .ops", line_num)));
-                    } else {
-                        parse_mode = ParseMode::Data;
-                    }
-                }
-                ".ops" => {
-                    if parse_mode == ParseMode::Ops {
+            };
+            match divider {
+                Some(mode) if seen.contains(&mode) => {
+                    if !recover {
                         return Err(Error::msg(format!(
-                            "Unexpected .ops divider at line {}, already in ops section",
+                            "Unexpected .{0} divider at line {1}, there can only be one {0} section",
+                            mode.name(),
                             line_num
                         )));
-                    } else {
-                        parse_mode = ParseMode::Ops;
                     }
+                    handle(
+                        Err(Error::msg(format!(
+                            "Unexpected .{0} divider, there can only be one {0} section",
+                            mode.name()
+                        ))),
+                        line_num,
+                        &line,
+                    )?;
+                }
+                Some(mode) => {
+                    seen.push(mode);
+                    parse_mode = mode;
                 }
-                "" => {}
-                _ => match parse_mode {
+                None => match parse_mode {
                     ParseMode::Header => {
-                        return Err(Error::msg(format!(
-                            "Unexpected content: {}\n\n{}",
-                            line, FORMAT_ERROR
-                        )));
-                    }
-                    ParseMode::Strings => {
-                        parse_string(&mut program_model, &line, line_num).context(line)?
-                    }
-                    ParseMode::Data => {
-                        parse_data(&mut program_model, &line, line_num).context(line)?
-                    }
-                    ParseMode::Ops => {
-                        if trimmed.to_lowercase().starts_with("const") {
-                            parse_constant(&mut program_model, &line, line_num).context(line)?
-                        } else {
-                            parse_op(&mut program_model, &line, line_num).context(line)?
+                        if !recover {
+                            return Err(Error::msg(format!(
+                                "Unexpected content: {}\n\n{}",
+                                line, FORMAT_ERROR
+                            )));
                         }
+                        handle(
+                            Err(Error::msg("Unexpected content before the first divider")),
+                            line_num,
+                            &line,
+                        )?;
                     }
+                    ParseMode::Strings => handle(
+                        parse_string(
+                            &mut program_model,
+                            strip_comment(&line),
+                            line_num,
+                            keep_whitespace,
+                        ),
+                        line_num,
+                        &line,
+                    )?,
+                    ParseMode::Data => handle(
+                        parse_data(&mut program_model, strip_comment(&line), line_num),
+                        line_num,
+                        &line,
+                    )?,
+                    //Ops are parsed after all strings and data as they may be defined later in the file
+                    ParseMode::Ops => op_lines.push((line_num, line)),
                 },
             }
         }
     }
 
-    Ok(program_model)
+    for (line_num, line) in op_lines {
+        let trimmed = line.trim().to_lowercase();
+        let result = if trimmed.starts_with("const") {
+            parse_constant(&mut program_model, &line, line_num)
+        } else if trimmed.starts_with("alias ") {
+            parse_alias(&mut program_model, &line, line_num)
+        } else if trimmed.starts_with(".jumptable ") {
+            parse_jumptable(&mut program_model, &line, line_num)
+        } else if trimmed.starts_with(".entry ") {
+            parse_entry(&mut program_model, &line, line_num)
+        } else {
+            parse_op(&mut program_model, &line, line_num)
+        };
+        handle(result, line_num, &line)?;
+    }
+
+    if options.strict {
+        let errors = strict_label_errors(&program_model);
+        if recover {
+            line_errors.extend(
+                errors
+                    .into_iter()
+                    .map(|(line_num, msg)| (line_num, Error::msg(msg))),
+            );
+        } else if !errors.is_empty() {
+            return Err(Error::msg(
+                errors
+                    .into_iter()
+                    .map(|(_, msg)| msg)
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            ));
+        }
+    }
+
+    Ok((program_model, line_errors))
 }
 
-pub fn parse_constant(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
-    let splits = line.split_whitespace().collect::<Vec<&str>>();
-    if splits.len() < 2 {
-        return Err(Error::msg(format!(
-            "Error parsing constant on line {}, format must be const <key> <value>, e.g. const result d3",
-            line_num
-        )));
+struct Conditional {
+    line_num: usize,
+    ///True if the block containing this one is included
+    parent_included: bool,
+    included: bool,
+    seen_else: bool,
+}
+
+///Removes the lines of `.ifdef <name>`/`.ifndef <name>` ... [`.else` ...] `.endif` blocks that aren't included
+///A name is defined if it's in `defines` or is an included constant before the block, blocks can be nested
+///Directive and removed lines are left empty so line numbers are unchanged
+fn apply_conditionals(input: &mut [String], start: usize, defines: &[String]) -> Result<()> {
+    let mut defined: HashSet<String> = defines.iter().cloned().collect();
+    let mut open: Vec<Conditional> = vec![];
+    for (idx, line) in input.iter_mut().enumerate().skip(start) {
+        let line_num = idx + 1;
+        let included = open.last().map(|block| block.included).unwrap_or(true);
+        let words = strip_comment(line)
+            .split_whitespace()
+            .collect::<Vec<&str>>();
+        match words.as_slice() {
+            [directive @ (".ifdef" | ".ifndef"), name] => {
+                let condition = defined.contains(*name) == (*directive == ".ifdef");
+                open.push(Conditional {
+                    line_num,
+                    parent_included: included,
+                    included: included && condition,
+                    seen_else: false,
+                });
+            }
+            [".else"] => match open.last_mut() {
+                Some(block) if !block.seen_else => {
+                    block.seen_else = true;
+                    block.included = block.parent_included && !block.included;
+                }
+                Some(block) => {
+                    return Err(Error::msg(format!(
+                        "Unexpected .else on line {}, the block on line {} already has one",
+                        line_num, block.line_num
+                    )))
+                }
+                None => {
+                    return Err(Error::msg(format!(
+                        "Unexpected .else on line {}, there's no .ifdef or .ifndef before it",
+                        line_num
+                    )))
+                }
+            },
+            [".endif"] => {
+                if open.pop().is_none() {
+                    return Err(Error::msg(format!(
+                        "Unexpected .endif on line {}, there's no .ifdef or .ifndef before it",
+                        line_num
+                    )));
+                }
+            }
+            [directive, ..] if matches!(*directive, ".ifdef" | ".ifndef" | ".else" | ".endif") => {
+                return Err(Error::msg(format!(
+                    "Error parsing {} on line {}, format must be .ifdef <name>, .ifndef <name>, .else or .endif",
+                    directive, line_num
+                )))
+            }
+            [keyword, name, ..] if included && keyword.eq_ignore_ascii_case("const") => {
+                defined.insert(name.to_string());
+                continue;
+            }
+            _ if included => continue,
+            _ => {}
+        }
+        line.clear();
+    }
+    match open.first() {
+        Some(block) => Err(Error::msg(format!(
+            "Conditional block on line {} has no .endif",
+            block.line_num
+        ))),
+        None => Ok(()),
+    }
+}
+
+///Joins string and data lines ending with `\` to the next line (without its leading whitespace)
+///The joined lines are left empty so line numbers are unchanged, errors use the first line of the definition
+fn join_continued_lines(input: &mut [String], start: usize) {
+    let mut in_ops = false;
+    let mut idx = start;
+    while idx < input.len() {
+        let line = input[idx].trim_start();
+        match strip_comment(line).trim() {
+            ".ops" => in_ops = true,
+            ".strings" | ".data" => in_ops = false,
+            _ => {}
+        }
+        let mut next = idx + 1;
+        if !in_ops && !line.starts_with('#') {
+            while next < input.len() && is_continued(&input[idx]) {
+                let line = input[idx].trim_end();
+                let mut joined = line[..line.len() - 1].to_string();
+                joined.push_str(input[next].trim_start());
+                input[idx] = joined;
+                input[next].clear();
+                next += 1;
+            }
+        }
+        idx = next;
     }
-    let key = splits[1].trim();
-    let value = splits[2];
+}
+
+///True if `line` ends with an odd number of backslashes, `\\` is an escaped backslash
+fn is_continued(line: &str) -> bool {
+    line.trim_end()
+        .chars()
+        .rev()
+        .take_while(|chr| *chr == '\\')
+        .count()
+        % 2
+        == 1
+}
+
+///Returns the section for a divider line, or None if `line` isn't a divider
+///Sections can be in any order but each can only appear once
+///Dividers can have a trailing comment, e.g. `.ops # main`
+fn parse_divider(line: &str, line_num: usize) -> Result<Option<ParseMode>> {
+    let line = strip_comment(line).trim();
+    match line {
+        ".strings" => Ok(Some(ParseMode::Strings)),
+        ".data" => Ok(Some(ParseMode::Data)),
+        ".ops" => Ok(Some(ParseMode::Ops)),
+        _ if line.starts_with('.')
+            && !line.contains(|chr: char| chr == '=' || chr.is_whitespace()) =>
+        {
+            match DIVDERS
+                .iter()
+                .find(|divider| edit_distance(line, divider) <= 2)
+            {
+                Some(divider) => Err(Error::msg(format!(
+                    "Unknown divider {} at line {}, did you mean {}?",
+                    line, line_num, divider
+                ))),
+                None => Err(Error::msg(format!(
+                    "Unknown divider {} at line {}, must be one of {}",
+                    line,
+                    line_num,
+                    DIVDERS.join(" ")
+                ))),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+///Number of single char insertions, deletions or substitutions to turn `lhs` into `rhs`
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs = rhs.chars().collect::<Vec<char>>();
+    let mut previous = (0..=rhs.len()).collect::<Vec<usize>>();
+    for (idx, lhs_chr) in lhs.chars().enumerate() {
+        let mut current = vec![idx + 1];
+        for (jdx, rhs_chr) in rhs.iter().enumerate() {
+            let cost = if lhs_chr == *rhs_chr { 0 } else { 1 };
+            current.push(
+                (previous[jdx] + cost)
+                    .min(previous[jdx + 1] + 1)
+                    .min(current[jdx] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[rhs.len()]
+}
+
+///Reads `.name` and `.version` directives from the start of the file, missing values use the defaults
+///Returns the name, version and the number of lines used
+fn parse_header_directives(input: &[String]) -> Result<(String, String, usize)> {
+    let mut name = None;
+    let mut version = None;
+    let mut header_len = 0;
+    for (idx, line) in input.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let (directive, value) = trimmed
+            .split_once(char::is_whitespace)
+            .unwrap_or((trimmed, ""));
+        let target = match directive {
+            ".name" => &mut name,
+            ".version" => &mut version,
+            _ => break,
+        };
+        if target.is_some() {
+            return Err(Error::msg(format!(
+                "Duplicate {} directive on line {}",
+                directive,
+                idx + 1
+            )));
+        }
+        let value = value.trim();
+        let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+        *target = Some(value.to_string());
+        header_len = idx + 1;
+    }
+    let name = ProgramModel::validate_name(name.unwrap_or_else(|| String::from(DEFAULT_NAME)))?;
+    let version =
+        ProgramModel::validate_version(version.unwrap_or_else(|| String::from(DEFAULT_VERSION)))?;
+    Ok((name, version, header_len))
+}
+
+///Every use of an undefined label with its line number, sorted by line
+fn strict_label_errors(program_model: &ProgramModel) -> Vec<(usize, String)> {
+    let mut errors = program_model
+        .labels
+        .values()
+        .filter(|label| label.definition.is_none())
+        .flat_map(|label| {
+            label.usage.iter().map(move |usage| {
+                (
+                    usage.line_num,
+                    format!(
+                        "`{}` on line {} is not an address or a defined label (addresses must start with @): {}",
+                        label.key, usage.line_num, usage.original_line.trim()
+                    ),
+                )
+            })
+        })
+        .collect::<Vec<(usize, String)>>();
+    errors.sort();
+    errors
+}
+
+///Numeric values (in any form, e.g. `xFF`, `b10000000` or `' '`) are stored as decimal so they're substituted as plain numbers
+pub fn parse_constant(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
+    let (key, value) = match split_line(line).as_slice() {
+        [_, key, value] if !value.starts_with('#') => (*key, *value),
+        [_, key, value, comment, ..] if !value.starts_with('#') && comment.starts_with('#') => {
+            (*key, *value)
+        }
+        _ => {
+            return Err(Error::msg(format!(
+                "Error parsing constant on line {}, format must be const <key> <value>, e.g. const result d3",
+                line_num
+            )))
+        }
+    };
     program_model.validate_key("constant key", key, line_num, false)?;
-    let model = ConstantModel::new(key.to_owned(), value.to_owned(), line.to_owned(), line_num);
+    let value = match parse_number(value) {
+        Ok(Param::Number(num)) => num.to_string(),
+        _ => value.to_owned(),
+    };
+    let model = ConstantModel::new(key.to_owned(), value, line.to_owned(), line_num);
     program_model.constants.insert(key.to_owned(), model);
     Ok(())
 }
 
-pub fn parse_string(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
-    return if let Some((key, content)) = line.split_once('=') {
-        let key = key.trim();
-        program_model.validate_key("string key", key, line_num, false)?;
-        let mut content = content.trim().to_owned();
-        if content.is_empty() {
+///Aliases share the definition line of their target so they resolve to the same op
+pub fn parse_alias(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
+    let definition = line.trim().get("alias".len()..).unwrap_or("");
+    let (key, target) = match definition.split_once('=') {
+        Some((key, target)) if !key.trim().is_empty() && !target.trim().is_empty() => {
+            (key.trim(), target.trim())
+        }
+        _ => {
             return Err(Error::msg(format!(
-                "String on line {} has no content, it must be defined as <key>=<content>, e.g. greeting=Hello world",
+                "Error parsing alias on line {}, format must be alias <new> = <existing>, e.g. alias again = start",
                 line_num
-            )));
+            )))
+        }
+    };
+    let target_line = match program_model
+        .labels
+        .get(target)
+        .and_then(|model| model.definition.as_ref())
+    {
+        Some(def) => def.line_num,
+        None => {
+            return Err(Error::msg(format!(
+            "Invalid alias '{}' on line {}\nLabel '{}' must be defined before it can be aliased",
+            key, line_num, target
+        )))
         }
+    };
+    program_model.validate_key("label", key, line_num, true)?;
+    let def = Some(Definition::new(line.to_owned(), target_line));
+    if let Some(model) = program_model.labels.get_mut(key) {
+        model.definition = def;
+    } else {
+        program_model
+            .labels
+            .insert(key.to_owned(), LabelModel::new(key.to_owned(), def, vec![]));
+    }
+    Ok(())
+}
+
+///Keys prefixed with `raw:` keep their whitespace regardless of `keep_whitespace`
+pub fn parse_string(
+    program_model: &mut ProgramModel,
+    line: &str,
+    line_num: usize,
+    keep_whitespace: bool,
+) -> Result<()> {
+    if let Some((key, content)) = line.split_once('=') {
+        let key = key.trim();
+        let (key, keep_whitespace) = match key.strip_prefix("raw:") {
+            Some(key) => (key.trim(), true),
+            None => (key, keep_whitespace),
+        };
+        program_model.validate_key("string key", key, line_num, false)?;
+        let mut content = if keep_whitespace {
+            content.to_owned()
+        } else {
+            content.trim().to_owned()
+        };
         if content.starts_with('"') && content.ends_with('"') && content.len() > 2 {
             let mut chars = content.chars();
             chars.next();
             chars.next_back();
             content = chars.collect();
         }
+        if let Some(chr) = content.chars().find(|chr| chr.is_ascii_control()) {
+            return Err(Error::msg(format!(
+                "String {} on line {} contains control character x{:02X}, use an escape sequence instead (\\n, \\t or \\\\)",
+                key, line_num, chr as u8
+            )));
+        }
+        let content = unescape_string(&content);
         if content.len() > MAX_STRING_LEN {
             return Err(Error::msg(format!(
                 "String {} (parsed as {}) on line {} is too long, max {} chars",
@@ -145,17 +573,65 @@ pub fn parse_string(program_model: &mut ProgramModel, line: &str, line_num: usiz
             "String on line {} must be defined as <key>=<content>, e.g. greeting=Hello world",
             line_num
         )))
-    };
+    }
+}
+
+///Removes everything from the first `#` that isn't escaped (`\#`) or inside double quotes
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaping = false;
+    for (idx, chr) in line.char_indices() {
+        match chr {
+            _ if escaping => escaping = false,
+            '\\' => escaping = true,
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..idx],
+            _ => {}
+        }
+    }
+    line
+}
+
+///Replaces `\n`, `\t`, `\#` and `\\`, any other backslash is kept as is
+fn unescape_string(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    while let Some(chr) = chars.next() {
+        if chr == '\\' {
+            let escaped = match chars.peek() {
+                Some('n') => Some('\n'),
+                Some('t') => Some('\t'),
+                Some('#') => Some('#'),
+                Some('\\') => Some('\\'),
+                _ => None,
+            };
+            if let Some(escaped) = escaped {
+                chars.next();
+                output.push(escaped);
+                continue;
+            }
+        }
+        output.push(chr);
+    }
+    output
 }
 
 pub fn parse_data(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
-    return if let Some((key, content)) = line.split_once('=') {
+    if let Some((key, content)) = line.split_once('=') {
         let key = key.trim();
         program_model.validate_key("data key", key, line_num, false)?;
-        let mut parser = DataParser::new();
-        let error_msg = format!("Data definition on line {}: \"{}\"", line_num, line);
-        parser.run(content).context(error_msg.clone())?;
-        let (content_bytes, debug_bytes) = parser.into_bytes().context(error_msg)?;
+        let trimmed = content.trim();
+        let (content_bytes, debug_bytes) =
+            if trimmed.len() >= 2 && trimmed.starts_with('"') && trimmed.ends_with('"') {
+                parse_data_ascii(&trimmed[1..trimmed.len() - 1], line_num)?
+            } else if is_data_repeat(trimmed) {
+                parse_data_repeat(&trimmed[1..trimmed.len() - 1], line_num)?
+            } else {
+                let mut parser = DataParser::new();
+                let error_msg = format!("Data definition on line {}: \"{}\"", line_num, line);
+                parser.run(content).context(error_msg.clone())?;
+                parser.into_bytes().context(error_msg)?
+            };
         program_model.data.insert(
             key.to_owned(),
             DataModel::new(
@@ -172,7 +648,183 @@ pub fn parse_data(program_model: &mut ProgramModel, line: &str, line_num: usize)
             "Data on line {} must be defined as <key>=<content>, e.g. some_data=[[1,2,3],[50,60]]",
             line_num
         )))
+    }
+}
+
+/// Quoted data (`key="text"`) is stored as the raw ASCII bytes, without the array count or length
+/// prefixes used for array data or the length prefix used in the strings section
+fn parse_data_ascii(content: &str, line_num: usize) -> Result<(Vec<u8>, Vec<Vec<u8>>)> {
+    if !content.is_ascii() {
+        return Err(Error::msg(format!(
+            "Data on line {} contains non ASCII characters, quoted data must only contain ASCII",
+            line_num
+        )));
+    }
+    let bytes = content.as_bytes().to_vec();
+    Ok((bytes.clone(), vec![bytes]))
+}
+
+fn is_data_repeat(content: &str) -> bool {
+    content.starts_with('[')
+        && content.ends_with(']')
+        && content.contains(';')
+        && content.matches('[').count() == 1
+        && !content.contains('"')
+}
+
+/// Repeated data (`key=[<value>; <count>]`) is stored as `count` raw bytes of `value`, without any prefixes
+fn parse_data_repeat(content: &str, line_num: usize) -> Result<(Vec<u8>, Vec<Vec<u8>>)> {
+    let (value, count) = content.split_once(';').unwrap();
+    let value = match parse_number(value.trim()) {
+        Ok(Param::Number(num)) => num,
+        _ => {
+            return Err(Error::msg(format!(
+            "Data on line {} has an invalid repeat value '{}', must be a byte, e.g. buf=[0; 64]",
+            line_num,
+            value.trim()
+        )))
+        }
+    };
+    let count = count.trim();
+    let count = match count.parse::<usize>() {
+        Ok(num) if num <= MAX_DATA_BYTES => num,
+        _ => {
+            return Err(Error::msg(format!(
+            "Data on line {} has an invalid repeat count '{}', must be a number between 0 and {}",
+            line_num, count, MAX_DATA_BYTES
+        )))
+        }
+    };
+    let bytes = vec![value; count];
+    Ok((bytes.clone(), vec![bytes]))
+}
+
+///`.jumptable reg lbl0 lbl1 ...` jumps to the label at index `reg`, if `reg` is out of range it falls through
+///The label addresses are written to a generated data block (high bytes then low bytes), after one range check
+///the address is loaded into A0 and jumped to with `JMP A0`, so ACC and A0 are overwritten
+pub fn parse_jumptable(
+    program_model: &mut ProgramModel,
+    line: &str,
+    line_num: usize,
+) -> Result<()> {
+    let content = strip_comment(line);
+    let (reg, labels) = match split_line(content).as_slice() {
+        [_, reg, labels @ ..] if !labels.is_empty() => (reg.to_lowercase(), labels.to_vec()),
+        _ => {
+            return Err(Error::msg(format!(
+                "Error parsing jump table on line {}, format must be .jumptable <reg> <label>..., e.g. .jumptable d0 first second",
+                line_num
+            )))
+        }
+    };
+    if labels.len() > MAX_DATA_ARRAY_LEN {
+        return Err(Error::msg(format!(
+            "Jump table on line {} has {} labels, max {}",
+            line_num,
+            labels.len(),
+            MAX_DATA_ARRAY_LEN
+        )));
+    }
+    let key = format!("jumptable_{}", line_num);
+    let end = format!("{}_end", key);
+    if program_model.data.contains_key(&key) || program_model.labels.contains_key(&end) {
+        return Err(Error::msg(format!(
+            "Jump table on line {} needs the data key {} and label {} but one is already used",
+            line_num, key, end
+        )));
+    }
+    for lbl in &labels {
+        program_model.validate_key("label", lbl, line_num, true)?;
+        program_model
+            .labels
+            .entry(lbl.to_string())
+            .or_insert_with(|| LabelModel::new(lbl.to_string(), None, vec![]))
+            .usage
+            .push(Usage::new(line.to_owned(), line_num));
+    }
+    let count = labels.len();
+    let mut content = vec![2, count as u8, count as u8];
+    content.resize(3 + count * 2, 0);
+    program_model.data.insert(
+        key.clone(),
+        DataModel::new(
+            key.clone(),
+            content,
+            vec![vec![0; count], vec![0; count]],
+            line.to_owned(),
+            line_num,
+        ),
+    );
+    program_model.jump_tables.insert(
+        key.clone(),
+        labels.iter().map(|lbl| lbl.to_string()).collect(),
+    );
+    //The end label points at the first op after the jump table, see `generate_ops_bytes`
+    program_model.labels.insert(
+        end.clone(),
+        LabelModel::new(
+            end.clone(),
+            Some(Definition::new(line.to_owned(), line_num + 1)),
+            vec![],
+        ),
+    );
+
+    //The high byte is kept in a scratch register (restored from the stack) as ACC holds the low byte
+    let scratch = if reg == "d0" { "d1" } else { "d0" };
+    let mut ops = vec![];
+    if reg == "acc" {
+        ops.push(String::from("cpy a0 acc acc"));
+    }
+    ops.push(format!("cmp {} {}", reg, count));
+    ops.push(format!("je {}", end));
+    ops.push(format!("jg {}", end));
+    if reg == "acc" {
+        ops.push(String::from("cpy acc acc a0"));
+    }
+    ops.push(format!("ld a0 {} 1 {}", key, reg));
+    ops.push(format!("push {}", scratch));
+    ops.push(format!("cpy {} a0", scratch));
+    ops.push(format!("ld a0 {} 2 {}", key, reg));
+    ops.push(String::from("cpy acc a0"));
+    ops.push(format!("cpy a0 {} acc", scratch));
+    ops.push(format!("pop {}", scratch));
+    ops.push(String::from("jmp a0"));
+
+    let first_op = program_model.ops.len();
+    for op in ops {
+        parse_op(program_model, &op, line_num)?;
+    }
+    for op in &mut program_model.ops[first_op..] {
+        op.original_line = line.to_owned();
+    }
+    Ok(())
+}
+
+///`.entry lbl` starts the program at `lbl` instead of the first op
+pub fn parse_entry(program_model: &mut ProgramModel, line: &str, line_num: usize) -> Result<()> {
+    let lbl = match split_line(strip_comment(line)).as_slice() {
+        [_, lbl] => lbl.to_string(),
+        _ => {
+            return Err(Error::msg(format!(
+            "Error parsing entry point on line {}, format must be .entry <label>, e.g. .entry main",
+            line_num
+        )))
+        }
     };
+    if let Some(entry) = &program_model.entry {
+        return Err(Error::msg(format!(
+            "Entry point on line {} but it was already set to {}",
+            line_num, entry
+        )));
+    }
+    program_model
+        .labels
+        .entry(lbl.clone())
+        .or_insert_with(|| LabelModel::new(lbl.clone(), None, vec![]))
+        .usage
+        .push(Usage::new(line.to_owned(), line_num));
+    program_model.entry = Some(lbl);
+    Ok(())
 }
 
 pub fn parse_op(program_model: &mut ProgramModel, orig_line: &str, line_num: usize) -> Result<()> {
@@ -180,9 +832,14 @@ pub fn parse_op(program_model: &mut ProgramModel, orig_line: &str, line_num: usi
     if line.contains('#') {
         line = line.split_once('#').unwrap().0.to_owned();
     }
-    if line.contains(':') {
-        let (lbl, content) = line.split_once(':').unwrap();
+    //A line can have multiple labels, e.g. `start: loop: inc d0`
+    let mut first_label = true;
+    while let Some((lbl, content)) = line.split_once(':') {
         let lbl = lbl.trim();
+        if !first_label && (lbl.contains(char::is_whitespace) || lbl.contains('\'')) {
+            break;
+        }
+        first_label = false;
         program_model.validate_key("label", lbl, line_num, true)?;
         let def = Some(Definition::new(orig_line.to_owned(), line_num));
         if program_model.labels.contains_key(lbl) {
@@ -202,13 +859,24 @@ pub fn parse_op(program_model: &mut ProgramModel, orig_line: &str, line_num: usi
         return Ok(());
     }
 
-    let processed = replace_constants(&mut program_model.constants, trimmed, line_num);
+    let processed = replace_sizeof(program_model, trimmed, orig_line, line_num)?;
+    let processed = replace_constants(&mut program_model.constants, &processed, line_num);
+
+    if let Some(bytes) = parse_inline_data(&processed, line_num)? {
+        program_model.ops.push(OpModel::new_raw(
+            bytes,
+            processed,
+            orig_line.to_string(),
+            line_num,
+        ));
+        return Ok(());
+    }
 
     let (opcode, params) = parse_line(&processed)?;
 
     for param in &params {
         match param {
-            Param::Label(lbl) => {
+            Param::Label(lbl) | Param::LabelAddr(lbl) => {
                 if !program_model.labels.contains_key(lbl) {
                     program_model.labels.insert(
                         lbl.to_owned(),
@@ -223,29 +891,33 @@ pub fn parse_op(program_model: &mut ProgramModel, orig_line: &str, line_num: usi
                     .push(Usage::new(orig_line.to_owned(), line_num));
             }
             Param::StrKey(key) => {
-                if let Some(model) = program_model.strings.get_mut(key) {
-                    model.usage.push(Usage::new(orig_line.to_owned(), line_num));
-                } else {
-                    return Err(Error::msg(format!(
-                        "String key {} used on {}, line {} but was never defined",
-                        key, orig_line, line_num
-                    )));
+                let usage = Usage::new(orig_line.to_owned(), line_num);
+                match program_model.strings.get_mut(key) {
+                    Some(model) => model.usage.push(usage),
+                    None => program_model.undefined_keys.push(UndefinedKey::new(
+                        "String key",
+                        key.to_owned(),
+                        usage,
+                    )),
                 }
             }
             Param::DataKey(key) => {
-                if let Some(model) = program_model.data.get_mut(key) {
-                    model.usage.push(Usage::new(orig_line.to_owned(), line_num));
-                } else {
-                    return Err(Error::msg(format!(
-                        "Data key {} used on {}, line {} but was never defined",
-                        key, orig_line, line_num
-                    )));
+                let usage = Usage::new(orig_line.to_owned(), line_num);
+                match program_model.data.get_mut(key) {
+                    Some(model) => model.usage.push(usage),
+                    None => program_model.undefined_keys.push(UndefinedKey::new(
+                        "Data key",
+                        key.to_owned(),
+                        usage,
+                    )),
                 }
             }
             _ => {}
         }
     }
 
+    check_data_offsets(program_model, opcode, &params, line_num)?;
+
     program_model.ops.push(OpModel::new(
         opcode,
         params,
@@ -257,16 +929,156 @@ pub fn parse_op(program_model: &mut ProgramModel, orig_line: &str, line_num: usi
     Ok(())
 }
 
+///Returns the bytes for `.db` (numbers) and `.dw` (addresses, big endian) lines, e.g. `table: .db 1 2 'a'`
+///or None if `line` isn't either
+fn parse_inline_data(line: &str, line_num: usize) -> Result<Option<Vec<u8>>> {
+    let parts = split_line(line);
+    let directive = match parts.first() {
+        Some(&".db") | Some(&".dw") => parts[0],
+        _ => return Ok(None),
+    };
+    if parts.len() == 1 {
+        return Err(Error::msg(format!(
+            "{} on line {} has no values",
+            directive, line_num
+        )));
+    }
+    let mut bytes = vec![];
+    for value in &parts[1..] {
+        let param = match directive {
+            ".db" => parse_number(value),
+            _ => parse_addr(value),
+        };
+        match param {
+            Ok(Param::Number(num)) => bytes.push(num),
+            Ok(Param::Addr(addr)) => bytes.extend_from_slice(&addr.to_be_bytes()),
+            Ok(param) => {
+                return Err(Error::msg(format!(
+                    "Invalid {} value on line {}: {} is not a number or address",
+                    directive, line_num, param
+                )))
+            }
+            Err(err) => {
+                return Err(Error::msg(format!(
+                    "Invalid {} value on line {}: {}",
+                    directive, line_num, err
+                )))
+            }
+        }
+    }
+    Ok(Some(bytes))
+}
+
+///Numeric LD offsets must point inside the data, e.g. `ld a0 nums 3 0` errors if `nums` has less than 3 arrays
+///Array 0 is the count and lengths, see language.md
+fn check_data_offsets(
+    program_model: &ProgramModel,
+    opcode: u8,
+    params: &[Param],
+    line_num: usize,
+) -> Result<()> {
+    if opcode != LD_AREG_DATA_VAL_VAL && opcode != LD_AREG_DATA_VAL_REG {
+        return Ok(());
+    }
+    if let [_, Param::DataKey(key), Param::Number(array), rest @ ..] = params {
+        let model = match program_model.data.get(key) {
+            Some(model) => model,
+            //Reported by `ProgramModel::validate`
+            None => return Ok(()),
+        };
+        let arrays = &model.interpretation;
+        //Raw and repeated data has no count or lengths so can't be checked
+        let packed_len = 1 + arrays.len() + arrays.iter().map(|bytes| bytes.len()).sum::<usize>();
+        if model.content.len() != packed_len {
+            return Ok(());
+        }
+        let array_len = match *array as usize {
+            0 => arrays.len() + 1,
+            idx if idx <= arrays.len() => arrays[idx - 1].len(),
+            _ => {
+                return Err(Error::msg(format!(
+                    "Array {} is out of range on line {}, {} has {} array(s)",
+                    array,
+                    line_num,
+                    key,
+                    arrays.len()
+                )))
+            }
+        };
+        if let [Param::Number(byte)] = rest {
+            if *byte as usize >= array_len {
+                return Err(Error::msg(format!(
+                    "Byte {} is out of range on line {}, array {} of {} has {} byte(s)",
+                    byte, line_num, array, key, array_len
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+///Replaces `sizeof(key)` with the byte length of the string or data `key`, e.g. `cpy d0 sizeof(greeting)`
+fn replace_sizeof(
+    program_model: &mut ProgramModel,
+    line: &str,
+    orig_line: &str,
+    line_num: usize,
+) -> Result<String> {
+    let mut error = None;
+    let processed = SIZEOF_REGEX.replace_all(line, |caps: &Captures| {
+        let key = &caps[1];
+        let usage = Usage::new(orig_line.to_owned(), line_num);
+        let len = if let Some(model) = program_model.strings.get_mut(key) {
+            model.usage.push(usage);
+            model.content.len()
+        } else if let Some(model) = program_model.data.get_mut(key) {
+            model.usage.push(usage);
+            model.content.len()
+        } else {
+            error.get_or_insert(format!(
+                "String or data key {} used in sizeof on line {} but was never defined",
+                key, line_num
+            ));
+            0
+        };
+        if len > u8::MAX as usize {
+            error.get_or_insert(format!(
+                "{} is {} bytes, sizeof can only be used with keys up to {} bytes",
+                key,
+                len,
+                u8::MAX
+            ));
+        }
+        len.to_string()
+    });
+    match error {
+        Some(msg) => Err(Error::msg(msg)),
+        None => Ok(processed.into_owned()),
+    }
+}
+
 fn replace_constants(
     constants: &mut HashMap<String, ConstantModel>,
     line: &str,
     line_num: usize,
 ) -> String {
-    line.split_whitespace()
+    split_line(line)
+        .into_iter()
         .map(|word| {
             if let Some(model) = constants.get_mut(word) {
                 model.usage.push(Usage::new(line.to_owned(), line_num));
                 model.content.clone()
+            } else if word.starts_with('(') || word.starts_with("@(") {
+                //Constants can be used inside expressions, e.g. (top-1)
+                EXPR_TERM_REGEX
+                    .replace_all(word, |caps: &Captures| match constants.get_mut(&caps[0]) {
+                        Some(model) => {
+                            model.usage.push(Usage::new(line.to_owned(), line_num));
+                            model.content.clone()
+                        }
+                        None => caps[0].to_owned(),
+                    })
+                    .into_owned()
             } else {
                 word.to_owned()
             }
@@ -276,6 +1088,8 @@ fn replace_constants(
 }
 
 lazy_static! {
+    static ref SIZEOF_REGEX: Regex = Regex::new("sizeof\\(([a-zA-Z0-9_]+)\\)").unwrap();
+    static ref EXPR_TERM_REGEX: Regex = Regex::new("'.'|[a-zA-Z_][a-zA-Z0-9_]*").unwrap();
     static ref KEY_NAME_ERROR: String = format!("Key names must not include any register, keyword, section divider or mnemonic.\nThese include:\n{}\n{}\n{}\n{}",
         REGISTERS.join(" "),KEYWORDS.join(" "),DIVDERS.join(" "),MNEMONICS.join(" ")
         );
@@ -284,8 +1098,11 @@ lazy_static! {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::constants::code::{ADD_REG_VAL, CMP_REG_REG};
-    use crate::constants::hardware::{REG_ACC, REG_D1, REG_D3};
+    use crate::constants::code::{
+        ADD_REG_VAL, CMP_REG_REG, CMP_REG_VAL, DJNZ_AREG_ADDR, ENTER_VAL, LDA_REG_ADDR,
+        LDSTR_AREG_REG_STR, LEAVE, STA_ADDR_REG,
+    };
+    use crate::constants::hardware::{REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
     use crate::language::parser::params::Param;
 
     #[rustfmt::skip]
@@ -302,6 +1119,85 @@ mod test {
         parse_constant(&mut program_model, "  const     key   3", 0).unwrap();
     }
 
+    #[test]
+    fn test_divider_formatting() {
+        assert_eq!(parse_divider(".ops", 1).unwrap(), Some(ParseMode::Ops));
+        assert_eq!(parse_divider("  .ops\t", 1).unwrap(), Some(ParseMode::Ops));
+        assert_eq!(
+            parse_divider(".ops # main", 1).unwrap(),
+            Some(ParseMode::Ops)
+        );
+        assert_eq!(
+            parse_divider(".strings#text", 1).unwrap(),
+            Some(ParseMode::Strings)
+        );
+        assert_eq!(parse_divider(".jumptable d0 a b # c", 1).unwrap(), None);
+        assert_eq!(
+            parse_divider(".op", 3).unwrap_err().to_string(),
+            "Unknown divider .op at line 3, did you mean .ops?"
+        );
+        assert_eq!(
+            parse_divider(".dtaa", 4).unwrap_err().to_string(),
+            "Unknown divider .dtaa at line 4, did you mean .data?"
+        );
+        assert_eq!(
+            parse_divider(".code", 5).unwrap_err().to_string(),
+            "Unknown divider .code at line 5, must be one of .data .strings .ops"
+        );
+
+        let input = vec![
+            "test",
+            "1",
+            " .strings # text",
+            "greeting=hi",
+            "  .ops  #main",
+            "prts greeting",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let program_model = generate_program_model(input, false, false).unwrap();
+        assert_eq!(program_model.strings.len(), 1);
+        assert_eq!(program_model.ops.len(), 1);
+    }
+
+    #[test]
+    fn test_constant_number_forms() {
+        let mut program_model = ProgramModel::new(String::new(), String::new());
+
+        parse_constant(&mut program_model, "const flag b10000000", 0).unwrap();
+        parse_constant(&mut program_model, "const space ' '", 1).unwrap();
+        parse_constant(&mut program_model, "const top xFF", 2).unwrap();
+        parse_constant(&mut program_model, "const result d3", 3).unwrap();
+        assert!(parse_constant(&mut program_model, "const missing", 4).is_err());
+        assert!(parse_constant(&mut program_model, "const missing #comment", 5).is_err());
+        parse_constant(&mut program_model, "const commented 12 #comment", 6).unwrap();
+        parse_constant(&mut program_model, "const hash '#' #comment", 7).unwrap();
+        assert!(parse_constant(&mut program_model, "const extra 1 2", 9).is_err());
+        assert!(parse_constant(&mut program_model, "const extra 1 2 #comment", 10).is_err());
+        parse_constant(
+            &mut program_model,
+            "const spaced 3 # comment with words",
+            11,
+        )
+        .unwrap();
+        //Names of instructions added later are no longer valid
+        let error = parse_constant(&mut program_model, "const max 15", 8).unwrap_err();
+        assert!(error.to_string().starts_with(
+            "Invalid constant key 'max' on line 8\n'max' conflicts with the MAX instruction"
+        ));
+
+        let content = |key: &str| program_model.constants[key].content.clone();
+        assert_eq!(content("flag"), "128");
+        assert_eq!(content("space"), "32");
+        assert_eq!(content("top"), "255");
+        assert_eq!(content("result"), "d3");
+        assert_eq!(content("commented"), "12");
+        assert_eq!(content("hash"), "35");
+        assert_eq!(content("spaced"), "3");
+        assert!(!program_model.constants.contains_key("extra"));
+    }
+
     mod method_integration {
         use super::*;
 
@@ -319,12 +1215,97 @@ mod test {
             ];
 
             for entry in data {
-                parse_string(&mut program_model, entry.1, entry.2).unwrap();
+                parse_string(&mut program_model, entry.1, entry.2, false).unwrap();
                 let value = program_model.strings.get(entry.0).unwrap();
                 assert_eq!(value, &entry.3, "{}", entry.0);
             }
         }
 
+        #[test]
+        fn test_parse_raw_strings() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            parse_string(&mut program_model, "trimmed=  padded  ", 1, false).unwrap();
+            parse_string(&mut program_model, "raw:kept=  padded  ", 2, false).unwrap();
+            parse_string(&mut program_model, " raw: spaced =  padded", 3, false).unwrap();
+
+            assert_eq!(program_model.strings["trimmed"].content, "padded");
+            assert_eq!(program_model.strings["kept"].content, "  padded  ");
+            assert_eq!(program_model.strings["spaced"].content, "  padded");
+            assert!(parse_string(&mut program_model, "raw:=content", 4, false).is_err());
+        }
+
+        #[test]
+        fn test_parse_control_chars() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            assert!(parse_string(&mut program_model, "tab=a\tb", 1, false).is_err());
+            assert!(parse_string(&mut program_model, "nul=a\0b", 2, false).is_err());
+            parse_string(&mut program_model, r"escaped=a\tb\nc\\d\e", 3, false).unwrap();
+
+            assert_eq!(program_model.strings["escaped"].content, "a\tb\nc\\d\\e");
+        }
+
+        #[test]
+        fn test_comments() {
+            let input = [
+                "test",
+                "1",
+                ".strings",
+                "# full line comment",
+                "greeting=hello # wave",
+                r"tag=\#1 # escaped",
+                "quoted=\"a # b\" # kept in quotes",
+                ".data",
+                "nums=[[1,2]] # two numbers",
+                ".ops",
+                "prts greeting",
+            ]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+            let model = generate_program_model(input, false, false).unwrap();
+
+            assert_eq!(model.strings.len(), 3);
+            assert_eq!(model.strings["greeting"].content, "hello");
+            assert_eq!(model.strings["tag"].content, "#1");
+            assert_eq!(model.strings["quoted"].content, "a # b");
+            assert_eq!(model.data["nums"].content, vec![1, 2, 1, 2]);
+        }
+
+        #[test]
+        fn test_blank_and_comment_lines() {
+            let input = [
+                "test",
+                "1",
+                "",
+                "# header comment",
+                ".strings",
+                "first=one",
+                "",
+                "    # indented comment",
+                "second=two",
+                "",
+                ".data",
+                "# data comment",
+                "",
+                "nums=[[1]]",
+                "# before ops",
+                "",
+                ".ops",
+                "",
+                "# ops comment",
+                "prts first",
+            ]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+            let model = generate_program_model(input, false, false).unwrap();
+
+            assert_eq!(model.strings.len(), 2);
+            assert_eq!(model.data.len(), 1);
+            assert_eq!(model.ops.len(), 1);
+            assert_eq!(model.ops[0].line_num, 20);
+        }
+
         #[test]
         fn test_parse_valid_data() {
             let mut program_model = ProgramModel::new(String::new(), String::new());
@@ -341,14 +1322,159 @@ mod test {
             }
         }
 
+        #[test]
+        fn test_parse_ascii_data() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            parse_data(&mut program_model, "greeting=\"hello\"", 5).unwrap();
+            parse_data(&mut program_model, "spaced = \" a b \"", 6).unwrap();
+
+            let value = program_model.data.get("greeting").unwrap();
+            assert_eq!(value.content, b"hello".to_vec());
+            assert_eq!(value.interpretation, vec![b"hello".to_vec()]);
+            let value = program_model.data.get("spaced").unwrap();
+            assert_eq!(value.content, b" a b ".to_vec());
+
+            assert!(parse_data(&mut program_model, "invalid=\"héllo\"", 7).is_err());
+        }
+
+        #[test]
+        fn test_parse_repeat_data() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            parse_data(&mut program_model, "buf=[0; 64]", 5).unwrap();
+            parse_data(&mut program_model, "fives=[5;3]", 6).unwrap();
+            parse_data(&mut program_model, "chars=[ 'a' ; 2 ]", 7).unwrap();
+            parse_data(&mut program_model, "empty=[xFF; 0]", 8).unwrap();
+
+            assert_eq!(program_model.data.get("buf").unwrap().content, vec![0; 64]);
+            assert_eq!(
+                program_model.data.get("fives").unwrap().content,
+                vec![5, 5, 5]
+            );
+            assert_eq!(
+                program_model.data.get("chars").unwrap().content,
+                vec![97, 97]
+            );
+            assert!(program_model.data.get("empty").unwrap().content.is_empty());
+        }
+
+        #[test]
+        fn test_parse_invalid_repeat_data() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            assert!(parse_data(&mut program_model, "huge=[0; 65536]", 5).is_err());
+            assert!(parse_data(&mut program_model, "huger=[0; 99999999999999999999]", 6).is_err());
+            assert!(parse_data(&mut program_model, "negative=[0; -1]", 7).is_err());
+            assert!(parse_data(&mut program_model, "big_value=[256; 1]", 8).is_err());
+        }
+
+        #[test]
+        fn test_ld_offsets() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            parse_data(&mut program_model, "nums=[[1,2,3],[4]]", 1).unwrap();
+            parse_data(&mut program_model, "text=\"hello\"", 2).unwrap();
+
+            parse_op(&mut program_model, "ld a0 nums 0 2", 3).unwrap();
+            parse_op(&mut program_model, "ld a0 nums 1 2", 4).unwrap();
+            parse_op(&mut program_model, "ld a0 nums 2 0", 5).unwrap();
+            parse_op(&mut program_model, "ld a0 nums 2 d0", 6).unwrap();
+            parse_op(&mut program_model, "ld a0 nums d0 5", 7).unwrap();
+            parse_op(&mut program_model, "ld a0 text 0 4", 8).unwrap();
+            assert_eq!(program_model.ops.len(), 6);
+
+            assert_eq!(
+                parse_op(&mut program_model, "ld a0 nums 3 0", 9)
+                    .unwrap_err()
+                    .to_string(),
+                "Array 3 is out of range on line 9, nums has 2 array(s)"
+            );
+            assert_eq!(
+                parse_op(&mut program_model, "ld a0 nums 2 1", 10)
+                    .unwrap_err()
+                    .to_string(),
+                "Byte 1 is out of range on line 10, array 2 of nums has 1 byte(s)"
+            );
+            assert!(parse_op(&mut program_model, "ld a0 nums 0 3", 11).is_err());
+            assert!(parse_op(&mut program_model, "ld a0 nums 3 d1", 12).is_err());
+            assert_eq!(program_model.ops.len(), 6);
+        }
+
+        #[test]
+        fn test_sizeof() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            parse_string(&mut program_model, "greeting=Hello", 1, false).unwrap();
+            parse_data(&mut program_model, "nums=[[1,2,3],[4]]", 2).unwrap();
+            parse_data(&mut program_model, "big=[0; 300]", 3).unwrap();
+
+            parse_op(&mut program_model, "cpy d0 sizeof(greeting)", 4).unwrap();
+            parse_op(&mut program_model, "cmp d1 sizeof(nums)", 5).unwrap();
+            parse_op(&mut program_model, "cpy d2 (sizeof(greeting)-1)", 6).unwrap();
+            assert_eq!(
+                program_model.ops[0].params,
+                vec![Param::DataReg(REG_D0), Param::Number(5)]
+            );
+            assert_eq!(
+                program_model.ops[1].params,
+                vec![Param::DataReg(REG_D1), Param::Number(7)]
+            );
+            assert_eq!(
+                program_model.ops[2].params,
+                vec![Param::DataReg(REG_D2), Param::Number(4)]
+            );
+            assert_eq!(program_model.strings["greeting"].usage.len(), 2);
+
+            assert_eq!(
+                parse_op(&mut program_model, "cpy d0 sizeof(big)", 7)
+                    .unwrap_err()
+                    .to_string(),
+                "big is 300 bytes, sizeof can only be used with keys up to 255 bytes"
+            );
+            assert_eq!(
+                parse_op(&mut program_model, "cpy d0 sizeof(missing)", 8)
+                    .unwrap_err()
+                    .to_string(),
+                "String or data key missing used in sizeof on line 8 but was never defined"
+            );
+        }
+
+        #[test]
+        fn test_inline_data() {
+            let mut program_model = ProgramModel::new(String::new(), String::new());
+            parse_op(&mut program_model, ".db 1 x2 'c' (2*2)", 1).unwrap();
+            parse_op(&mut program_model, "words: .dw @1000 @x10 # comment", 2).unwrap();
+            assert_eq!(program_model.ops[0].raw, vec![1, 2, 99, 4]);
+            assert_eq!(program_model.ops[1].raw, vec![3, 232, 0, 16]);
+            assert!(program_model.labels["words"].definition.is_some());
+
+            assert_eq!(
+                parse_op(&mut program_model, ".db", 3)
+                    .unwrap_err()
+                    .to_string(),
+                ".db on line 3 has no values"
+            );
+            assert_eq!(
+                parse_op(&mut program_model, ".db 256", 4).unwrap_err().to_string(),
+                "Invalid .db value on line 4: Error parsing number 256: number too large to fit in target type"
+            );
+            assert!(parse_op(&mut program_model, ".dw 10", 5).is_err());
+            assert_eq!(program_model.ops.len(), 2);
+        }
+
         #[test]
         fn test_parse_valid_ops() {
             let mut program_model = ProgramModel::new(String::new(), String::new());
+            parse_string(&mut program_model, "greeting=Hi", 1, false).unwrap();
 
             #[rustfmt::skip]
-                let ops = vec![
+                let ops = [
                 ("add reg val", "add d3 10", 30, OpModel::new(ADD_REG_VAL, vec![Param::DataReg(REG_D3), Param::Number(10)], String::from("add d3 10"), String::from("add d3 10"), 30)),
                 ("cmp reg reg", "cmp d1 acc", 31, OpModel::new(CMP_REG_REG, vec![Param::DataReg(REG_D1), Param::DataReg(REG_ACC)], String::from("cmp d1 acc"), String::from("cmp d1 acc"), 31)),
+                ("tabs", "add\td3\t10", 32, OpModel::new(ADD_REG_VAL, vec![Param::DataReg(REG_D3), Param::Number(10)], String::from("add d3 10"), String::from("add\td3\t10"), 32)),
+                ("tab char", "cmp\td1\t'\t'", 33, OpModel::new(CMP_REG_VAL, vec![Param::DataReg(REG_D1), Param::Number(9)], String::from("cmp d1 '\t'"), String::from("cmp\td1\t'\t'"), 33)),
+                ("lda reg addr", "lda d2 @100", 34, OpModel::new(LDA_REG_ADDR, vec![Param::DataReg(REG_D2), Param::Addr(100)], String::from("lda d2 @100"), String::from("lda d2 @100"), 34)),
+                ("sta addr reg", "sta @x64 d0", 35, OpModel::new(STA_ADDR_REG, vec![Param::Addr(100), Param::DataReg(REG_D0)], String::from("sta @x64 d0"), String::from("sta @x64 d0"), 35)),
+                ("enter val", "enter 4", 36, OpModel::new(ENTER_VAL, vec![Param::Number(4)], String::from("enter 4"), String::from("enter 4"), 36)),
+                ("leave", "leave", 37, OpModel::new(LEAVE, vec![], String::from("leave"), String::from("leave"), 37)),
+                ("djnz areg addr", "djnz a1 @100", 38, OpModel::new(DJNZ_AREG_ADDR, vec![Param::AddrReg(REG_A1), Param::Addr(100)], String::from("djnz a1 @100"), String::from("djnz a1 @100"), 38)),
+                ("ldstr areg reg str", "ldstr a1 d1 greeting", 39, OpModel::new(LDSTR_AREG_REG_STR, vec![Param::AddrReg(REG_A1), Param::DataReg(REG_D1), Param::StrKey(String::from("greeting"))], String::from("ldstr a1 d1 greeting"), String::from("ldstr a1 d1 greeting"), 39)),
             ];
 
             for (idx, entry) in ops.iter().enumerate() {
@@ -409,6 +1535,26 @@ mod test {
             assert_eq!(processed, "ld a0 words 450 xF1");
         }
 
+        #[test]
+        fn test_expressions() {
+            let mut constants = HashMap::new();
+            constants.insert(
+                String::from("base"),
+                ConstantModel::new(String::new(), String::from("@x100"), String::new(), 0),
+            );
+            constants.insert(
+                String::from("a"),
+                ConstantModel::new(String::new(), String::from("10"), String::new(), 0),
+            );
+            let processed = replace_constants(&mut constants, "cpy d0 (a-1)", 0);
+            assert_eq!(processed, "cpy d0 (10-1)");
+            let processed = replace_constants(&mut constants, "lda d0 @(base+xA*a)", 0);
+            assert_eq!(processed, "lda d0 @(@x100+xA*10)");
+            let processed = replace_constants(&mut constants, "cpy d0 ('a'+a)", 0);
+            assert_eq!(processed, "cpy d0 ('a'+10)");
+            assert_eq!(constants["a"].usage.len(), 3);
+        }
+
         #[test]
         fn test_duplicate_param() {
             let mut constants = HashMap::new();
@@ -439,7 +1585,7 @@ mod test {
                 .into_iter()
                 .map(|line| line.to_string())
                 .collect();
-            assert!(generate_program_model(input).is_err());
+            assert!(generate_program_model(input, false, false).is_err());
         }
 
         #[test]
@@ -448,18 +1594,214 @@ mod test {
                 .into_iter()
                 .map(|line| line.to_string())
                 .collect();
-            assert!(generate_program_model(input).is_err());
+            assert!(generate_program_model(input, false, false).is_err());
         }
 
         #[test]
         fn test_no_content() {
-            assert!(generate_program_model(vec![]).is_err());
+            assert!(generate_program_model(vec![], false, false).is_err());
         }
     }
 
     mod edge_cases {
         use super::*;
 
+        #[test]
+        fn test_strict_labels() {
+            let input = || {
+                vec!["test", "1.0", ".ops", "jmp end", "jmp 100", "end: halt"]
+                    .into_iter()
+                    .map(|line| line.to_string())
+                    .collect()
+            };
+
+            let model = generate_program_model(input(), false, false).unwrap();
+            assert_eq!(model.ops[1].params, vec![Param::Label(String::from("100"))]);
+            assert!(model.labels.get("100").unwrap().definition.is_none());
+
+            let error = generate_program_model(input(), false, true)
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("`100` on line 5"), "{}", error);
+            assert!(!error.contains("`end`"), "{}", error);
+        }
+
+        #[test]
+        fn test_duplicate_label() {
+            let input = vec![
+                "test",
+                "1.0",
+                ".ops",
+                "start: inc d0",
+                "jmp start",
+                "start: halt",
+            ]
+            .into_iter()
+            .map(|line| line.to_string())
+            .collect();
+
+            let error = format!(
+                "{:#}",
+                generate_program_model(input, false, false).unwrap_err()
+            );
+            assert!(
+                error.contains("'start' on line 6\nAlready defined as label on line 4"),
+                "{}",
+                error
+            );
+        }
+
+        #[test]
+        fn test_continued_lines() {
+            let input = vec![
+                "test",
+                "1.0",
+                ".data",
+                "table=[[1, 2, 3, \\",
+                "    4, 5, 6]]",
+                ".strings",
+                "long=Hello \\",
+                "world",
+                "path=C:\\\\",
+                ".ops",
+                "prts long",
+                "prts path",
+                "ld a0 table 0 0",
+            ]
+            .into_iter()
+            .map(|line| line.to_string())
+            .collect();
+
+            let model = generate_program_model(input, false, false).unwrap();
+
+            let table = model.data.get("table").unwrap();
+            assert_eq!(table.interpretation, vec![vec![1, 2, 3, 4, 5, 6]]);
+            assert_eq!(table.definition.line_num, 4);
+            let long = model.strings.get("long").unwrap();
+            assert_eq!(long.content, "Hello world");
+            assert_eq!(long.definition.line_num, 7);
+            assert_eq!(model.strings.get("path").unwrap().content, "C:\\");
+            assert_eq!(model.ops[0].line_num, 11);
+            assert_eq!(model.ops[2].line_num, 13);
+        }
+
+        #[test]
+        fn test_continued_line_errors() {
+            let input = vec![
+                "test",
+                "1.0",
+                ".data",
+                "table=[[1, 2, \\",
+                "3, 4]]",
+                "bad=[[1, 2, \\",
+                "300]]",
+                ".ops",
+                "halt",
+            ]
+            .into_iter()
+            .map(|line| line.to_string())
+            .collect();
+
+            let error = format!(
+                "{:#}",
+                generate_program_model(input, false, false).unwrap_err()
+            );
+            assert!(error.contains("Data definition on line 6"), "{}", error);
+        }
+
+        #[test]
+        fn test_conditionals() {
+            let input: Vec<String> = vec![
+                "test",
+                "1.0",
+                ".strings",
+                ".ifdef DEBUG",
+                "dbg=debug build",
+                ".else",
+                "dbg=release build",
+                ".endif",
+                ".ops",
+                "const verbose 1",
+                ".ifdef verbose # set above",
+                "  .ifndef DEBUG",
+                "  inc d0",
+                "  .endif",
+                "inc d1",
+                ".endif",
+                ".ifdef missing",
+                "inc d2",
+                ".endif",
+                "prts dbg",
+            ]
+            .into_iter()
+            .map(|line| line.to_string())
+            .collect();
+
+            let model = generate_program_model(input.clone(), false, false).unwrap();
+            assert_eq!(model.strings.get("dbg").unwrap().content, "release build");
+            assert_eq!(model.strings.get("dbg").unwrap().definition.line_num, 7);
+            assert_eq!(
+                model
+                    .ops
+                    .iter()
+                    .map(|op| op.line_num)
+                    .collect::<Vec<usize>>(),
+                vec![13, 15, 20]
+            );
+
+            let options = AssembleOptions {
+                defines: vec![String::from("DEBUG")],
+                ..AssembleOptions::default()
+            };
+            let model = generate_program_model_with_options(input, &options).unwrap();
+            assert_eq!(model.strings.get("dbg").unwrap().content, "debug build");
+            assert_eq!(
+                model
+                    .ops
+                    .iter()
+                    .map(|op| op.line_num)
+                    .collect::<Vec<usize>>(),
+                vec![15, 20]
+            );
+        }
+
+        #[test]
+        fn test_unbalanced_conditionals() {
+            let error = |lines: &[&str]| {
+                let input = ["test", "1.0", ".ops"]
+                    .iter()
+                    .chain(lines)
+                    .map(|line| line.to_string())
+                    .collect();
+                generate_program_model(input, false, false)
+                    .unwrap_err()
+                    .to_string()
+            };
+            assert_eq!(
+                error(&[".ifdef a", "halt"]),
+                "Conditional block on line 4 has no .endif"
+            );
+            assert_eq!(
+                error(&[".ifdef a", ".ifdef b", ".endif", "halt"]),
+                "Conditional block on line 4 has no .endif"
+            );
+            assert_eq!(
+                error(&["halt", ".endif"]),
+                "Unexpected .endif on line 5, there's no .ifdef or .ifndef before it"
+            );
+            assert_eq!(
+                error(&[".else", "halt"]),
+                "Unexpected .else on line 4, there's no .ifdef or .ifndef before it"
+            );
+            assert_eq!(
+                error(&[".ifdef a", ".else", ".else", ".endif", "halt"]),
+                "Unexpected .else on line 6, the block on line 4 already has one"
+            );
+            assert!(
+                error(&[".ifdef", "halt", ".endif"]).starts_with("Error parsing .ifdef on line 4")
+            );
+        }
+
         #[test]
         fn test_mixed_strings_data() {
             let input = vec![
@@ -470,24 +1812,53 @@ mod test {
                 "dk2=[[4,5]]",
                 ".strings",
                 "sk1=Test",
-                ".data",
-                "dk3=[['a']]",
-                ".strings",
                 "sk2=Another",
             ]
             .into_iter()
             .map(|line| line.to_string())
             .collect();
 
-            let model = generate_program_model(input).unwrap();
+            let model = generate_program_model(input, false, false).unwrap();
 
             assert_eq!(model.strings.len(), 2);
-            assert_eq!(model.data.len(), 3);
+            assert_eq!(model.data.len(), 2);
             assert!(model.strings.contains_key("sk1"));
             assert!(model.strings.contains_key("sk2"));
             assert!(model.data.contains_key("dk1"));
             assert!(model.data.contains_key("dk2"));
-            assert!(model.data.contains_key("dk3"));
+        }
+
+        #[test]
+        fn test_repeated_sections() {
+            let error = |lines: &[&str]| {
+                let input = ["test", "1.0"]
+                    .iter()
+                    .chain(lines)
+                    .map(|line| line.to_string())
+                    .collect();
+                generate_program_model(input, false, false)
+                    .unwrap_err()
+                    .to_string()
+            };
+            assert_eq!(
+                error(&[
+                    ".strings",
+                    "sk1=a",
+                    ".data",
+                    "dk1=[[1]]",
+                    ".strings",
+                    "sk2=b"
+                ]),
+                "Unexpected .strings divider at line 7, there can only be one strings section"
+            );
+            assert_eq!(
+                error(&[".data", "dk1=[[1]]", ".ops", "halt", ".data", "dk2=[[2]]"]),
+                "Unexpected .data divider at line 7, there can only be one data section"
+            );
+            assert_eq!(
+                error(&[".ops", "halt", ".ops", "halt"]),
+                "Unexpected .ops divider at line 5, there can only be one ops section"
+            );
         }
     }
 
@@ -500,8 +1871,8 @@ mod test {
         #[rustfmt::skip]
         fn test_direct_calls() {
             let mut program_model = ProgramModel::new(String::from("Test Program"), String::from("1"));
-            parse_string(&mut program_model, "str_test1=First test string", 4).unwrap();
-            parse_string(&mut program_model, "str_test2=\"  Second test string:  \"", 5).unwrap();
+            parse_string(&mut program_model, "str_test1=First test string", 4, false).unwrap();
+            parse_string(&mut program_model, "str_test2=\"  Second test string:  \"", 5, false).unwrap();
             parse_data(&mut program_model, "dat_numbers=[[4, 8, 15 , 16, 23,42],[ 1, 4 ,9, 16, 25, 36 ] ]", 7).unwrap();
             parse_data(&mut program_model, "dat_chars=[['f', 'o', 'o'] , ['b', 'a', 'r']]", 8).unwrap();
             parse_constant(&mut program_model, "const true 0", 10).unwrap();
@@ -535,7 +1906,7 @@ mod test {
                 "ld a0 dat_numbers 0 0"
             ].into_iter().map(|line| line.to_string()).collect();
             
-            let program_model = generate_program_model(input).unwrap();
+            let program_model = generate_program_model(input, false, false).unwrap();
             
             validate_integration_program_model(program_model);
         }
@@ -608,7 +1979,7 @@ mod test {
                 ))
             );
             assert_eq!(
-                program_model.ops.get(0).unwrap(),
+                program_model.ops.first().unwrap(),
                 &OpModel::new(
                     CPY_REG_VAL,
                     vec![Param::DataReg(REG_D1), Param::Number(10)],
@@ -915,7 +2286,7 @@ mod test {
         #[test]
         #[rustfmt::skip]
         fn test_valid_no_params() {
-            for (op, opcode) in [("ret", RET), ("prtln", PRTLN), ("debug", DEBUG), ("halt", HALT), ("nop", NOP), ("time", TIME)] {
+            for (op, opcode) in [("ret", RET), ("prtln", PRTLN), ("debug", DEBUG), ("halt", HALT), ("nop", NOP), ("time", TIME), ("dup", DUP), ("swap", SWAP)] {
                 let mut program_model = ProgramModel::new(String::new(), String::new());
                 parse_op(&mut program_model, op, 0).unwrap();
 
@@ -936,14 +2307,24 @@ mod test {
         #[rustfmt::skip]
         fn test_missing_string() {
             let mut program_model = ProgramModel::new(String::new(), String::new());
-            assert!(parse_op(&mut program_model, "prts not_set", 0).is_err());
+            parse_op(&mut program_model, "prts not_set", 0).unwrap();
+            parse_op(&mut program_model, "prtln other", 1).unwrap();
+            assert_eq!(
+                program_model.validate().unwrap_err().to_string(),
+                "String key not_set used on prts not_set, line 0 but was never defined\nString key other used on prtln other, line 1 but was never defined"
+            );
         }
 
         #[test]
         #[rustfmt::skip]
         fn test_missing_data() {
             let mut program_model = ProgramModel::new(String::new(), String::new());
-            assert!(parse_op(&mut program_model, "ld a0 not_set 0 0", 0).is_err());
+            parse_op(&mut program_model, "ld a0 not_set 0 0", 0).unwrap();
+            parse_op(&mut program_model, "jmp nowhere", 1).unwrap();
+            assert_eq!(
+                program_model.validate().unwrap_err().to_string(),
+                "Label nowhere is never defined\nData key not_set used on ld a0 not_set 0 0, line 0 but was never defined"
+            );
         }
     }
 }
@@ -1,19 +1,51 @@
-use crate::assembler::{FORMAT_ERROR, KEY_NAME_ERROR};
-use crate::constants::code::{DIVDERS, KEYWORDS, MNEMONICS, REGISTERS};
+use crate::assembler::lint::{Warning, WarningKind};
+use crate::assembler::{Diagnostic, FORMAT_ERROR, KEY_NAME_ERROR};
+use crate::constants::code::{DIVDERS, KEYWORDS, MNEMONICS, NOP, REGISTERS};
+use crate::language::is_mnemonic;
 use crate::language::parser::params::Param;
 use anyhow::{Error, Result};
-use serde::Serialize;
-use std::collections::HashMap;
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Serialize)]
 pub struct ProgramModel {
     pub name: String,
     pub version: String,
+    #[serde(serialize_with = "sorted_map")]
     pub strings: HashMap<String, StringModel>,
+    #[serde(serialize_with = "sorted_map")]
     pub data: HashMap<String, DataModel>,
+    #[serde(serialize_with = "sorted_map")]
     pub constants: HashMap<String, ConstantModel>,
     pub ops: Vec<OpModel>,
+    #[serde(serialize_with = "sorted_map")]
     pub labels: HashMap<String, LabelModel>,
+    ///Label set with `.entry`, if None the `main` label is used if defined, otherwise the first op
+    pub entry: Option<String>,
+    ///Data keys of `.jumptable` address blocks, mapped to the labels whose addresses are written into them
+    #[serde(
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "sorted_map"
+    )]
+    pub jump_tables: HashMap<String, Vec<String>>,
+    ///String and data keys used by ops but not defined, these are reported by `validate`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub undefined_keys: Vec<UndefinedKey>,
+    ///If true the tape includes a relocation table, set from `AssembleOptions`
+    #[serde(skip)]
+    pub relocatable: bool,
+}
+
+///Maps are written in key order so the build file is the same for the same program
+fn sorted_map<S: Serializer, V: Serialize>(
+    map: &HashMap<String, V>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    map.iter()
+        .collect::<BTreeMap<&String, &V>>()
+        .serialize(serializer)
 }
 
 #[derive(Debug, PartialEq, Eq, Serialize)]
@@ -51,6 +83,14 @@ pub struct StringModel {
     pub usage: Vec<Usage>,
 }
 
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct UndefinedKey {
+    ///`String key` or `Data key`
+    pub key_type: &'static str,
+    pub key: String,
+    pub usage: Usage,
+}
+
 #[derive(Debug, PartialEq, Eq, Serialize)]
 pub struct DataModel {
     pub key: String,
@@ -67,6 +107,9 @@ pub struct OpModel {
     pub after_processing: String,
     pub original_line: String,
     pub line_num: usize,
+    ///Bytes from `.db`/`.dw` that are written instead of an op, empty for ops
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub raw: Vec<u8>,
 }
 
 impl ProgramModel {
@@ -79,6 +122,10 @@ impl ProgramModel {
             constants: HashMap::new(),
             ops: vec![],
             labels: HashMap::new(),
+            entry: None,
+            jump_tables: HashMap::new(),
+            undefined_keys: vec![],
+            relocatable: false,
         }
     }
 
@@ -99,6 +146,114 @@ impl ProgramModel {
     }
 }
 
+/// Builds a `ProgramModel` in code, each call is treated as a new source line
+pub struct ProgramModelBuilder {
+    model: ProgramModel,
+    line_num: usize,
+}
+
+impl ProgramModel {
+    pub fn builder(name: &str, version: &str) -> ProgramModelBuilder {
+        ProgramModelBuilder {
+            model: ProgramModel::new(name.to_owned(), version.to_owned()),
+            line_num: 0,
+        }
+    }
+}
+
+impl ProgramModelBuilder {
+    fn next_line(&mut self) -> usize {
+        self.line_num += 1;
+        self.line_num
+    }
+
+    pub fn string(mut self, key: &str, content: &str) -> Self {
+        let line_num = self.next_line();
+        self.model.strings.insert(
+            key.to_owned(),
+            StringModel::new(
+                key.to_owned(),
+                content.to_owned(),
+                format!("{}={}", key, content),
+                line_num,
+            ),
+        );
+        self
+    }
+
+    /// Adds data made of `arrays`, stored with the same array count and length prefixes as the data section
+    pub fn data(mut self, key: &str, arrays: Vec<Vec<u8>>) -> Self {
+        let line_num = self.next_line();
+        let mut content = vec![arrays.len() as u8];
+        content.extend(arrays.iter().map(|array| array.len() as u8));
+        for array in &arrays {
+            content.extend_from_slice(array);
+        }
+        self.model.data.insert(
+            key.to_owned(),
+            DataModel::new(
+                key.to_owned(),
+                content,
+                arrays.clone(),
+                format!("{}={:?}", key, arrays),
+                line_num,
+            ),
+        );
+        self
+    }
+
+    /// Defines a label pointing at the next op
+    pub fn label(mut self, key: &str) -> Self {
+        let line_num = self.next_line();
+        let def = Some(Definition::new(format!("{}:", key), line_num));
+        self.model
+            .labels
+            .entry(key.to_owned())
+            .or_insert_with(|| LabelModel::new(key.to_owned(), None, vec![]))
+            .definition = def;
+        self
+    }
+
+    pub fn op(mut self, opcode: u8, params: Vec<Param>) -> Self {
+        let line_num = self.next_line();
+        for param in &params {
+            let usage = Usage::new(String::new(), line_num);
+            match param {
+                Param::Label(key) | Param::LabelAddr(key) => self
+                    .model
+                    .labels
+                    .entry(key.to_owned())
+                    .or_insert_with(|| LabelModel::new(key.to_owned(), None, vec![]))
+                    .usage
+                    .push(usage),
+                Param::StrKey(key) => {
+                    if let Some(model) = self.model.strings.get_mut(key) {
+                        model.usage.push(usage)
+                    }
+                }
+                Param::DataKey(key) => {
+                    if let Some(model) = self.model.data.get_mut(key) {
+                        model.usage.push(usage)
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.model.ops.push(OpModel::new(
+            opcode,
+            params,
+            String::new(),
+            String::new(),
+            line_num,
+        ));
+        self
+    }
+
+    pub fn build(self) -> ProgramModel {
+        self.model
+    }
+}
+
 impl ProgramModel {
     pub fn validate_key(
         &self,
@@ -107,6 +262,17 @@ impl ProgramModel {
         line_num: usize,
         is_label: bool,
     ) -> Result<()> {
+        if is_mnemonic(key) {
+            return Err(Error::msg(format!(
+                "Invalid {} '{}' on line {}\n'{}' conflicts with the {} instruction\n\n{}",
+                key_type,
+                key,
+                line_num,
+                key,
+                key.to_uppercase(),
+                *KEY_NAME_ERROR
+            )));
+        }
         let lowercased = key.to_lowercase();
         let lowercased = lowercased.as_str();
         if REGISTERS.contains(&lowercased)
@@ -116,10 +282,7 @@ impl ProgramModel {
         {
             return Err(Error::msg(format!(
                 "Invalid {} '{}' on line {}\n\n{}",
-                key_type,
-                key,
-                line_num,
-                KEY_NAME_ERROR.to_string()
+                key_type, key, line_num, *KEY_NAME_ERROR
             )));
         }
 
@@ -129,7 +292,7 @@ impl ProgramModel {
         let starts_with_letter = lowercased
             .chars()
             .next()
-            .map_or(false, |chr| chr.is_ascii_alphabetic());
+            .is_some_and(|chr| chr.is_ascii_alphabetic());
         if has_invalid_chars || !starts_with_letter {
             return Err(Error::msg(format!(
                 "Invalid {} '{}' on line {}\n{}s can only include ASCII letters, numbers and '_' and must start with a letter",
@@ -176,41 +339,107 @@ impl ProgramModel {
         Ok(())
     }
 
-    pub fn validate(&self) -> Result<()> {
-        if self.ops.is_empty() {
-            return Err(Error::msg(format!("No ops found\n\n{}", FORMAT_ERROR)));
-        }
+    ///Labels, strings and data that are defined but never used, sorted by line
+    pub fn unused_symbols(&self) -> Vec<Warning> {
+        let labels = self.labels.iter().filter_map(|(key, model)| {
+            model
+                .definition
+                .as_ref()
+                .filter(|_| model.usage.is_empty())
+                .map(|def| (WarningKind::UnusedLabel, "Label", key, def.line_num))
+        });
+        let strings = self
+            .strings
+            .iter()
+            .filter(|(_, model)| model.usage.is_empty())
+            .map(|(key, model)| {
+                (
+                    WarningKind::UnusedString,
+                    "String",
+                    key,
+                    model.definition.line_num,
+                )
+            });
+        let data = self
+            .data
+            .iter()
+            .filter(|(_, model)| model.usage.is_empty())
+            .map(|(key, model)| {
+                (
+                    WarningKind::UnusedData,
+                    "Data",
+                    key,
+                    model.definition.line_num,
+                )
+            });
+        let mut warnings = labels
+            .chain(strings)
+            .chain(data)
+            .map(|(kind, name, key, line_num)| {
+                Warning::new(kind, format!("{} {} is never used", name, key), line_num)
+            })
+            .collect::<Vec<Warning>>();
+        warnings
+            .sort_by(|lhs, rhs| (lhs.line_num, &lhs.message).cmp(&(rhs.line_num, &rhs.message)));
+        warnings
+    }
 
-        let mut error = String::new();
-        let mut warning = String::new();
+    ///Labels that are used but never defined, sorted by name
+    pub fn undefined_labels(&self) -> Vec<&LabelModel> {
+        let mut labels = self
+            .labels
+            .values()
+            .filter(|label| label.definition.is_none())
+            .collect::<Vec<&LabelModel>>();
+        labels.sort_unstable_by(|lhs, rhs| lhs.key.cmp(&rhs.key));
+        labels
+    }
 
-        for label in &self.labels {
-            if label.1.definition.is_none() {
-                error.push_str(&format!("Label {} is never defined\n", label.0));
-            }
-            if label.1.usage.is_empty() {
-                warning.push_str(&format!("Label {} is never used\n", label.0));
-            }
+    ///Every error that stops the model being generated, e.g. no ops or undefined labels and keys
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        if self.ops.is_empty() {
+            diagnostics.push(Diagnostic::error(String::from("No ops found"), None));
         }
-
-        for string in &self.strings {
-            if string.1.usage.is_empty() {
-                warning.push_str(&format!("String {} is never used\n", string.0));
-            }
+        for label in self.undefined_labels() {
+            diagnostics.push(Diagnostic::error(
+                format!("Label {} is never defined", label.key),
+                label.usage.first().map(|usage| usage.line_num),
+            ));
         }
-
-        for data in &self.data {
-            if data.1.usage.is_empty() {
-                warning.push_str(&format!("Data {} is never used\n", data.0));
-            }
+        for undefined in &self.undefined_keys {
+            diagnostics.push(Diagnostic::error(
+                undefined.to_string(),
+                Some(undefined.usage.line_num),
+            ));
         }
+        diagnostics
+    }
 
-        println!("{}", warning);
-        if error.is_empty() {
-            Ok(())
-        } else {
-            Err(Error::msg(error))
+    pub fn validate(&self) -> Result<()> {
+        let diagnostics = self.diagnostics();
+        if diagnostics.is_empty() {
+            return Ok(());
         }
+        let mut message = diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect::<Vec<String>>()
+            .join("\n");
+        if self.ops.is_empty() {
+            message.push_str(&format!("\n\n{}", FORMAT_ERROR));
+        }
+        Err(Error::msg(message))
+    }
+}
+
+impl Display for UndefinedKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} used on {}, line {} but was never defined",
+            self.key_type, self.key, self.usage.original_line, self.usage.line_num
+        )
     }
 }
 
@@ -244,6 +473,16 @@ impl Usage {
     }
 }
 
+impl UndefinedKey {
+    pub fn new(key_type: &'static str, key: String, usage: Usage) -> Self {
+        UndefinedKey {
+            key_type,
+            key,
+            usage,
+        }
+    }
+}
+
 impl Definition {
     pub fn new(original_line: String, line_num: usize) -> Self {
         Definition {
@@ -296,8 +535,30 @@ impl OpModel {
             after_processing: after_constants,
             original_line,
             line_num,
+            raw: vec![],
+        }
+    }
+
+    ///Inline data from `.db`/`.dw`, the opcode is NOP so it has no effect on linting or optimising
+    pub fn new_raw(
+        bytes: Vec<u8>,
+        after_constants: String,
+        original_line: String,
+        line_num: usize,
+    ) -> Self {
+        OpModel {
+            opcode: NOP,
+            params: vec![],
+            after_processing: after_constants,
+            original_line,
+            line_num,
+            raw: bytes,
         }
     }
+
+    pub fn is_inline_data(&self) -> bool {
+        !self.raw.is_empty()
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -310,13 +571,16 @@ pub enum AddressReplacement {
 
 impl OpModel {
     pub fn to_bytes(&self) -> (Vec<u8>, AddressReplacement) {
+        if self.is_inline_data() {
+            return (self.raw.clone(), AddressReplacement::None);
+        }
         let mut output = vec![self.opcode];
         let mut replacement = AddressReplacement::None;
         for param in &self.params {
             match param {
                 Param::DataReg(val) | Param::AddrReg(val) | Param::Number(val) => output.push(*val),
                 Param::Addr(addr) => output.extend_from_slice(&addr.to_be_bytes()),
-                Param::Label(lbl) => {
+                Param::Label(lbl) | Param::LabelAddr(lbl) => {
                     output.push(0);
                     output.push(0);
                     replacement = AddressReplacement::Label(lbl.to_owned());
@@ -372,7 +636,9 @@ mod test {
             "ld", "memr", "memw", "memp", "halt", "nop", "fopen", "filer", "filew", "fchk",
             "fseek", "fskip", "call", "ret", "swp", "prt", "prtc", "prtln", "prts", "prtd", "push",
             "pop", "arg", "ipoll", "rchr", "rstr", "and", "or", "xor", "not", "rand", "seed",
-            "time", "debug",
+            "time", "debug", "dup", "swap", "clr", "neg", "abs", "input", "rdtick", "ldf", "stf",
+            "assert", "rdsp", "rol", "ror", "lda", "sta", "min", "max", "smin", "smax", "enter",
+            "leave", "djnz", "ldstr",
         ];
 
         let program_model = ProgramModel::new(String::from("TEST"), String::new());
@@ -399,6 +665,28 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_mnemonic_label() {
+        let program_model = ProgramModel::new(String::from("TEST"), String::new());
+
+        for key in ["add", "ADD", "Halt"] {
+            let error = program_model
+                .validate_key("label", key, 5, true)
+                .unwrap_err()
+                .to_string();
+            assert!(
+                error.contains(&format!(
+                    "'{}' conflicts with the {} instruction",
+                    key,
+                    key.to_uppercase()
+                )),
+                "{}",
+                error
+            );
+            assert!(error.contains("line 5"), "{}", error);
+        }
+    }
+
     #[test]
     fn test_invalid_keys() {
         let invalid_keys = vec!["1", " ", "[]]", "ddf fsdfs", "rfegr-gfg", "ds,."];
@@ -755,6 +1043,6 @@ mod test {
             12,
         ));
 
-        assert_eq!(serde_json::to_string(&model).unwrap(), String::from("{\"name\":\"prog name\",\"version\":\"ver1\",\"strings\":{\"s_key\":{\"key\":\"s_key\",\"content\":\"example string\",\"definition\":{\"original_line\":\"s_key=example string\",\"line_num\":3},\"usage\":[{\"original_line\":\"prts s_key\",\"line_num\":10}]}},\"data\":{\"d_key\":{\"key\":\"d_key\",\"content\":[1,1,1],\"interpretation\":[[1]],\"definition\":{\"original_line\":\"d_key=[[1]]\",\"line_num\":6},\"usage\":[{\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11}]}},\"constants\":{\"foo\":{\"key\":\"foo\",\"content\":\"a1\",\"definition\":{\"original_line\":\"const foo a1\",\"line_num\":8},\"usage\":[{\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11}]}},\"ops\":[{\"opcode\":147,\"params\":[{\"StrKey\":\"s_key\"}],\"after_processing\":\"prts s_key\",\"original_line\":\"prts s_key\",\"line_num\":10},{\"opcode\":71,\"params\":[{\"AddrReg\":33},{\"DataKey\":\"d_key\"},{\"Number\":0},{\"Number\":0}],\"after_processing\":\"ld a1 d_key 0 0\",\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11},{\"opcode\":32,\"params\":[{\"Label\":\"lbl\"}],\"after_processing\":\"jmp lbl\",\"original_line\":\"jmp lbl\",\"line_num\":12}],\"labels\":{\"lbl\":{\"key\":\"lbl\",\"definition\":{\"original_line\":\"lbl:\",\"line_num\":7},\"usage\":[{\"original_line\":\"jmp lbl\",\"line_num\":12}]}}}"));
+        assert_eq!(serde_json::to_string(&model).unwrap(), String::from("{\"name\":\"prog name\",\"version\":\"ver1\",\"strings\":{\"s_key\":{\"key\":\"s_key\",\"content\":\"example string\",\"definition\":{\"original_line\":\"s_key=example string\",\"line_num\":3},\"usage\":[{\"original_line\":\"prts s_key\",\"line_num\":10}]}},\"data\":{\"d_key\":{\"key\":\"d_key\",\"content\":[1,1,1],\"interpretation\":[[1]],\"definition\":{\"original_line\":\"d_key=[[1]]\",\"line_num\":6},\"usage\":[{\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11}]}},\"constants\":{\"foo\":{\"key\":\"foo\",\"content\":\"a1\",\"definition\":{\"original_line\":\"const foo a1\",\"line_num\":8},\"usage\":[{\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11}]}},\"ops\":[{\"opcode\":147,\"params\":[{\"StrKey\":\"s_key\"}],\"after_processing\":\"prts s_key\",\"original_line\":\"prts s_key\",\"line_num\":10},{\"opcode\":71,\"params\":[{\"AddrReg\":33},{\"DataKey\":\"d_key\"},{\"Number\":0},{\"Number\":0}],\"after_processing\":\"ld a1 d_key 0 0\",\"original_line\":\"ld foo d_key 0 0\",\"line_num\":11},{\"opcode\":32,\"params\":[{\"Label\":\"lbl\"}],\"after_processing\":\"jmp lbl\",\"original_line\":\"jmp lbl\",\"line_num\":12}],\"labels\":{\"lbl\":{\"key\":\"lbl\",\"definition\":{\"original_line\":\"lbl:\",\"line_num\":7},\"usage\":[{\"original_line\":\"jmp lbl\",\"line_num\":12}]}},\"entry\":null}"));
     }
 }
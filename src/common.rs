@@ -4,7 +4,7 @@ use crossterm::execute;
 use crossterm::terminal::{Clear, ClearType};
 use std::fs::File;
 use std::io::stdout;
-use std::io::{BufRead, BufReader, Read};
+use std::io::Read;
 use std::path::PathBuf;
 
 ///Read file as bytes
@@ -31,11 +31,56 @@ pub fn read_lines(path_str: &str) -> Result<Vec<String>> {
         return Err(Error::msg(format!("File does not exist: {}", path_str)));
     }
 
-    let file = File::open(path).context(path_str.to_string())?;
-    Ok(BufReader::new(file)
+    let content = std::fs::read_to_string(path).context(path_str.to_string())?;
+    Ok(split_lines(&content))
+}
+
+///Split text into lines, a leading BOM is removed and CRLF, CR and LF are all treated as line endings
+pub fn split_lines(content: &str) -> Vec<String> {
+    content
+        .trim_start_matches('\u{feff}')
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
         .lines()
-        .map(|line| line.unwrap())
-        .collect())
+        .map(|line| line.to_owned())
+        .collect()
+}
+
+///CRC-32 (IEEE), bytes can be added in any number of chunks
+pub struct Crc32 {
+    value: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32 { value: !0 }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.value ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (self.value & 1).wrapping_neg();
+                self.value = (self.value >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.value
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32::new()
+    }
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
 }
 
 ///Move the terminal up one line and to first column
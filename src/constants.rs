@@ -19,6 +19,20 @@ pub mod hardware {
 
     pub const REG_A0: u8 = 0x20;
     pub const REG_A1: u8 = 0x21;
+
+    ///Upper case name of `reg`, e.g. `D0`, or `?` if it's not a register
+    pub fn reg_name(reg: u8) -> &'static str {
+        match reg {
+            REG_D0 => "D0",
+            REG_D1 => "D1",
+            REG_D2 => "D2",
+            REG_D3 => "D3",
+            REG_ACC => "ACC",
+            REG_A0 => "A0",
+            REG_A1 => "A1",
+            _ => "?",
+        }
+    }
 }
 
 pub mod compare {
@@ -31,17 +45,28 @@ pub mod system {
     pub const TAPE_HEADER_1: u8 = 0xFD;
     pub const TAPE_HEADER_2: u8 = 0xA0;
 
-    pub const PRG_VERSION: u8 = 1;
+    ///Tapes have the entry point (byte address, big endian) after the program version
+    ///and end with a CRC32 (big endian) of all the bytes before it
+    pub const PRG_VERSION: u8 = 3;
+    ///Same as `PRG_VERSION` with a relocation table section between the strings and data
+    pub const PRG_VERSION_RELOCATABLE: u8 = 4;
+    ///Tapes with a checksum but no entry point, still accepted by the loaders and start at byte 0
+    pub const PRG_VERSION_NO_ENTRY: u8 = 2;
+    ///Tapes without a checksum, still accepted by the loaders
+    pub const PRG_VERSION_NO_CHECKSUM: u8 = 1;
 }
 
 pub mod code {
     pub const DIVDERS: [&str; 3] = [".data", ".strings", ".ops"];
-    pub const KEYWORDS: [&str; 1] = ["const"];
-    pub const MNEMONICS: [&str; 47] = [
+    pub const KEYWORDS: [&str; 2] = ["const", "alias"];
+    pub const MNEMONICS: [&str; 70] = [
         "add", "sub", "inc", "dec", "cmp", "cpy", "swp", "jmp", "je", "jg", "jl", "jne", "over",
         "nover", "memr", "memw", "memp", "ld", "call", "ret", "push", "pop", "arg", "prt", "prtc",
         "prtln", "prtd", "prts", "and", "or", "xor", "not", "fchk", "fopen", "fseek", "fskip",
         "filew", "filer", "ipoll", "rchr", "rstr", "time", "rand", "seed", "debug", "halt", "nop",
+        "dup", "swap", "ldf", "stf", "clr", "neg", "abs", "input", "rdtick", "assert", "rdsp",
+        "rol", "ror", "lda", "sta", "min", "max", "smin", "smax", "enter", "leave", "djnz",
+        "ldstr",
     ];
     pub const REGISTERS: [&str; 7] = ["d0", "d1", "d2", "d3", "acc", "a0", "a1"];
 
@@ -53,6 +78,12 @@ pub mod code {
     pub const DEC_REG: u8 = 0x06;
     pub const ADD_REG_AREG: u8 = 0x07;
     pub const SUB_REG_AREG: u8 = 0x08;
+    pub const NEG_REG: u8 = 0x09;
+    pub const ABS_REG: u8 = 0x0A;
+    pub const MIN_REG_REG: u8 = 0x0B;
+    pub const MAX_REG_REG: u8 = 0x0C;
+    pub const SMIN_REG_REG: u8 = 0x0D;
+    pub const SMAX_REG_REG: u8 = 0x0E;
 
     pub const CPY_REG_REG: u8 = 0x10;
     pub const CPY_REG_VAL: u8 = 0x11;
@@ -63,6 +94,7 @@ pub mod code {
     pub const SWP_REG_REG: u8 = 0x16;
     pub const SWP_AREG_AREG: u8 = 0x17;
     pub const CPY_REG_AREG: u8 = 0x18;
+    pub const CLR_REG: u8 = 0x19;
 
     pub const JMP_ADDR: u8 = 0x20;
     pub const JMP_AREG: u8 = 0x21;
@@ -78,6 +110,7 @@ pub mod code {
     pub const OVER_AREG: u8 = 0x2B;
     pub const NOVER_ADDR: u8 = 0x2C;
     pub const NOVER_AREG: u8 = 0x2D;
+    pub const DJNZ_AREG_ADDR: u8 = 0x2E;
 
     pub const CMP_REG_REG: u8 = 0x30;
     pub const CMP_REG_VAL: u8 = 0x31;
@@ -95,6 +128,9 @@ pub mod code {
     pub const LD_AREG_DATA_REG_VAL: u8 = 0x45;
     pub const LD_AREG_DATA_VAL_REG: u8 = 0x46;
     pub const LD_AREG_DATA_VAL_VAL: u8 = 0x47;
+    pub const LDA_REG_ADDR: u8 = 0x48;
+    pub const STA_ADDR_REG: u8 = 0x49;
+    pub const LDSTR_AREG_REG_STR: u8 = 0x4A;
 
     pub const CALL_ADDR: u8 = 0x70;
     pub const CALL_AREG: u8 = 0x71;
@@ -104,6 +140,13 @@ pub mod code {
     pub const POP_REG: u8 = 0x75;
     pub const ARG_REG_VAL: u8 = 0x76;
     pub const ARG_REG_REG: u8 = 0x77;
+    pub const DUP: u8 = 0x78;
+    pub const SWAP: u8 = 0x79;
+    pub const LDF_REG_VAL: u8 = 0x7A;
+    pub const STF_REG_VAL: u8 = 0x7B;
+    pub const RDSP_AREG: u8 = 0x7C;
+    pub const ENTER_VAL: u8 = 0x7D;
+    pub const LEAVE: u8 = 0x7E;
 
     pub const PRT_REG: u8 = 0x90;
     pub const PRT_VAL: u8 = 0x91;
@@ -116,6 +159,7 @@ pub mod code {
     pub const PRTD_AREG: u8 = 0x98;
     pub const PRT_AREG: u8 = 0x99;
     pub const PRTC_AREG: u8 = 0x9A;
+    pub const PRTLN_STR: u8 = 0x9B;
 
     pub const AND_REG_REG: u8 = 0xA0;
     pub const AND_REG_VAL: u8 = 0xA1;
@@ -127,6 +171,8 @@ pub mod code {
     pub const AND_REG_AREG: u8 = 0xA7;
     pub const OR_REG_AREG: u8 = 0xA8;
     pub const XOR_REG_AREG: u8 = 0xA9;
+    pub const ROL_REG_VAL: u8 = 0xAA;
+    pub const ROR_REG_VAL: u8 = 0xAB;
 
     pub const FOPEN_REG: u8 = 0xC0;
     pub const FILER_REG_ADDR: u8 = 0xC1;
@@ -161,7 +207,11 @@ pub mod code {
     pub const RAND_REG: u8 = 0xE5;
     pub const TIME: u8 = 0xE6;
     pub const SEED_REG: u8 = 0xE7;
+    pub const INPUT_REG: u8 = 0xE8;
+    pub const RDTICK_REG: u8 = 0xE9;
+    pub const RDTICK_AREG: u8 = 0xEA;
 
+    pub const ASSERT_REG_VAL: u8 = 0xFC;
     pub const DEBUG: u8 = 0xFD;
     pub const NOP: u8 = 0xFE;
     pub const HALT: u8 = 0xFF;
@@ -169,38 +219,66 @@ pub mod code {
 
 pub fn get_byte_count(opcode: u8) -> usize {
     match opcode {
-        PRTLN | RET | NOP | HALT | TIME | DEBUG => 1,
+        PRTLN | RET | NOP | HALT | TIME | DEBUG | DUP | SWAP | LEAVE => 1,
         INC_REG | DEC_REG | JMP_AREG | JE_AREG | JNE_AREG | JL_AREG | JG_AREG | OVER_AREG
         | NOVER_AREG | MEMR_AREG | MEMW_AREG | CALL_AREG | PUSH_REG | PUSH_VAL | POP_REG
-        | PRT_REG | PRT_VAL | PRTC_REG | PRTC_VAL | RCHR_REG | RAND_REG | NOT_REG | SEED_REG
-        | FSEEK_REG | FSEEK_VAL | FOPEN_REG | FOPEN_VAL | PRTD_AREG | MEMP_AREG | PRT_AREG
-        | PRTC_AREG | RSTR_AREG | IPOLL_AREG => 2,
+        | PRT_REG | PRT_VAL | PRTC_REG | PRTC_VAL | RCHR_REG | INPUT_REG | RDTICK_REG
+        | RDTICK_AREG | RAND_REG | NOT_REG | SEED_REG | CLR_REG | NEG_REG | ABS_REG | FSEEK_REG
+        | FSEEK_VAL | FOPEN_REG | FOPEN_VAL | PRTD_AREG | MEMP_AREG | PRT_AREG | PRTC_AREG
+        | RSTR_AREG | IPOLL_AREG | RDSP_AREG | ENTER_VAL => 2,
         ADD_REG_REG | ADD_REG_VAL | SUB_REG_REG | SUB_REG_VAL | CPY_REG_REG | CPY_REG_VAL
         | SWP_AREG_AREG | SWP_REG_REG | JMP_ADDR | JE_ADDR | JNE_ADDR | JL_ADDR | JG_ADDR
         | OVER_ADDR | CMP_AREG_AREG | CPY_AREG_AREG | NOVER_ADDR | CMP_REG_REG | CMP_REG_VAL
-        | MEMR_ADDR | MEMW_ADDR | CALL_ADDR | PRTS_STR | FSKIP_REG_REG | FSKIP_REG_VAL
-        | FSKIP_VAL_REG | FSKIP_VAL_VAL | ARG_REG_VAL | ARG_REG_REG | MEMP_ADDR
-        | FILER_REG_AREG | FILER_VAL_AREG | FILEW_REG_AREG | FILEW_VAL_AREG | IPOLL_ADDR
-        | RSTR_ADDR | AND_REG_VAL | AND_REG_REG | AND_REG_AREG | OR_REG_AREG | XOR_REG_AREG
-        | OR_REG_VAL | OR_REG_REG | XOR_REG_REG | XOR_REG_VAL | FCHK_REG_AREG | FCHK_VAL_AREG
-        | ADD_REG_AREG | SUB_REG_AREG | CPY_REG_AREG | CMP_REG_AREG | FILEW_REG_REG
-        | FILEW_REG_VAL | FILEW_VAL_REG | FILEW_VAL_VAL => 3,
+        | MEMR_ADDR | MEMW_ADDR | CALL_ADDR | PRTS_STR | PRTLN_STR | FSKIP_REG_REG
+        | FSKIP_REG_VAL | FSKIP_VAL_REG | FSKIP_VAL_VAL | ARG_REG_VAL | ARG_REG_REG
+        | LDF_REG_VAL | STF_REG_VAL | MEMP_ADDR | FILER_REG_AREG | FILER_VAL_AREG
+        | FILEW_REG_AREG | FILEW_VAL_AREG | IPOLL_ADDR | RSTR_ADDR | AND_REG_VAL | AND_REG_REG
+        | AND_REG_AREG | OR_REG_AREG | XOR_REG_AREG | OR_REG_VAL | OR_REG_REG | XOR_REG_REG
+        | XOR_REG_VAL | FCHK_REG_AREG | FCHK_VAL_AREG | ADD_REG_AREG | SUB_REG_AREG
+        | CPY_REG_AREG | CMP_REG_AREG | FILEW_REG_REG | FILEW_REG_VAL | FILEW_VAL_REG
+        | FILEW_VAL_VAL | ASSERT_REG_VAL | ROL_REG_VAL | ROR_REG_VAL | MIN_REG_REG
+        | MAX_REG_REG | SMIN_REG_REG | SMAX_REG_REG => 3,
         CMP_AREG_ADDR | CPY_AREG_ADDR | CMP_AREG_REG_REG | CMP_REG_REG_AREG | CPY_REG_REG_AREG
         | FCHK_REG_ADDR | FCHK_VAL_ADDR | CPY_AREG_REG_REG | FILER_REG_ADDR | FILEW_VAL_ADDR
-        | FILER_VAL_ADDR | FILEW_REG_ADDR => 4,
+        | FILER_VAL_ADDR | FILEW_REG_ADDR | LDA_REG_ADDR | STA_ADDR_REG | DJNZ_AREG_ADDR => 4,
+        LDSTR_AREG_REG_STR => 5,
         LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL | LD_AREG_DATA_VAL_REG
         | LD_AREG_DATA_VAL_VAL => 6,
         _ => panic!("Unknown opcode: {:02X}", opcode),
     }
 }
 
+///Addresses in ops, e.g. labels, strings and data
+pub type Address = u16;
+///Number of bytes used by an `Address` in an op
+pub const ADDR_BYTES: usize = 2;
+
+///Encodes `addr` as it's written in ops (big endian)
+pub fn addr_to_bytes(addr: Address) -> [u8; ADDR_BYTES] {
+    addr.to_be_bytes()
+}
+
+///Decodes an address written by `addr_to_bytes`, only the first `ADDR_BYTES` of `bytes` are read
+pub fn addr_from_bytes(bytes: &[u8]) -> Address {
+    let mut addr = [0; ADDR_BYTES];
+    addr.copy_from_slice(&bytes[..ADDR_BYTES]);
+    Address::from_be_bytes(addr)
+}
+
+///Writes `addr` at `offset` in `bytes`
+pub fn patch_addr(bytes: &mut [u8], offset: usize, addr: Address) {
+    bytes[offset..offset + ADDR_BYTES].copy_from_slice(&addr_to_bytes(addr));
+}
+
 pub fn get_addr_byte_offset(opcode: u8) -> Option<usize> {
     match opcode {
         JMP_ADDR | JE_ADDR | JL_ADDR | JNE_ADDR | RSTR_ADDR | JG_ADDR | OVER_ADDR | NOVER_ADDR
-        | CALL_ADDR | MEMR_ADDR | MEMW_ADDR | IPOLL_ADDR | PRTS_STR | MEMP_ADDR => Some(1),
+        | CALL_ADDR | MEMR_ADDR | MEMW_ADDR | IPOLL_ADDR | PRTS_STR | PRTLN_STR | MEMP_ADDR
+        | STA_ADDR_REG => Some(1),
         FCHK_VAL_ADDR | FCHK_REG_ADDR | LD_AREG_DATA_VAL_VAL | CPY_AREG_ADDR | CMP_AREG_ADDR
         | FILEW_VAL_ADDR | FILER_VAL_ADDR | FILER_REG_ADDR | LD_AREG_DATA_VAL_REG
-        | LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL => Some(2),
+        | LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL | LDA_REG_ADDR | DJNZ_AREG_ADDR => Some(2),
+        LDSTR_AREG_REG_STR => Some(3),
         _ => None,
     }
 }
@@ -222,6 +300,7 @@ pub fn is_jump_op(opcode: u8) -> bool {
             | OVER_AREG
             | NOVER_ADDR
             | NOVER_AREG
+            | DJNZ_AREG_ADDR
             | CALL_ADDR
             | CALL_AREG
             | RET
@@ -236,13 +315,15 @@ pub fn is_jump_op(opcode: u8) -> bool {
 
 #[rustfmt::skip]
 #[allow(dead_code)]
-pub const ALL_OPS: [u8; 108] = [
+pub const ALL_OPS: [u8; 133] = [
     ADD_REG_REG, ADD_REG_VAL, ADD_REG_AREG,
     SUB_REG_REG, SUB_REG_VAL, SUB_REG_AREG,
     AND_REG_REG, AND_REG_VAL, AND_REG_AREG,
     OR_REG_REG, OR_REG_VAL, OR_REG_AREG,
     XOR_REG_REG, XOR_REG_VAL, XOR_REG_AREG,
     INC_REG, DEC_REG,
+    NEG_REG, ABS_REG,
+    MIN_REG_REG, MAX_REG_REG, SMIN_REG_REG, SMAX_REG_REG,
     CPY_REG_REG,
     CPY_REG_VAL,
     CPY_AREG_AREG,
@@ -250,6 +331,7 @@ pub const ALL_OPS: [u8; 108] = [
     CPY_AREG_REG_REG,
     CPY_REG_REG_AREG,
     CPY_REG_AREG,
+    CLR_REG,
     CMP_AREG_AREG,
     CMP_AREG_ADDR,
     CMP_REG_REG_AREG,
@@ -264,12 +346,16 @@ pub const ALL_OPS: [u8; 108] = [
     JG_ADDR, JG_AREG,
     OVER_ADDR, OVER_AREG,
     NOVER_ADDR, NOVER_AREG,
+    DJNZ_AREG_ADDR,
     MEMR_ADDR, MEMR_AREG,
     MEMW_ADDR, MEMW_AREG,
+    LDA_REG_ADDR, STA_ADDR_REG,
+    LDSTR_AREG_REG_STR,
     CALL_ADDR, CALL_AREG,
     RET,
     PUSH_REG, PUSH_VAL,
     POP_REG,
+    DUP, SWAP,
     PRT_REG, PRT_VAL, PRT_AREG,
     PRTLN,
     PRTC_REG, PRTC_VAL, PRTC_AREG,
@@ -285,22 +371,29 @@ pub const ALL_OPS: [u8; 108] = [
     NOP,
     HALT,
     ARG_REG_VAL, ARG_REG_REG,
+    LDF_REG_VAL, STF_REG_VAL,
+    RDSP_AREG,
+    ENTER_VAL, LEAVE,
     IPOLL_ADDR, IPOLL_AREG,
     RCHR_REG,
+    INPUT_REG,
+    RDTICK_REG, RDTICK_AREG,
     RSTR_AREG, RSTR_ADDR,
     SWP_REG_REG, SWP_AREG_AREG,
     TIME,
     RAND_REG,
     SEED_REG,
     NOT_REG,
+    ROL_REG_VAL, ROR_REG_VAL,
     LD_AREG_DATA_REG_REG,
     LD_AREG_DATA_REG_VAL,
     LD_AREG_DATA_VAL_REG,
     LD_AREG_DATA_VAL_VAL,
     MEMP_ADDR, MEMP_AREG,
     PRTD_AREG,
-    PRTS_STR,
+    PRTS_STR, PRTLN_STR,
     DEBUG,
+    ASSERT_REG_VAL,
     FILEW_REG_REG, FILEW_REG_VAL, FILEW_VAL_REG, FILEW_VAL_VAL
 ];
 
@@ -320,6 +413,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_reg_names_match_keywords() {
+        use hardware::*;
+        let regs = [REG_D0, REG_D1, REG_D2, REG_D3, REG_ACC, REG_A0, REG_A1];
+        for (reg, keyword) in regs.iter().zip(REGISTERS.iter()) {
+            assert_eq!(reg_name(*reg).to_ascii_lowercase(), *keyword);
+        }
+        assert_eq!(reg_name(0), "?");
+    }
+
     #[test]
     fn check_ops_have_byte_counts() {
         for op in ALL_OPS.iter() {
@@ -327,6 +430,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn check_addr_round_trip() {
+        for addr in [0, 1, 0xFF, 0x100, 0x1234, Address::MAX] {
+            assert_eq!(addr_from_bytes(&addr_to_bytes(addr)), addr);
+        }
+        assert_eq!(addr_to_bytes(0x1234), [0x12, 0x34]);
+        assert_eq!(addr_from_bytes(&[0xAB, 0xCD, 0xEF]), 0xABCD);
+
+        let mut bytes = vec![JMP_ADDR, 0, 0, HALT];
+        patch_addr(&mut bytes, 1, 0x0102);
+        assert_eq!(bytes, vec![JMP_ADDR, 1, 2, HALT]);
+    }
+
     #[test]
     fn check_jump_ops_have_addr_offsets() {
         for op in ALL_OPS.iter() {
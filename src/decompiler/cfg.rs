@@ -0,0 +1,221 @@
+use crate::constants::code::*;
+use crate::constants::{get_byte_count, is_jump_op};
+use crate::decompiler::DecodedOp;
+use crate::language::parser::params::Param;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BasicBlock {
+    ///Byte address of the first op in the block
+    pub start: usize,
+    ///Byte address after the last op in the block
+    pub end: usize,
+    pub ops: Vec<DecodedOp>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EdgeKind {
+    Fallthrough,
+    Jump,
+    Call,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum EdgeTarget {
+    ///Index of the block in `Cfg.blocks`
+    Block(usize),
+    ///Target is computed at runtime (e.g. `JMP a0`) or isn't the start of an op
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Edge {
+    ///Index of the block in `Cfg.blocks`
+    pub from: usize,
+    pub to: EdgeTarget,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+impl Cfg {
+    pub fn block_starting_at(&self, byte_addr: usize) -> Option<usize> {
+        self.blocks
+            .iter()
+            .position(|block| block.start == byte_addr)
+    }
+
+    pub fn edges_from(&self, block: usize) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.from == block)
+            .collect()
+    }
+}
+
+///Splits ops into basic blocks at jump targets and after any jump, call, return or halt
+pub fn build_cfg(ops: &[DecodedOp]) -> Cfg {
+    let mut leaders = BTreeSet::new();
+    if let Some(op) = ops.first() {
+        leaders.insert(op.byte_addr);
+    }
+    for (idx, op) in ops.iter().enumerate() {
+        if let Some(addr) = jump_target(op) {
+            leaders.insert(addr);
+        }
+        if ends_block(op.opcode) {
+            if let Some(next) = ops.get(idx + 1) {
+                leaders.insert(next.byte_addr);
+            }
+        }
+    }
+
+    let mut blocks: Vec<BasicBlock> = vec![];
+    for op in ops {
+        match blocks.last_mut() {
+            Some(block) if !leaders.contains(&op.byte_addr) => {
+                block.end = op.byte_addr + op_len(op);
+                block.ops.push(op.clone());
+            }
+            _ => blocks.push(BasicBlock {
+                start: op.byte_addr,
+                end: op.byte_addr + op_len(op),
+                ops: vec![op.clone()],
+            }),
+        }
+    }
+
+    let mut cfg = Cfg {
+        blocks,
+        edges: vec![],
+    };
+
+    for idx in 0..cfg.blocks.len() {
+        let last = cfg.blocks[idx].ops.last().unwrap().clone();
+        let next = if idx + 1 < cfg.blocks.len() {
+            Some(idx + 1)
+        } else {
+            None
+        };
+        let target = match last.params.last() {
+            Some(Param::Addr(addr)) => cfg
+                .block_starting_at(*addr as usize)
+                .map(EdgeTarget::Block)
+                .unwrap_or(EdgeTarget::Unknown),
+            _ => EdgeTarget::Unknown,
+        };
+        let mut edges = vec![];
+        match last.opcode {
+            HALT | RET => {}
+            JMP_ADDR | JMP_AREG => edges.push((target, EdgeKind::Jump)),
+            CALL_ADDR | CALL_AREG => edges.push((target, EdgeKind::Call)),
+            opcode if is_jump_op(opcode) => edges.push((target, EdgeKind::Jump)),
+            _ => {}
+        }
+        if !matches!(last.opcode, HALT | RET | JMP_ADDR | JMP_AREG) {
+            if let Some(next) = next {
+                edges.push((EdgeTarget::Block(next), EdgeKind::Fallthrough));
+            }
+        }
+        for (to, kind) in edges {
+            cfg.edges.push(Edge {
+                from: idx,
+                to,
+                kind,
+            });
+        }
+    }
+
+    cfg
+}
+
+fn ends_block(opcode: u8) -> bool {
+    opcode == HALT || is_jump_op(opcode)
+}
+
+fn jump_target(op: &DecodedOp) -> Option<usize> {
+    if is_jump_op(op.opcode) {
+        if let Some(Param::Addr(addr)) = op.params.last() {
+            return Some(*addr as usize);
+        }
+    }
+    None
+}
+
+fn op_len(op: &DecodedOp) -> usize {
+    get_byte_count(op.opcode)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::constants::hardware::*;
+    use crate::decompiler::decode_ops;
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_branch_and_loop() {
+        let bytes = vec![
+            CPY_REG_VAL, REG_D0, 0,     //0
+            INC_REG, REG_D0,            //3 loop
+            CMP_REG_VAL, REG_D0, 10,    //5
+            JNE_ADDR, 0, 3,             //8 jne loop
+            CMP_REG_VAL, REG_D1, 1,     //11
+            JE_ADDR, 0, 20,             //14 je end
+            PRT_REG, REG_D0,            //17
+            NOP,                        //19
+            HALT,                       //20 end
+        ];
+        let cfg = build_cfg(&decode_ops(&bytes).unwrap());
+
+        assert_eq!(
+            cfg.blocks.iter().map(|block| (block.start, block.end)).collect::<Vec<(usize, usize)>>(),
+            vec![(0, 3), (3, 11), (11, 17), (17, 20), (20, 21)]
+        );
+        assert_eq!(cfg.edges.len(), 6);
+        assert_eq!(cfg.edges_from(0), vec![&Edge { from: 0, to: EdgeTarget::Block(1), kind: EdgeKind::Fallthrough }]);
+        assert_eq!(cfg.edges_from(1), vec![
+            &Edge { from: 1, to: EdgeTarget::Block(1), kind: EdgeKind::Jump },
+            &Edge { from: 1, to: EdgeTarget::Block(2), kind: EdgeKind::Fallthrough },
+        ]);
+        assert_eq!(cfg.edges_from(2), vec![
+            &Edge { from: 2, to: EdgeTarget::Block(4), kind: EdgeKind::Jump },
+            &Edge { from: 2, to: EdgeTarget::Block(3), kind: EdgeKind::Fallthrough },
+        ]);
+        assert_eq!(cfg.edges_from(3), vec![&Edge { from: 3, to: EdgeTarget::Block(4), kind: EdgeKind::Fallthrough }]);
+        assert!(cfg.edges_from(4).is_empty());
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_calls_and_computed_jumps() {
+        let bytes = vec![
+            CALL_ADDR, 0, 6,    //0
+            CALL_AREG, REG_A0,  //3
+            HALT,               //5
+            JMP_AREG, REG_A1,   //6
+        ];
+        let cfg = build_cfg(&decode_ops(&bytes).unwrap());
+
+        assert_eq!(
+            cfg.blocks.iter().map(|block| (block.start, block.end)).collect::<Vec<(usize, usize)>>(),
+            vec![(0, 3), (3, 5), (5, 6), (6, 8)]
+        );
+        assert_eq!(cfg.edges, vec![
+            Edge { from: 0, to: EdgeTarget::Block(3), kind: EdgeKind::Call },
+            Edge { from: 0, to: EdgeTarget::Block(1), kind: EdgeKind::Fallthrough },
+            Edge { from: 1, to: EdgeTarget::Unknown, kind: EdgeKind::Call },
+            Edge { from: 1, to: EdgeTarget::Block(2), kind: EdgeKind::Fallthrough },
+            Edge { from: 3, to: EdgeTarget::Unknown, kind: EdgeKind::Jump },
+        ]);
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(build_cfg(&[]), Cfg::default());
+    }
+}
@@ -1,9 +1,15 @@
+use crate::assembler::debug_model::DebugModel;
 use crate::constants::code::*;
 use crate::constants::hardware::*;
-use crate::constants::{get_addr_byte_offset, get_byte_count};
+use crate::constants::{get_addr_byte_offset, get_byte_count, ALL_OPS};
+use crate::language::parser::params::Param;
+use crate::language::{decode_params, mnemonic};
 use crate::tape_reader::read_tape;
-use anyhow::Result;
+use anyhow::{Error, Result};
 use std::collections::HashSet;
+use std::fs::read_to_string;
+
+pub mod cfg;
 
 pub struct Decoded {
     pub bytes: Vec<u8>,
@@ -28,14 +34,209 @@ impl Decoded {
     }
 }
 
-pub fn start(path: &str) -> Result<()> {
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DecodedOp {
+    pub byte_addr: usize,
+    pub opcode: u8,
+    pub params: Vec<Param>,
+}
+
+impl DecodedOp {
+    pub fn new(byte_addr: usize, opcode: u8, params: Vec<Param>) -> Self {
+        DecodedOp {
+            byte_addr,
+            opcode,
+            params,
+        }
+    }
+}
+
+///How numbers are written by `disassemble`, all can be parsed by the assembler
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum NumberBase {
+    ///e.g. `255`
+    #[default]
+    Decimal,
+    ///e.g. `xFF`
+    Hex,
+    ///e.g. `b11111111`
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct DisasmOptions {
+    pub number_base: NumberBase,
+}
+
+impl NumberBase {
+    fn format(&self, value: u8) -> String {
+        match self {
+            NumberBase::Decimal => format!("{}", value),
+            NumberBase::Hex => format!("x{:02X}", value),
+            NumberBase::Binary => format!("b{:08b}", value),
+        }
+    }
+}
+
+/// Decodes every instruction in `ops_bytes` without executing them
+/// Addresses, labels, string keys and data keys are all returned as `Param::Addr`
+pub fn decode_ops(ops_bytes: &[u8]) -> Result<Vec<DecodedOp>> {
+    let mut output = vec![];
+    let mut pc = 0;
+    while pc < ops_bytes.len() {
+        let opcode = ops_bytes[pc];
+        if !ALL_OPS.contains(&opcode) {
+            return Err(Error::msg(format!(
+                "Unknown opcode {:02X} at byte {}",
+                opcode, pc
+            )));
+        }
+        let count = get_byte_count(opcode);
+        if pc + count > ops_bytes.len() {
+            return Err(Error::msg(format!(
+                "Truncated instruction {:02X} at byte {}, needs {} bytes but only {} remain",
+                opcode,
+                pc,
+                count,
+                ops_bytes.len() - pc
+            )));
+        }
+        let params = decode_params(opcode, &ops_bytes[pc + 1..pc + count]).ok_or_else(|| {
+            Error::msg(format!(
+                "Unable to decode instruction {:02X} at byte {}",
+                opcode, pc
+            ))
+        })?;
+        output.push(DecodedOp::new(pc, opcode, params));
+        pc += count;
+    }
+    Ok(output)
+}
+
+enum Disassembled {
+    Op(DecodedOp),
+    ///Byte address and bytes of `.db`/`.dw`
+    Data(usize, Vec<u8>),
+}
+
+///Same as `decode_ops` but bytes marked as data in `debug` are returned as they are
+fn decode_ops_and_data(ops_bytes: &[u8], debug: Option<&DebugModel>) -> Result<Vec<Disassembled>> {
+    let mut output = vec![];
+    let mut start = 0;
+    let data_ops = debug
+        .map(|debug| debug.ops.iter().filter(|op| op.is_data).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for data in data_ops {
+        let data_start = data.byte_addr as usize;
+        let data_end = data_start + data.bytes.len();
+        if data_start < start || data_end > ops_bytes.len() {
+            return Err(Error::msg(format!(
+                "Data at byte {} from debug info doesn't match the ops",
+                data_start
+            )));
+        }
+        output.extend(decode_segment(&ops_bytes[start..data_start], start)?);
+        output.push(Disassembled::Data(
+            data_start,
+            ops_bytes[data_start..data_end].to_vec(),
+        ));
+        start = data_end;
+    }
+    output.extend(decode_segment(&ops_bytes[start..], start)?);
+    Ok(output)
+}
+
+fn decode_segment(bytes: &[u8], offset: usize) -> Result<Vec<Disassembled>> {
+    decode_ops(bytes)
+        .map_err(|err| Error::msg(format!("{} (from byte {})", err, offset)))
+        .map(|ops| {
+            ops.into_iter()
+                .map(|op| {
+                    Disassembled::Op(DecodedOp::new(op.byte_addr + offset, op.opcode, op.params))
+                })
+                .collect()
+        })
+}
+
+/// Converts `ops_bytes` back into BASM, one line per op (labels are on their own line)
+/// If `debug` is provided then addresses are replaced by the string, data or label name at that
+/// address, and inline data is written as `.db`, otherwise addresses are written as `@addr`
+pub fn disassemble(
+    ops_bytes: &[u8],
+    debug: Option<&DebugModel>,
+    options: DisasmOptions,
+) -> Result<Vec<String>> {
+    let mut lines = vec![];
+    for item in decode_ops_and_data(ops_bytes, debug)? {
+        let byte_addr = match &item {
+            Disassembled::Op(op) => op.byte_addr,
+            Disassembled::Data(byte_addr, _) => *byte_addr,
+        };
+        if let Some(debug) = debug {
+            for label in debug.labels_for_byte(byte_addr as u16) {
+                lines.push(format!("{}:", label));
+            }
+        }
+        let op = match item {
+            Disassembled::Op(op) => op,
+            Disassembled::Data(_, bytes) => {
+                let values = bytes
+                    .iter()
+                    .map(|byte| options.number_base.format(*byte))
+                    .collect::<Vec<String>>();
+                lines.push(format!(".db {}", values.join(" ")));
+                continue;
+            }
+        };
+        let mut parts = vec![mnemonic(op.opcode).unwrap_or("???").to_ascii_lowercase()];
+        for (idx, param) in op.params.iter().enumerate() {
+            parts.push(match param {
+                Param::Number(value) => options.number_base.format(*value),
+                Param::Addr(addr) => debug
+                    .and_then(|debug| match op.opcode {
+                        PRTS_STR | PRTLN_STR | LDSTR_AREG_REG_STR => {
+                            debug.string_key_for_addr(*addr)
+                        }
+                        LD_AREG_DATA_REG_REG | LD_AREG_DATA_REG_VAL | LD_AREG_DATA_VAL_REG
+                        | LD_AREG_DATA_VAL_VAL
+                            if idx == 1 =>
+                        {
+                            debug.data_key_for_addr(*addr)
+                        }
+                        _ => debug.labels_for_byte(*addr).first().copied(),
+                    })
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| format!("@{}", addr)),
+                _ => param.to_string(),
+            });
+        }
+        lines.push(parts.join(" "));
+    }
+    Ok(lines)
+}
+
+pub fn start(path: &str, debug_path: Option<&str>) -> Result<()> {
     println!("Decompiling tape at {}", path);
 
     let mut tape = read_tape(path)?;
 
+    if let Some(debug_path) = debug_path {
+        let debug_info_text = read_to_string(debug_path)?;
+        let debug_info: DebugModel = serde_json::from_str(&debug_info_text)?;
+        println!(
+            "\n\nProgram\nName: {}\nVersion: {}\nEntry: {}",
+            tape.name, tape.version, tape.entry
+        );
+        println!("\n\nOps:");
+        for line in disassemble(&tape.ops, Some(&debug_info), DisasmOptions::default())? {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
     println!(
-        "\n\nProgram\nName: {}\nVersion: {}",
-        tape.name, tape.version
+        "\n\nProgram\nName: {}\nVersion: {}\nEntry: {}",
+        tape.name, tape.version, tape.entry
     );
     let (strings, strings_unused) = collect_strings(&tape.ops, &tape.strings);
     let (data, data_unused) = collect_data(&tape.ops, &tape.data);
@@ -123,8 +324,9 @@ pub fn collect_strings(ops: &[u8], data: &[u8]) -> (Vec<String>, usize) {
     let mut op_idx = 0;
     let mut addresses = HashSet::new();
     while op_idx < ops.len() {
-        if ops[op_idx] == PRTS_STR {
-            let addr = u16::from_be_bytes([ops[op_idx + 1], ops[op_idx + 2]]);
+        if matches!(ops[op_idx], PRTS_STR | PRTLN_STR | LDSTR_AREG_REG_STR) {
+            let offset = op_idx + get_addr_byte_offset(ops[op_idx]).unwrap();
+            let addr = u16::from_be_bytes([ops[offset], ops[offset + 1]]);
             addresses.insert(addr);
         }
         op_idx += get_byte_count(ops[op_idx]);
@@ -178,6 +380,7 @@ pub fn decode(
         CPY_REG_REG | CPY_AREG_AREG | CPY_REG_AREG => {
             ("CPY", vec![decode_reg(op[1]), decode_reg(op[2])])
         }
+        CLR_REG => ("CLR", vec![decode_reg(op[1])]),
         CPY_AREG_ADDR => ("CPY", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
         CPY_REG_REG_AREG | CPY_AREG_REG_REG => (
             "CPY",
@@ -226,7 +429,18 @@ pub fn decode(
         MEMR_AREG => ("MEMR", vec![decode_reg(op[1])]),
         MEMW_ADDR => ("MEMW", vec![decode_addr(op[1], op[2])]),
         MEMW_AREG => ("MEMW", vec![decode_reg(op[1])]),
+        LDA_REG_ADDR => ("LDA", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
+        STA_ADDR_REG => ("STA", vec![decode_addr(op[1], op[2]), decode_reg(op[3])]),
         PRTS_STR => ("PRTS", vec![decode_string(op[1], op[2], strings)]),
+        PRTLN_STR => ("PRTLN", vec![decode_string(op[1], op[2], strings)]),
+        LDSTR_AREG_REG_STR => (
+            "LDSTR",
+            vec![
+                decode_reg(op[1]),
+                decode_reg(op[2]),
+                decode_string(op[3], op[4], strings),
+            ],
+        ),
         JMP_ADDR => ("JMP", vec![decode_addr(op[1], op[2])]),
         JE_ADDR => ("JE", vec![decode_addr(op[1], op[2])]),
         JNE_ADDR => ("JNE", vec![decode_addr(op[1], op[2])]),
@@ -249,6 +463,13 @@ pub fn decode(
         POP_REG => ("POP", vec![decode_reg(op[1])]),
         PUSH_REG => ("PUSH", vec![decode_reg(op[1])]),
         PUSH_VAL => ("PUSH", vec![decode_num(op[1])]),
+        DUP => ("DUP", vec![]),
+        SWAP => ("SWAP", vec![]),
+        RDSP_AREG => ("RDSP", vec![decode_reg(op[1])]),
+        LDF_REG_VAL => ("LDF", vec![decode_reg(op[1]), decode_num(op[2])]),
+        STF_REG_VAL => ("STF", vec![decode_reg(op[1]), decode_num(op[2])]),
+        ENTER_VAL => ("ENTER", vec![decode_num(op[1])]),
+        LEAVE => ("LEAVE", vec![]),
         SWP_REG_REG | SWP_AREG_AREG => ("SWP", vec![decode_reg(op[1]), decode_reg(op[2])]),
         ARG_REG_VAL => ("ARG", vec![decode_reg(op[1]), decode_num(op[2])]),
         ARG_REG_REG => ("ARG", vec![decode_reg(op[1]), decode_reg(op[2])]),
@@ -256,6 +477,7 @@ pub fn decode(
         FCHK_REG_ADDR => ("FCHK", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
         FCHK_VAL_AREG => ("FCHK", vec![decode_num(op[1]), decode_reg(op[2])]),
         FCHK_VAL_ADDR => ("FCHK", vec![decode_num(op[1]), decode_addr(op[2], op[3])]),
+        DJNZ_AREG_ADDR => ("DJNZ", vec![decode_reg(op[1]), decode_addr(op[2], op[3])]),
         IPOLL_AREG => ("IPOLL", vec![decode_reg(op[1])]),
         IPOLL_ADDR => ("IPOLL", vec![decode_addr(op[1], op[2])]),
         RSTR_AREG => ("RSTR", vec![decode_reg(op[1])]),
@@ -263,6 +485,8 @@ pub fn decode(
         MEMP_AREG => ("PSTR", vec![decode_reg(op[1])]),
         MEMP_ADDR => ("PSTR", vec![decode_addr(op[1], op[2])]),
         RCHR_REG => ("RCHR", vec![decode_reg(op[1])]),
+        INPUT_REG => ("INPUT", vec![decode_reg(op[1])]),
+        RDTICK_REG | RDTICK_AREG => ("RDTICK", vec![decode_reg(op[1])]),
         AND_REG_REG => ("AND", vec![decode_reg(op[1]), decode_reg(op[2])]),
         AND_REG_VAL => ("AND", vec![decode_reg(op[1]), decode_num(op[2])]),
         AND_REG_AREG => ("AND", vec![decode_reg(op[1]), decode_reg(op[2])]),
@@ -274,7 +498,16 @@ pub fn decode(
         XOR_REG_AREG => ("XOR", vec![decode_reg(op[1]), decode_reg(op[2])]),
         TIME => ("TIME", vec![]),
         DEBUG => ("DEBUG", vec![]),
+        ASSERT_REG_VAL => ("ASSERT", vec![decode_reg(op[1]), decode_num(op[2])]),
         NOT_REG => ("NOT", vec![decode_reg(op[1])]),
+        ROL_REG_VAL => ("ROL", vec![decode_reg(op[1]), decode_num(op[2])]),
+        ROR_REG_VAL => ("ROR", vec![decode_reg(op[1]), decode_num(op[2])]),
+        NEG_REG => ("NEG", vec![decode_reg(op[1])]),
+        ABS_REG => ("ABS", vec![decode_reg(op[1])]),
+        MIN_REG_REG => ("MIN", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        MAX_REG_REG => ("MAX", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        SMIN_REG_REG => ("SMIN", vec![decode_reg(op[1]), decode_reg(op[2])]),
+        SMAX_REG_REG => ("SMAX", vec![decode_reg(op[1]), decode_reg(op[2])]),
         SEED_REG => ("SEED", vec![decode_reg(op[1])]),
         RAND_REG => ("RAND", vec![decode_reg(op[1])]),
         PRTD_AREG => ("PRTD", vec![decode_reg(op[1])]),
@@ -344,22 +577,14 @@ fn decode_num(value: u8) -> String {
 }
 
 fn decode_reg(reg: u8) -> String {
-    match reg {
-        REG_D0 => String::from("D0"),
-        REG_D1 => String::from("D1"),
-        REG_D2 => String::from("D2"),
-        REG_D3 => String::from("D3"),
-        REG_ACC => String::from("ACC"),
-        REG_A0 => String::from("A0"),
-        REG_A1 => String::from("A1"),
-        _ => String::from("?"),
-    }
+    reg_name(reg).to_string()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::constants::ALL_OPS;
+    use crate::assembler::assemble_str;
+    use crate::tape_reader::parse_tape;
 
     #[test]
     fn check_decoding_all() {
@@ -373,4 +598,90 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn check_decoding_ops_all() {
+        for op in ALL_OPS.iter() {
+            let mut bytes = vec![*op];
+            bytes.resize(get_byte_count(*op), 0);
+            let decoded = decode_ops(&bytes).unwrap();
+            assert_eq!(decoded.len(), 1, "Missing decoded: {:02X}", op);
+        }
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_decode_ops() {
+        let bytes = vec![
+            INC_REG, REG_D0,
+            CPY_REG_REG, REG_D1, REG_D0,
+            ADD_REG_REG, REG_D0, REG_D1,
+            INC_REG, REG_A1,
+            JMP_ADDR, 0, 3,
+            LD_AREG_DATA_REG_VAL, REG_A0, 1, 2, REG_D2, 10,
+        ];
+
+        assert_eq!(decode_ops(&bytes).unwrap(), vec![
+            DecodedOp::new(0, INC_REG, vec![Param::DataReg(REG_D0)]),
+            DecodedOp::new(2, CPY_REG_REG, vec![Param::DataReg(REG_D1), Param::DataReg(REG_D0)]),
+            DecodedOp::new(5, ADD_REG_REG, vec![Param::DataReg(REG_D0), Param::DataReg(REG_D1)]),
+            DecodedOp::new(8, INC_REG, vec![Param::AddrReg(REG_A1)]),
+            DecodedOp::new(10, JMP_ADDR, vec![Param::Addr(3)]),
+            DecodedOp::new(13, LD_AREG_DATA_REG_VAL, vec![Param::AddrReg(REG_A0), Param::Addr(258), Param::DataReg(REG_D2), Param::Number(10)]),
+        ]);
+        assert!(decode_ops(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let src = "Simple\n1\n.strings\nabc=Hello\n.data\nnums=[[1,2]]\n.ops\nstart:\nprts abc\nld a0 nums 0 1\njmp start\n";
+        let (bytes, debug) = assemble_str(src, false).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+
+        assert_eq!(
+            disassemble(&tape.ops, Some(&debug), DisasmOptions::default()).unwrap(),
+            vec!["start:", "prts abc", "ld a0 nums 0 1", "jmp start"]
+        );
+        assert_eq!(
+            disassemble(&tape.ops, None, DisasmOptions::default()).unwrap(),
+            vec!["prts @0", "ld a0 @0 0 1", "jmp @0"]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_inline_data() {
+        let src = "Simple\n1\n.ops\njmp end\ntable: .db 1 2 xFF\nend: cpy a0 &table\n.dw @1000";
+        let (bytes, debug) = assemble_str(src, false).unwrap();
+        let tape = parse_tape(bytes).unwrap();
+
+        assert_eq!(
+            disassemble(&tape.ops, Some(&debug), DisasmOptions::default()).unwrap(),
+            vec![
+                "jmp end",
+                "table:",
+                ".db 1 2 255",
+                "end:",
+                "cpy a0 table",
+                ".db 3 232"
+            ]
+        );
+        assert!(disassemble(&tape.ops, None, DisasmOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_disassemble_number_base() {
+        let ops = [CPY_REG_VAL, REG_D0, 255];
+        let lines = |number_base| disassemble(&ops, None, DisasmOptions { number_base }).unwrap();
+
+        assert_eq!(lines(NumberBase::Decimal), vec!["cpy d0 255"]);
+        assert_eq!(lines(NumberBase::Hex), vec!["cpy d0 xFF"]);
+        assert_eq!(lines(NumberBase::Binary), vec!["cpy d0 b11111111"]);
+    }
+
+    #[test]
+    fn test_decode_ops_invalid() {
+        assert!(decode_ops(&[INC_REG, REG_D0, CPY_REG_REG, REG_D1]).is_err());
+        assert!(decode_ops(&[JMP_ADDR, 0]).is_err());
+        assert!(decode_ops(&[0x00]).is_err());
+    }
 }
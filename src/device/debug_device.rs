@@ -5,6 +5,7 @@ use crate::device::comm::Output;
 use crate::device::internals::{Device, RunResult};
 use crate::device::util::{convert_and_fit, fit_in_lines};
 use crate::device::Dump;
+use crate::tape_reader::Tape;
 use anyhow::Result;
 use crossterm::cursor::{Hide, MoveToColumn, MoveToPreviousLine, Show};
 use crossterm::event::{Event, KeyCode, KeyModifiers};
@@ -83,15 +84,18 @@ pub fn shutdown_terminal() {
 }
 
 impl DebugDevice {
+    ///`input` is read by INPUT, stdin can't be used as it's needed for the debugger controls
     pub fn new(
-        ops: Vec<u8>,
-        strings: Vec<u8>,
-        data: Vec<u8>,
+        tape: Tape,
         debug_info: DebugModel,
         data_files: Vec<String>,
+        input: Vec<u8>,
     ) -> Self {
+        let mut device = Device::from_tape(tape, data_files);
+        device.attach_debug(&debug_info);
+        device.set_input_source(input);
         DebugDevice {
-            device: Device::new(ops, strings, data, data_files),
+            device,
             debug: debug_info,
             last_run_result: RunResult::Pause,
             ui_memory: None,
@@ -202,6 +206,8 @@ impl DebugDevice {
                         self.redraw = true;
                     }
                 }
+                //Never returned as the input source is always set
+                RunResult::InputRequested => {}
             }
         }
     }
@@ -288,13 +294,11 @@ impl DebugDevice {
                                     self.redraw = true;
                                     return Ok(Some(Input::Text(line.clone())));
                                 }
-                                KeyCode::Backspace => {
-                                    if !line.is_empty() {
-                                        let mut new_line = line.clone();
-                                        new_line.truncate(line.len() - 1);
-                                        self.state = DebuggerState::WaitingForString(new_line);
-                                        self.redraw = true;
-                                    }
+                                KeyCode::Backspace if !line.is_empty() => {
+                                    let mut new_line = line.clone();
+                                    new_line.truncate(line.len() - 1);
+                                    self.state = DebuggerState::WaitingForString(new_line);
+                                    self.redraw = true;
                                 }
                                 _ => {}
                             }
@@ -303,7 +307,7 @@ impl DebugDevice {
                         DebuggerState::WaitingForBreakpointLineToSet(line) => {
                             match key.code {
                                 KeyCode::Char(chr) => {
-                                    if ('0'..='9').contains(&chr) {
+                                    if chr.is_ascii_digit() {
                                         let mut num = line.clone();
                                         num.push(chr);
                                         self.state =
@@ -325,14 +329,12 @@ impl DebugDevice {
                                     }
                                     self.redraw = true;
                                 }
-                                KeyCode::Backspace => {
-                                    if !line.is_empty() {
-                                        let mut new_line = line.clone();
-                                        new_line.truncate(line.len() - 1);
-                                        self.state =
-                                            DebuggerState::WaitingForBreakpointLineToSet(new_line);
-                                        self.redraw = true;
-                                    }
+                                KeyCode::Backspace if !line.is_empty() => {
+                                    let mut new_line = line.clone();
+                                    new_line.truncate(line.len() - 1);
+                                    self.state =
+                                        DebuggerState::WaitingForBreakpointLineToSet(new_line);
+                                    self.redraw = true;
                                 }
                                 _ => {}
                             }
@@ -341,7 +343,7 @@ impl DebugDevice {
                         DebuggerState::WaitingForBreakpointLineToClear(line) => {
                             match key.code {
                                 KeyCode::Char(chr) => {
-                                    if ('0'..='9').contains(&chr) {
+                                    if chr.is_ascii_digit() {
                                         let mut num = line.clone();
                                         num.push(chr);
                                         self.state =
@@ -363,15 +365,12 @@ impl DebugDevice {
                                     }
                                     self.redraw = true;
                                 }
-                                KeyCode::Backspace => {
-                                    if !line.is_empty() {
-                                        let mut new_line = line.clone();
-                                        new_line.truncate(line.len() - 1);
-                                        self.state = DebuggerState::WaitingForBreakpointLineToClear(
-                                            new_line,
-                                        );
-                                        self.redraw = true;
-                                    }
+                                KeyCode::Backspace if !line.is_empty() => {
+                                    let mut new_line = line.clone();
+                                    new_line.truncate(line.len() - 1);
+                                    self.state =
+                                        DebuggerState::WaitingForBreakpointLineToClear(new_line);
+                                    self.redraw = true;
                                 }
                                 _ => {}
                             }
@@ -590,7 +589,7 @@ impl DebugDevice {
             .map(|op| op.line_num.to_string())
             .unwrap_or_else(|| String::from("??"));
         dump.insert(0, format!("Line Num: {: <5}  ", line_num));
-        lines.extend_from_slice(&fit_in_lines(dump, cols as usize - 1));
+        lines.extend_from_slice(&fit_in_lines(dump, cols - 1));
         Ok(lines)
     }
 
@@ -614,6 +613,7 @@ fn format_dump(dump: Dump, hex_8bit: bool, hex_16bit: bool, chars: bool) -> Vec<
         format!("D2: {}  ", format_8bit(dump.data_reg[2], hex_8bit, chars)),
         format!("D3: {}  ", format_8bit(dump.data_reg[3], hex_8bit, chars)),
         format!("Overflow: {}  ", dump.overflow),
+        format!("EOF: {}  ", dump.eof),
     ]
 }
 
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use crossterm::event::{Event, KeyCode, KeyModifiers};
-use std::io::stdin;
+use std::io::{stdin, Read};
 
 pub fn read_str() -> Vec<u8> {
     let mut chars = String::new();
@@ -8,6 +8,15 @@ pub fn read_str() -> Vec<u8> {
     chars.trim().as_bytes().to_vec()
 }
 
+///Reads one byte from stdin for INPUT, None at the end of stdin
+pub fn read_byte() -> Result<Option<u8>> {
+    let mut byte = [0_u8; 1];
+    match stdin().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
 pub fn read_char() -> Result<u8> {
     let mut char = [0_u8; 1];
     crossterm::terminal::enable_raw_mode()?;
@@ -1,3 +1,7 @@
+#[cfg(feature = "std")]
+use crate::assembler::debug_model::DebugModel;
+#[cfg(feature = "std")]
+use crate::assembler::program_model::{AddressReplacement, OpModel};
 use crate::constants::code::*;
 use crate::constants::hardware::*;
 use crate::constants::{compare, get_byte_count, is_jump_op};
@@ -5,13 +9,29 @@ use crate::device::comm::Output::*;
 use crate::device::comm::*;
 use crate::device::internals::RunResult::{Breakpoint, EoF, ProgError};
 use crate::device::Dump;
+#[cfg(feature = "std")]
+use crate::language::parse_line;
+#[cfg(feature = "std")]
+use crate::tape_reader::{check_section_sizes, Program, Section, Tape};
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
 use anyhow::{Error, Result};
+#[cfg(feature = "std")]
 use chrono::{Local, Timelike};
+use core::cmp::Ordering;
+use core::ops::{BitAnd, BitOr, BitXor, Not, Range};
 use random_fast_rng::{FastRng, Random};
-use std::cmp::Ordering;
+#[cfg(feature = "std")]
+use std::convert::TryFrom;
+#[cfg(feature = "std")]
 use std::fs::{File, OpenOptions};
+#[cfg(feature = "std")]
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+#[cfg(not(feature = "std"))]
+type File = core::convert::Infallible;
 
 //Fields are only public for testing
 pub struct Device {
@@ -31,7 +51,29 @@ pub struct Device {
     pub breakpoints: Vec<u16>,
     rng: FastRng,
     pub keyboard_buffer: Vec<u8>,
+    //Read by INPUT, if not set the host is asked for input with `RunResult::InputRequested`
+    input_source: Option<Box<dyn Iterator<Item = u8>>>,
+    //Input supplied by the host, only used if there's no input source
+    input_buffer: Vec<u8>,
+    input_ended: bool,
+    ticks: u16,
+    overflow_mode: OverflowMode,
     pub output: Vec<Output>,
+    //If set printed text is written here instead of to `output`
+    #[cfg(feature = "std")]
+    output_writer: Option<Box<dyn Write>>,
+    //Byte address, line number and text of each op, sorted by address, set by `attach_debug`
+    source_lines: Vec<(u16, usize, String)>,
+}
+
+///How ADD, SUB, INC and DEC handle going past 0 or the max value
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum OverflowMode {
+    ///Wrap around and set the overflow flag
+    #[default]
+    Wrap,
+    ///Stop with an error, the register and flags are unchanged
+    Trap,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -47,6 +89,20 @@ pub enum RunResult {
     Halt,
     CharInputRequested,
     StringInputRequested,
+    ///INPUT needs a byte, supply it with `push_input` or `end_input`
+    InputRequested,
+}
+
+///Everything a caller needs after `Device::run_to_end`
+#[derive(Debug, Eq, PartialEq)]
+pub struct RunOutcome {
+    ///Why the run stopped, never `Pause`
+    pub result: RunResult,
+    pub final_dump: Dump,
+    ///Text printed during the run, errors and breakpoints are left in `Device.output`
+    pub output: Vec<u8>,
+    ///Number of steps executed, including the one that stopped the run
+    pub cycles: u64,
 }
 
 impl Device {
@@ -70,16 +126,225 @@ impl Device {
             tape_data: data,
             data_files,
             files,
+            #[cfg(feature = "std")]
             rng: FastRng::new(),
+            #[cfg(not(feature = "std"))]
+            rng: FastRng::seed(0, 0),
             keyboard_buffer: vec![],
+            input_source: None,
+            input_buffer: vec![],
+            input_ended: false,
+            ticks: 0,
+            overflow_mode: OverflowMode::Wrap,
             output: vec![],
+            #[cfg(feature = "std")]
+            output_writer: None,
+            source_lines: vec![],
+        }
+    }
+
+    ///Creates a device ready to run `tape` from its entry point
+    #[cfg(feature = "std")]
+    pub fn from_tape(tape: Tape, data_files: Vec<String>) -> Self {
+        let mut device = Device::new(tape.ops, tape.strings, tape.data, data_files);
+        device.pc = tape.entry;
+        device
+    }
+
+    ///Creates a device ready to run `program` from its entry point, the program can't use files
+    #[cfg(feature = "std")]
+    pub fn from_program(program: &Program) -> Result<Self> {
+        let mut device = Device::new(
+            program.ops.clone(),
+            program.string_bytes()?,
+            program.data.clone(),
+            vec![],
+        );
+        device.pc = program.entry;
+        Ok(device)
+    }
+
+    ///Loads the tape in `bytes` so its first op is at `base` and returns the address of its entry point,
+    ///it can then be run by jumping to or calling that address
+    ///The tape must have been assembled as relocatable, label addresses in it are moved by `base`
+    ///and string and data addresses by the end of the already loaded strings and data
+    ///Any gap between the loaded ops and `base` is filled with HALT
+    #[cfg(feature = "std")]
+    pub fn load_overlay(&mut self, bytes: &[u8], base: u16) -> Result<u16> {
+        let mut program = Program::try_from(bytes)?;
+        let relocations = program.relocations.take().ok_or_else(|| {
+            Error::msg("Overlay has no relocation table, assemble it with --relocatable")
+        })?;
+        if (base as usize) < self.tape_ops.len() {
+            return Err(Error::msg(format!(
+                "Overlay at @{} overlaps loaded ops, the first free address is @{}",
+                base,
+                self.tape_ops.len()
+            )));
+        }
+        let strings = program.string_bytes()?;
+        check_section_sizes(
+            base as usize + program.ops.len(),
+            self.tape_strings.len() + strings.len(),
+            self.tape_data.len() + program.data.len(),
+            RAM_SIZE,
+        )?;
+
+        let mut ops = program.ops;
+        let mut data = program.data;
+        for relocation in relocations {
+            let shift = match relocation.target {
+                Section::Ops => base as usize,
+                Section::Strings => self.tape_strings.len(),
+                Section::Data => self.tape_data.len(),
+            };
+            //Offsets were checked against the section sizes when the tape was decoded
+            let bytes = match relocation.location {
+                Section::Data => &mut data,
+                _ => &mut ops,
+            };
+            let (high, low) = (relocation.high as usize, relocation.low as usize);
+            let addr = addr(bytes[high], bytes[low]) as usize + shift;
+            let addr = u16::try_from(addr).map_err(|_| {
+                Error::msg(format!(
+                    "Relocated address at {:?} {} is {}, max is {}",
+                    relocation.location,
+                    high,
+                    addr,
+                    u16::MAX
+                ))
+            })?;
+            let [addr_high, addr_low] = addr.to_be_bytes();
+            bytes[high] = addr_high;
+            bytes[low] = addr_low;
+        }
+
+        self.tape_ops.resize(base as usize, HALT);
+        self.tape_ops.extend_from_slice(&ops);
+        self.tape_strings.extend_from_slice(&strings);
+        self.tape_data.extend_from_slice(&data);
+        Ok(base + program.entry)
+    }
+
+    ///Parses `line` as a single BASM instruction, appends it to the ops and executes it
+    ///For driving the device interactively, labels, strings and data keys aren't supported
+    ///and the op always runs next even if the previous one jumped
+    #[cfg(feature = "std")]
+    pub fn exec_line(&mut self, line: &str) -> Result<RunResult> {
+        let line = line.trim();
+        let (opcode, params) = parse_line(line)?;
+        let (bytes, replacement) =
+            OpModel::new(opcode, params, line.to_string(), line.to_string(), 0).to_bytes();
+        if replacement != AddressReplacement::None {
+            return Err(Error::msg(format!(
+                "Labels, strings and data keys can't be used here: {}",
+                line
+            )));
+        }
+        let start = self.tape_ops.len();
+        if start + bytes.len() > u16::MAX as usize {
+            return Err(Error::msg(format!(
+                "Ops are full, max is {} bytes",
+                u16::MAX
+            )));
+        }
+        self.tape_ops.extend_from_slice(&bytes);
+        self.pc = start as u16;
+        Ok(self.step(true))
+    }
+
+    ///Advances the counter read by RDTICK, wrapping at u16::MAX
+    pub fn advance_ticks(&mut self, ticks: u16) {
+        self.ticks = self.ticks.wrapping_add(ticks);
+    }
+
+    pub fn set_overflow_mode(&mut self, mode: OverflowMode) {
+        self.overflow_mode = mode;
+    }
+
+    ///Sets all the bytes read by INPUT, once they've been read the EOF flag is set
+    ///Without a source INPUT asks the host for each byte with `RunResult::InputRequested`
+    pub fn set_input_source(&mut self, input: Vec<u8>) {
+        self.input_source = Some(Box::new(input.into_iter()));
+    }
+
+    ///Adds bytes for INPUT after `RunResult::InputRequested`
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.input_buffer.extend_from_slice(bytes);
+    }
+
+    ///Marks the end of the input supplied with `push_input`, INPUT sets the EOF flag once it's all been read
+    pub fn end_input(&mut self) {
+        self.input_ended = true;
+    }
+
+    ///Printed text is written (and flushed) to `writer` as it's printed instead of being added to `output`
+    ///Errors and breakpoints are still added to `output`
+    #[cfg(feature = "std")]
+    pub fn set_output_writer(&mut self, writer: Box<dyn Write>) {
+        self.output_writer = Some(writer);
+    }
+
+    ///Runtime errors will include the source line of the op that failed
+    #[cfg(feature = "std")]
+    pub fn attach_debug(&mut self, debug: &DebugModel) {
+        let mut lines = debug
+            .ops
+            .iter()
+            .map(|op| {
+                (
+                    op.byte_addr,
+                    op.line_num,
+                    op.original_line.trim().to_string(),
+                )
+            })
+            .collect::<Vec<(u16, usize, String)>>();
+        lines.sort_unstable_by_key(|line| line.0);
+        self.source_lines = lines;
+    }
+
+    ///Line number and text of the op starting at `byte`, always None without `attach_debug`
+    pub fn source_line(&self, byte: u16) -> Option<(usize, &str)> {
+        self.source_lines
+            .binary_search_by_key(&byte, |line| line.0)
+            .ok()
+            .map(|idx| {
+                let (_, line_num, text) = &self.source_lines[idx];
+                (*line_num, text.as_str())
+            })
+    }
+
+    ///Copies `bytes` into memory starting at `addr`, errors without writing anything if it doesn't fit
+    pub fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> Result<()> {
+        let range = self.memory_range(addr, bytes.len())?;
+        self.mem[range].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    ///Returns `len` bytes of memory starting at `addr`
+    pub fn read_memory(&self, addr: u16, len: u16) -> Result<&[u8]> {
+        let range = self.memory_range(addr, len as usize)?;
+        Ok(&self.mem[range])
+    }
+
+    fn memory_range(&self, addr: u16, len: usize) -> Result<Range<usize>> {
+        let end = addr as usize + len;
+        if end > RAM_SIZE {
+            return Err(Error::msg(format!(
+                "Attempted to access {} bytes from {}, max address is {}",
+                len,
+                addr,
+                RAM_SIZE - 1
+            )));
         }
+        Ok(addr as usize..end)
     }
 }
 
 #[derive(Debug, Default)]
 pub struct Flags {
     overflow: bool,
+    eof: bool,
 }
 
 impl Device {
@@ -95,7 +360,74 @@ impl Device {
         self.execute()
     }
 
+    ///Steps up to `count` times, stopping early on anything other than `Pause` (halt, error, breakpoint, input, etc)
+    ///The first step ignores breakpoints so this can be called again after stopping at one
+    pub fn step_n(&mut self, count: usize) -> RunResult {
+        for idx in 0..count {
+            let result = self.step(idx == 0);
+            if result != RunResult::Pause {
+                return result;
+            }
+        }
+        RunResult::Pause
+    }
+
+    ///Steps until `predicate` is true after a step (returning `Pause`) or the program stops
+    ///Breakpoints are handled the same as `step_n`
+    pub fn run_until(&mut self, predicate: impl Fn(&Dump) -> bool) -> RunResult {
+        let mut first = true;
+        loop {
+            let result = self.step(first);
+            first = false;
+            if result != RunResult::Pause {
+                return result;
+            }
+            if predicate(&self.dump()) {
+                return RunResult::Pause;
+            }
+        }
+    }
+
+    ///Steps until the program stops (halt, error, breakpoint, input, etc)
+    ///Breakpoints are handled the same as `step_n`
+    pub fn run_to_end(&mut self) -> RunOutcome {
+        let output_start = self.output.len();
+        let mut cycles = 0;
+        let result = loop {
+            let result = self.step(cycles == 0);
+            cycles += 1;
+            if result != RunResult::Pause {
+                break result;
+            }
+        };
+        let output = self.output[output_start..]
+            .iter()
+            .filter_map(|output| match output {
+                OutputStd(text) => Some(text.as_bytes()),
+                _ => None,
+            })
+            .flatten()
+            .copied()
+            .collect();
+        RunOutcome {
+            result,
+            final_dump: self.dump(),
+            output,
+            cycles,
+        }
+    }
+
     fn log(&mut self, msg: String) {
+        #[cfg(feature = "std")]
+        if let Some(writer) = self.output_writer.as_mut() {
+            let result = writer
+                .write_all(msg.as_bytes())
+                .and_then(|_| writer.flush());
+            if let Err(err) = result {
+                self.elog(format!("Unable to write output: {}", err));
+            }
+            return;
+        }
         self.output.push(OutputStd(msg));
     }
 
@@ -104,10 +436,16 @@ impl Device {
     }
 
     fn execute(&mut self) -> RunResult {
-        return match self.try_execute() {
+        match self.try_execute() {
             Ok(output) => output,
             Err(err) => {
                 self.elog(format!("\nFatal error at byte {}:", self.pc));
+                if let Some(line) = self
+                    .source_line(self.pc)
+                    .map(|(line_num, text)| format!("Line {}: {}", line_num, text))
+                {
+                    self.elog(line);
+                }
                 self.elog(format!("{}", err));
                 self.elog(String::from("\nInstructions:"));
                 let mut output = String::new();
@@ -139,7 +477,7 @@ impl Device {
                 ));
                 ProgError
             }
-        };
+        }
     }
 
     fn cond_jump(&mut self, should_jump: bool, addr: u16, opcode: u8) {
@@ -158,40 +496,41 @@ impl Device {
             ADD_REG_REG => self.add(
                 self.get_reg_content(self.tape_ops[idx + 1])?,
                 self.get_reg_content(self.tape_ops[idx + 2])?,
-            ),
+            )?,
             ADD_REG_VAL => self.add(
                 self.get_reg_content(self.tape_ops[idx + 1])?,
                 self.tape_ops[idx + 2],
-            ),
+            )?,
             ADD_REG_AREG => self.add(
                 self.get_reg_content(self.tape_ops[idx + 1])?,
                 self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 2])?)?,
-            ),
+            )?,
             SUB_REG_REG => self.sub(
                 self.get_reg_content(self.tape_ops[idx + 1])?,
                 self.get_reg_content(self.tape_ops[idx + 2])?,
-            ),
+            )?,
             SUB_REG_VAL => self.sub(
                 self.get_reg_content(self.tape_ops[idx + 1])?,
                 self.tape_ops[idx + 2],
-            ),
+            )?,
             SUB_REG_AREG => self.sub(
                 self.get_reg_content(self.tape_ops[idx + 1])?,
                 self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 2])?)?,
-            ),
+            )?,
             MEMR_ADDR => self.set_data_reg(
                 REG_ACC,
-                self.get_mem(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2])),
+                self.get_mem(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]))?,
             )?,
             MEMR_AREG => self.set_data_reg(
                 REG_ACC,
-                self.get_mem(self.get_addr_reg_content(self.tape_ops[idx + 1])?),
+                self.get_mem(self.get_addr_reg_content(self.tape_ops[idx + 1])?)?,
             )?,
             CPY_REG_VAL => self.set_data_reg(self.tape_ops[idx + 1], self.tape_ops[idx + 2])?,
             CPY_REG_REG => self.set_data_reg(
                 self.tape_ops[idx + 1],
                 self.get_reg_content(self.tape_ops[idx + 2])?,
             )?,
+            CLR_REG => self.clear_reg(self.tape_ops[idx + 1])?,
             CPY_AREG_REG_REG => self.copy_addr_reg(
                 self.tape_ops[idx + 1],
                 self.tape_ops[idx + 2],
@@ -210,8 +549,21 @@ impl Device {
                 self.tape_ops[idx + 1],
                 self.get_addr_reg_content(self.tape_ops[idx + 2])?,
             )?,
-            MEMW_ADDR => self.store(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2])),
-            MEMW_AREG => self.store(self.get_addr_reg_content(self.tape_ops[idx + 1])?),
+            MEMW_ADDR => self.set_mem(
+                addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]),
+                self.acc,
+            )?,
+            MEMW_AREG => {
+                self.set_mem(self.get_addr_reg_content(self.tape_ops[idx + 1])?, self.acc)?
+            }
+            LDA_REG_ADDR => self.set_data_reg(
+                self.tape_ops[idx + 1],
+                self.get_mem(addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]))?,
+            )?,
+            STA_ADDR_REG => {
+                let value = self.get_reg_content(self.tape_ops[idx + 3])?;
+                self.set_mem(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]), value)?;
+            }
             JMP_AREG => self.jump(self.get_addr_reg_content(self.tape_ops[idx + 1])?),
             JE_AREG => self.cond_jump(
                 self.acc == compare::EQUAL,
@@ -274,6 +626,16 @@ impl Device {
                 addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]),
                 NOVER_ADDR,
             ),
+            DJNZ_AREG_ADDR => {
+                let reg = self.tape_ops[idx + 1];
+                let value = self.get_addr_reg_content(reg)?.wrapping_sub(1);
+                self.set_addr_reg(reg, value)?;
+                self.cond_jump(
+                    value != 0,
+                    addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
+                    DJNZ_AREG_ADDR,
+                );
+            }
             INC_REG => self.change(self.tape_ops[idx + 1], 1)?,
             DEC_REG => self.change(self.tape_ops[idx + 1], -1)?,
             CMP_REG_REG => self.compare(
@@ -315,11 +677,20 @@ impl Device {
             PRTC_AREG => self
                 .printc(self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 1])?)?),
             PRTLN => {
-                self.output.push(OutputStd(String::from("\n")));
+                self.log(String::from("\n"));
             }
             PRTS_STR => {
                 self.print_tape_string(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]))?
             }
+            PRTLN_STR => {
+                self.print_tape_string(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]))?;
+                self.log(String::from("\n"));
+            }
+            LDSTR_AREG_REG_STR => self.load_tape_string(
+                self.tape_ops[idx + 1],
+                self.tape_ops[idx + 2],
+                addr(self.tape_ops[idx + 3], self.tape_ops[idx + 4]),
+            )?,
             FOPEN_REG => self.open_file(self.get_reg_content(self.tape_ops[idx + 1])? as usize)?,
             FILER_REG_ADDR => self.read_file(
                 self.get_reg_content(self.tape_ops[idx + 1])? as usize,
@@ -393,12 +764,25 @@ impl Device {
             PUSH_VAL => self.stack_push(self.tape_ops[idx + 1]),
             PUSH_REG => self.stack_push_reg(self.tape_ops[idx + 1])?,
             POP_REG => self.stack_pop(self.tape_ops[idx + 1])?,
+            DUP => self.stack_dup()?,
+            SWAP => self.stack_swap()?,
+            RDSP_AREG => self.set_addr_reg(self.tape_ops[idx + 1], self.sp)?,
+            LDF_REG_VAL => {
+                let addr = self.frame_addr(self.tape_ops[idx + 2])?;
+                self.set_data_reg(self.tape_ops[idx + 1], self.mem[addr])?
+            }
+            STF_REG_VAL => {
+                let addr = self.frame_addr(self.tape_ops[idx + 2])?;
+                self.mem[addr] = self.get_reg_content(self.tape_ops[idx + 1])?
+            }
             ARG_REG_VAL => self.stack_arg(self.tape_ops[idx + 1], self.tape_ops[idx + 2])?,
             ARG_REG_REG => self.stack_arg(
                 self.tape_ops[idx + 1],
                 self.get_reg_content(self.tape_ops[idx + 2])?,
             )?,
             RET => self.stack_return()?,
+            ENTER_VAL => self.stack_enter(self.tape_ops[idx + 1])?,
+            LEAVE => self.stack_leave()?,
             CALL_ADDR => {
                 self.stack_call(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]), false)
             }
@@ -417,6 +801,11 @@ impl Device {
                     return Ok(RunResult::CharInputRequested);
                 }
             }
+            INPUT_REG => {
+                if !self.read_input(self.tape_ops[idx + 1])? {
+                    return Ok(RunResult::InputRequested);
+                }
+            }
             RSTR_ADDR => {
                 if !self.read_string(addr(self.tape_ops[idx + 1], self.tape_ops[idx + 2]))? {
                     return Ok(RunResult::StringInputRequested);
@@ -449,7 +838,9 @@ impl Device {
                 self.get_addr_reg_content(self.tape_ops[idx + 2])?,
                 FCHK_VAL_AREG,
             ),
-            TIME => self.set_time(),
+            TIME => self.set_time()?,
+            RDTICK_REG => self.set_data_reg(self.tape_ops[idx + 1], self.ticks as u8)?,
+            RDTICK_AREG => self.set_addr_reg(self.tape_ops[idx + 1], self.ticks)?,
             RAND_REG => self.rand(self.tape_ops[idx + 1])?,
             SEED_REG => self.seed(self.get_reg_content(self.tape_ops[idx + 1])?)?,
             AND_REG_REG => self.bit_and(
@@ -489,6 +880,28 @@ impl Device {
                 self.get_data_content(self.get_addr_reg_content(self.tape_ops[idx + 2])?)?,
             ),
             NOT_REG => self.bit_not(self.get_reg_content(self.tape_ops[idx + 1])?),
+            ROL_REG_VAL => self.rotate(
+                self.get_reg_content(self.tape_ops[idx + 1])?,
+                self.tape_ops[idx + 2],
+                true,
+            ),
+            ROR_REG_VAL => self.rotate(
+                self.get_reg_content(self.tape_ops[idx + 1])?,
+                self.tape_ops[idx + 2],
+                false,
+            ),
+            NEG_REG => self.neg(self.get_reg_content(self.tape_ops[idx + 1])?),
+            ABS_REG => self.abs(self.get_reg_content(self.tape_ops[idx + 1])?),
+            MIN_REG_REG | MAX_REG_REG | SMIN_REG_REG | SMAX_REG_REG => {
+                let lhs = self.get_reg_content(self.tape_ops[idx + 1])?;
+                let rhs = self.get_reg_content(self.tape_ops[idx + 2])?;
+                self.acc = match op {
+                    MIN_REG_REG => lhs.min(rhs),
+                    MAX_REG_REG => lhs.max(rhs),
+                    SMIN_REG_REG => (lhs as i8).min(rhs as i8) as u8,
+                    _ => (lhs as i8).max(rhs as i8) as u8,
+                };
+            }
             LD_AREG_DATA_VAL_VAL => self.load_data_addr(
                 self.tape_ops[idx + 1],
                 addr(self.tape_ops[idx + 2], self.tape_ops[idx + 3]),
@@ -535,6 +948,7 @@ impl Device {
                     &self.mem[dump.sp as usize..0xFFFF]
                 ));
             }
+            ASSERT_REG_VAL => self.assert_reg(self.tape_ops[idx + 1], self.tape_ops[idx + 2])?,
             _ => {
                 return Err(Error::msg(format!(
                     "Unknown instruction: {:02X}",
@@ -558,32 +972,51 @@ impl Device {
             data_reg: self.data_reg,
             addr_reg: self.addr_reg,
             overflow: self.flags.overflow,
+            eof: self.flags.eof,
         }
     }
 
     //Accessors
 
     fn get_reg_content(&self, id: u8) -> Result<u8> {
-        return match id {
+        match id {
             REG_ACC => Ok(self.acc),
             REG_D0 => Ok(self.data_reg[0]),
             REG_D1 => Ok(self.data_reg[1]),
             REG_D2 => Ok(self.data_reg[2]),
             REG_D3 => Ok(self.data_reg[3]),
             _ => Err(Error::msg(format!("Invalid data register: {:02X}", id))),
-        };
+        }
     }
 
     fn get_addr_reg_content(&self, id: u8) -> Result<u16> {
-        return match id {
+        match id {
             REG_A0 => Ok(self.addr_reg[0]),
             REG_A1 => Ok(self.addr_reg[1]),
             _ => Err(Error::msg(format!("Invalid address register: {:02X}", id))),
-        };
+        }
     }
 
-    fn get_mem(&self, addr: u16) -> u8 {
-        self.mem[addr as usize]
+    fn get_mem(&self, addr: u16) -> Result<u8> {
+        self.mem
+            .get(addr as usize)
+            .copied()
+            .ok_or_else(|| memory_error(addr))
+    }
+
+    fn set_mem(&mut self, addr: u16, value: u8) -> Result<()> {
+        let byte = self
+            .mem
+            .get_mut(addr as usize)
+            .ok_or_else(|| memory_error(addr))?;
+        *byte = value;
+        Ok(())
+    }
+
+    fn clear_reg(&mut self, reg: u8) -> Result<()> {
+        self.set_data_reg(reg, 0)?;
+        self.flags.overflow = false;
+        Ok(())
     }
 
     fn set_data_reg(&mut self, reg: u8, value: u8) -> Result<()> {
@@ -681,6 +1114,19 @@ impl Device {
         Ok(())
     }
 
+    ///Returns false if the host needs to supply input
+    fn read_input(&mut self, reg: u8) -> Result<bool> {
+        let value = match self.input_source.as_mut() {
+            Some(source) => source.next(),
+            None if !self.input_buffer.is_empty() => Some(self.input_buffer.remove(0)),
+            None if self.input_ended => None,
+            None => return Ok(false),
+        };
+        self.flags.eof = value.is_none();
+        self.set_data_reg(reg, value.unwrap_or(0))?;
+        Ok(true)
+    }
+
     fn read_char(&mut self, reg: u8) -> Result<bool> {
         if self.keyboard_buffer.is_empty() {
             Ok(false)
@@ -690,7 +1136,11 @@ impl Device {
             Ok(true)
         }
     }
+}
 
+//File and clock access needs std, without it these ops error
+#[cfg(feature = "std")]
+impl Device {
     fn open_file(&mut self, file_num: usize) -> Result<()> {
         if self.files[file_num].is_some() {
             return Err(Error::msg(format!("File {} already open", file_num)));
@@ -702,6 +1152,7 @@ impl Device {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&self.data_files[file_num])?;
         let pos = file
             .seek(SeekFrom::End(0))
@@ -833,6 +1284,61 @@ impl Device {
         }
     }
 
+    fn set_time(&mut self) -> Result<()> {
+        let time = Local::now();
+        let hour = time.hour() as u8;
+        let minute = time.minute() as u8;
+        let second = time.second() as u8;
+        self.data_reg[0] = second;
+        self.data_reg[1] = minute;
+        self.data_reg[2] = hour;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Device {
+    fn files_unsupported<T>(&self, file_num: usize) -> Result<T> {
+        if self.data_files.len() <= file_num {
+            return Err(Error::msg(format!("File {} not provided", file_num)));
+        }
+        Err(Error::msg("Files are not supported without std"))
+    }
+
+    fn open_file(&mut self, file_num: usize) -> Result<()> {
+        self.files_unsupported(file_num)
+    }
+
+    fn seek_file_stack(&mut self, file_num: usize) -> Result<()> {
+        self.files_unsupported(file_num)
+    }
+
+    fn seek_file(&mut self, file_num: usize) -> Result<()> {
+        self.files_unsupported(file_num)
+    }
+
+    fn read_file(&mut self, file_num: usize, _: u16) -> Result<()> {
+        self.files_unsupported(file_num)
+    }
+
+    fn write_file(&mut self, file_num: usize, _: u16) -> Result<()> {
+        self.files_unsupported(file_num)
+    }
+
+    fn write_file_value(&mut self, file_num: usize, _: u8) -> Result<()> {
+        self.files_unsupported(file_num)
+    }
+
+    fn skip_file(&mut self, file_num: usize, _: u8) -> Result<()> {
+        self.files_unsupported(file_num)
+    }
+
+    fn set_time(&mut self) -> Result<()> {
+        Err(Error::msg("TIME is not supported without std"))
+    }
+}
+
+impl Device {
     fn load_data_addr(&mut self, areg: u8, addr: u16, offset1: u8, offset2: u8) -> Result<()> {
         if (addr as usize + offset1 as usize) >= self.tape_data.len() {
             return Err(Error::msg(format!(
@@ -899,6 +1405,18 @@ impl Device {
         self.acc = value.not();
     }
 
+    //Plain rotate, the carry (overflow flag) is set to the last bit rotated but isn't rotated in
+    fn rotate(&mut self, value: u8, amount: u8, left: bool) {
+        let amount = (amount % 8) as u32;
+        if left {
+            self.acc = value.rotate_left(amount);
+            self.flags.overflow = amount > 0 && self.acc & 1 == 1;
+        } else {
+            self.acc = value.rotate_right(amount);
+            self.flags.overflow = amount > 0 && self.acc & 0x80 == 0x80;
+        }
+    }
+
     fn print_tape_string(&mut self, data_addr: u16) -> Result<()> {
         let length = self.tape_strings[data_addr as usize] as u16;
         let start = (data_addr + 1) as usize;
@@ -909,18 +1427,19 @@ impl Device {
         Ok(())
     }
 
-    fn printc(&mut self, val: u8) {
-        self.log(format!("{}", val as char));
+    ///Copies the string (without its length) to memory at `addr_reg`, then advances `addr_reg` past it and sets `reg` to the length
+    fn load_tape_string(&mut self, addr_reg: u8, reg: u8, data_addr: u16) -> Result<()> {
+        let length = self.tape_strings[data_addr as usize];
+        let start = (data_addr + 1) as usize;
+        let target = self.get_addr_reg_content(addr_reg)?;
+        let range = self.memory_range(target, length as usize)?;
+        self.mem[range].copy_from_slice(&self.tape_strings[start..start + length as usize]);
+        self.set_addr_reg(addr_reg, target + length as u16)?;
+        self.set_data_reg(reg, length)
     }
 
-    fn set_time(&mut self) {
-        let time = Local::now();
-        let hour = time.hour() as u8;
-        let minute = time.minute() as u8;
-        let second = time.second() as u8;
-        self.data_reg[0] = second;
-        self.data_reg[1] = minute;
-        self.data_reg[2] = hour;
+    fn printc(&mut self, val: u8) {
+        self.log(format!("{}", val as char));
     }
 
     fn seed(&mut self, value: u8) -> Result<()> {
@@ -968,6 +1487,16 @@ impl Device {
     }
 
     fn change(&mut self, id: u8, diff: isize) -> Result<()> {
+        if self.overflow_mode == OverflowMode::Trap {
+            let (value, limit) = match id {
+                REG_A0 | REG_A1 => (self.get_addr_reg_content(id)?, u16::MAX),
+                _ => (self.get_reg_content(id)? as u16, u8::MAX as u16),
+            };
+            if (diff < 1 && value == 0) || (diff > 0 && value == limit) {
+                let op = if diff < 1 { "DEC" } else { "INC" };
+                self.trap_overflow(format!("{} {}", op, value))?;
+            }
+        }
         let update = |value: u8| {
             if diff < 1 {
                 value.overflowing_sub(1)
@@ -1022,16 +1551,44 @@ impl Device {
         }
     }
 
-    fn add(&mut self, lhs: u8, rhs: u8) {
+    fn add(&mut self, lhs: u8, rhs: u8) -> Result<()> {
         let (value, overflowed) = lhs.overflowing_add(rhs);
+        if overflowed {
+            self.trap_overflow(format!("{} + {}", lhs, rhs))?;
+        }
         self.flags.overflow = overflowed;
         self.acc = value;
+        Ok(())
     }
 
-    fn sub(&mut self, lhs: u8, rhs: u8) {
+    fn sub(&mut self, lhs: u8, rhs: u8) -> Result<()> {
         let (value, overflowed) = lhs.overflowing_sub(rhs);
+        if overflowed {
+            self.trap_overflow(format!("{} - {}", lhs, rhs))?;
+        }
         self.flags.overflow = overflowed;
         self.acc = value;
+        Ok(())
+    }
+
+    fn trap_overflow(&self, calculation: String) -> Result<()> {
+        match self.overflow_mode {
+            OverflowMode::Wrap => Ok(()),
+            OverflowMode::Trap => Err(Error::msg(format!("Overflow trapped: {}", calculation))),
+        }
+    }
+
+    //-128 can't be negated so the result stays -128 and overflow is set
+    fn neg(&mut self, value: u8) {
+        let (value, overflowed) = (value as i8).overflowing_neg();
+        self.flags.overflow = overflowed;
+        self.acc = value as u8;
+    }
+
+    fn abs(&mut self, value: u8) {
+        let (value, overflowed) = (value as i8).overflowing_abs();
+        self.flags.overflow = overflowed;
+        self.acc = value as u8;
     }
 
     fn load_data(&mut self, dest: u8, areg: u8) -> Result<()> {
@@ -1056,10 +1613,6 @@ impl Device {
         Ok(self.tape_data[addr as usize])
     }
 
-    fn store(&mut self, addr: u16) {
-        self.mem[addr as usize] = self.acc;
-    }
-
     fn jump(&mut self, addr: u16) {
         self.pc = addr;
     }
@@ -1115,6 +1668,52 @@ impl Device {
         Ok(())
     }
 
+    fn stack_dup(&mut self) -> Result<()> {
+        let value = self.sp_remove()?;
+        self.stack_push(value);
+        self.stack_push(value);
+        Ok(())
+    }
+
+    fn stack_swap(&mut self) -> Result<()> {
+        //Checked first so a failed SWAP leaves the stack unchanged
+        if self.sp as usize + 2 > RAM_SIZE {
+            return Err(Error::msg(format!(
+                "Attempted to SWAP with less than 2 bytes on the stack, SP {}",
+                self.sp
+            )));
+        }
+        let first = self.sp_remove()?;
+        let second = self.sp_remove()?;
+        self.stack_push(first);
+        self.stack_push(second);
+        Ok(())
+    }
+
+    fn assert_reg(&self, reg: u8, expected: u8) -> Result<()> {
+        let actual = self.get_reg_content(reg)?;
+        if actual != expected {
+            return Err(Error::msg(format!(
+                "Assertion failed: {} is {}, expected {}",
+                reg_name(reg),
+                actual,
+                expected
+            )));
+        }
+        Ok(())
+    }
+
+    //Locals are pushed after CALL, so they are below the FP
+    fn frame_addr(&self, offset: u8) -> Result<usize> {
+        match self.fp.checked_sub(offset as u16) {
+            Some(addr) if offset > 0 && addr >= self.sp => Ok(addr as usize),
+            _ => Err(Error::msg(format!(
+                "Attempted to access local outside of frame, FP {} offset {}, SP {}",
+                self.fp, offset, self.sp
+            ))),
+        }
+    }
+
     fn stack_arg(&mut self, reg: u8, offset: u8) -> Result<()> {
         let addr = self.fp.saturating_add(offset.saturating_add(3) as u16) as usize;
         let addr_second = self.fp.saturating_add((offset.saturating_add(4)) as u16) as usize;
@@ -1142,7 +1741,7 @@ impl Device {
         Ok(())
     }
 
-    ///Stack explanation
+    //Stack explanation
     //Stack is written downwards from top of memory, so @FFFF is 0
     //FP points to the last byte of the previous frame (starts at FFFF)
     //SP points to the last byte of the current frame, which is also the end of the stack (starts at FFFF)
@@ -1179,6 +1778,48 @@ impl Device {
         self.fp = self.sp;
     }
 
+    //ENTER n pushes FP (high byte first, the same as CALL), sets FP to SP and then moves SP down by n
+    //The reserved bytes are zeroed and are locals 1 to n for LDF/STF
+    //LEAVE sets SP to FP and pops FP, so the stack is as it was before ENTER
+    //
+    //Inside ENTER the saved FP is between FP and the CALL, so ARG offsets are 2 higher
+    //
+    //Call:    SP=4 FP=4 Stack=[fp hb][fp lb][pc hb][pc lb]
+    //Enter 2: SP=8 FP=6 Stack=[fp hb][fp lb][pc hb][pc lb][fp hb][fp lb][local 1][local 2]
+    //Leave:   SP=4 FP=4 Stack=[fp hb][fp lb][pc hb][pc lb]
+    fn stack_enter(&mut self, locals: u8) -> Result<()> {
+        if (self.sp as usize) < 2 + locals as usize {
+            return Err(Error::msg(format!(
+                "Not enough stack for frame with {} locals, SP {}",
+                locals, self.sp
+            )));
+        }
+        let bytes = self.fp.to_be_bytes();
+        self.sp_add(bytes[0]);
+        self.sp_add(bytes[1]);
+        self.fp = self.sp;
+        for _ in 0..locals {
+            self.sp_add(0);
+        }
+        Ok(())
+    }
+
+    fn stack_leave(&mut self) -> Result<()> {
+        //Checked first so SP and FP are unchanged if there's no saved FP
+        if self.fp as usize + 2 > RAM_SIZE {
+            return Err(Error::msg(format!(
+                "Attempted to LEAVE with no saved FP on the stack, FP {}",
+                self.fp
+            )));
+        }
+        self.sp = self.fp;
+        let mut bytes = [0; 2];
+        bytes[1] = self.sp_remove()?;
+        bytes[0] = self.sp_remove()?;
+        self.fp = u16::from_be_bytes(bytes);
+        Ok(())
+    }
+
     fn stack_return(&mut self) -> Result<()> {
         let mut bytes = [0; 2];
         bytes[1] = self.sp_remove()?;
@@ -1197,6 +1838,14 @@ impl Device {
     }
 }
 
+fn memory_error(addr: u16) -> Error {
+    Error::msg(format!(
+        "Attempted to access memory at {}, max address is {}",
+        addr,
+        RAM_SIZE - 1
+    ))
+}
+
 fn addr(byte1: u8, byte2: u8) -> u16 {
     u16::from_be_bytes([byte1, byte2])
 }
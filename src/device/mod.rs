@@ -1,27 +1,40 @@
+//Only `internals`, `comm` and `Dump` are available without std
+#[cfg(feature = "std")]
 mod debug_device;
+#[cfg(feature = "std")]
 mod input;
 pub mod internals;
+#[cfg(feature = "std")]
 mod piped_device;
+#[cfg(feature = "std")]
 mod std_device;
+#[cfg(feature = "std")]
 mod util;
 
+#[cfg(feature = "std")]
+use crate::common::read_bytes;
 use crate::constants::hardware::{ADDR_REG_COUNT, DATA_REG_COUNT, RAM_SIZE};
+#[cfg(feature = "std")]
 use crate::device::debug_device::{setup_terminal, shutdown_terminal, DebugDevice};
+#[cfg(feature = "std")]
 use crate::device::piped_device::PipedDevice;
+#[cfg(feature = "std")]
 use crate::device::std_device::StdDevice;
+#[cfg(feature = "std")]
 use crate::tape_reader::read_tape;
+#[cfg(feature = "std")]
 use anyhow::Result;
+#[cfg(feature = "std")]
 use std::fs::read_to_string;
 
+#[cfg(feature = "std")]
 pub fn start(path: &str, input_paths: Vec<&str>) -> Result<()> {
     let tape = read_tape(path)?;
 
     println!("Running {} v{}", tape.name, tape.version);
 
     let mut device = StdDevice::new(
-        tape.ops,
-        tape.strings,
-        tape.data,
+        tape,
         input_paths.iter().map(|str| str.to_string()).collect(),
     );
     device.run();
@@ -29,13 +42,12 @@ pub fn start(path: &str, input_paths: Vec<&str>) -> Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "std")]
 pub fn start_piped(path: &str, input_paths: Vec<&str>) -> Result<()> {
     let tape = read_tape(path)?;
 
     let mut device = PipedDevice::new(
-        tape.ops,
-        tape.strings,
-        tape.data,
+        tape,
         input_paths.iter().map(|str| str.to_string()).collect(),
     );
     device.run();
@@ -43,17 +55,26 @@ pub fn start_piped(path: &str, input_paths: Vec<&str>) -> Result<()> {
     Ok(())
 }
 
-pub fn start_debug(path: &str, debug_path: &str, input_paths: Vec<&str>) -> Result<()> {
+#[cfg(feature = "std")]
+pub fn start_debug(
+    path: &str,
+    debug_path: &str,
+    input_paths: Vec<&str>,
+    input_source: Option<&str>,
+) -> Result<()> {
     let tape = read_tape(path)?;
+    let input = match input_source {
+        Some(input_path) => read_bytes(input_path)?,
+        None => vec![],
+    };
     let debug_info_text = read_to_string(debug_path).expect("Unable to read debug info file");
     let debug_info = serde_json::from_str(&debug_info_text).expect("Unable to parse debug info");
 
     let mut device = DebugDevice::new(
-        tape.ops,
-        tape.strings,
-        tape.data,
+        tape,
         debug_info,
         input_paths.iter().map(|str| str.to_string()).collect(),
+        input,
     );
 
     setup_terminal()?;
@@ -64,6 +85,8 @@ pub fn start_debug(path: &str, debug_path: &str, input_paths: Vec<&str>) -> Resu
 }
 
 pub mod comm {
+    use alloc::string::String;
+
     pub enum Output {
         OutputStd(String),
         OutputErr(String),
@@ -80,6 +103,7 @@ pub struct Dump {
     pub data_reg: [u8; DATA_REG_COUNT],
     pub addr_reg: [u16; ADDR_REG_COUNT],
     pub overflow: bool,
+    pub eof: bool,
 }
 
 impl Default for Dump {
@@ -92,6 +116,7 @@ impl Default for Dump {
             data_reg: [0, 0, 0, 0],
             addr_reg: [0, 0],
             overflow: false,
+            eof: false,
         }
     }
 }
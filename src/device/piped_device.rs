@@ -1,22 +1,23 @@
 use crate::device::comm::Output;
 use crate::device::internals::{Device, RunResult};
 use crate::device::piped_device::prefix::*;
+use crate::tape_reader::Tape;
 use std::io::{stdin, stdout, Read, Write};
 use std::mem::swap;
 use std::thread::sleep;
 use std::time::Duration;
 
-/// PipedDevice
-/// Can be used by external programs to host the Device so that it can used inside other programs such as debuggers
-///
-/// The Device can be communicated with over stdin and stdout using bytes
-/// Instructions and user input are sent over stdin
-/// Program output and diagnostics are sent over stdout
-///
-/// The format for both channels is <cmd><content len><content>
-/// Program output: o,11,H,e,l,l,o, ,w,o,r,l,d
-/// Error output:   e,12,C,r,a,s,h,e,d,\n,P,C,:, ,1
-/// Set breakpoint: b,2,30
+// PipedDevice
+// Can be used by external programs to host the Device so that it can used inside other programs such as debuggers
+//
+// The Device can be communicated with over stdin and stdout using bytes
+// Instructions and user input are sent over stdin
+// Program output and diagnostics are sent over stdout
+//
+// The format for both channels is <cmd><content len><content>
+// Program output: o,11,H,e,l,l,o, ,w,o,r,l,d
+// Error output:   e,12,C,r,a,s,h,e,d,\n,P,C,:, ,1
+// Set breakpoint: b,2,30
 
 mod prefix {
     pub const OUTPUT_STR: u8 = b'o';
@@ -24,6 +25,7 @@ mod prefix {
     pub const OUTPUT_BP_HIT: u8 = b'h';
     pub const OUTPUT_REQ_STR: u8 = b't';
     pub const OUTPUT_REQ_KEY: u8 = b'k';
+    pub const OUTPUT_REQ_INPUT: u8 = b'i';
     pub const OUTPUT_END: u8 = b'f';
     pub const OUTPUT_CRASH: u8 = b'c';
     pub const OUTPUT_DUMP: u8 = b'd';
@@ -37,6 +39,7 @@ mod prefix {
     pub const INPUT_MEMORY: u8 = b'm';
     pub const INPUT_CHAR: u8 = b'k';
     pub const INPUT_STRING: u8 = b't';
+    pub const INPUT_INPUT: u8 = b'i';
 }
 
 pub struct PipedDevice {
@@ -44,9 +47,9 @@ pub struct PipedDevice {
 }
 
 impl PipedDevice {
-    pub fn new(ops: Vec<u8>, strings: Vec<u8>, data: Vec<u8>, data_files: Vec<String>) -> Self {
+    pub fn new(tape: Tape, data_files: Vec<String>) -> Self {
         PipedDevice {
-            device: Device::new(ops, strings, data, data_files),
+            device: Device::from_tape(tape, data_files),
         }
     }
 }
@@ -101,6 +104,11 @@ impl PipedDevice {
                     .write_all(&[OUTPUT_REQ_STR])
                     .expect("Writing to stdout");
             }
+            RunResult::InputRequested => {
+                stdout()
+                    .write_all(&[OUTPUT_REQ_INPUT])
+                    .expect("Writing to stdout");
+            }
         }
     }
 
@@ -129,6 +137,7 @@ impl PipedDevice {
                 write_u16(dump.sp);
                 write_u16(dump.fp);
                 let overflow_byte = if dump.overflow { 1 } else { 0 };
+                let eof_byte = if dump.eof { 1 } else { 0 };
                 stdout()
                     .write_all(&[
                         dump.acc,
@@ -137,6 +146,7 @@ impl PipedDevice {
                         dump.data_reg[2],
                         dump.data_reg[3],
                         overflow_byte,
+                        eof_byte,
                     ])
                     .expect("Writing to stdout");
                 stdout().flush().expect("Writing to stdout");
@@ -174,6 +184,17 @@ impl PipedDevice {
                 }
                 self.device.keyboard_buffer.extend_from_slice(&bytes);
             }
+            INPUT_INPUT => {
+                let len = read_u8() as usize;
+                if len == 0 {
+                    self.device.end_input();
+                }
+                let mut bytes = vec![];
+                for _ in 0..len {
+                    bytes.push(read_u8())
+                }
+                self.device.push_input(&bytes);
+            }
             _ => {}
         }
     }
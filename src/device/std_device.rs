@@ -1,6 +1,7 @@
 use crate::device::comm::Output;
-use crate::device::input::{read_char, read_str};
+use crate::device::input::{read_byte, read_char, read_str};
 use crate::device::internals::{Device, RunResult};
+use crate::tape_reader::Tape;
 use crossterm::style::{Color, Print, ResetColor, SetForegroundColor};
 use crossterm::ExecutableCommand;
 use std::io::stdout;
@@ -12,9 +13,9 @@ pub struct StdDevice {
 }
 
 impl StdDevice {
-    pub fn new(ops: Vec<u8>, strings: Vec<u8>, data: Vec<u8>, data_files: Vec<String>) -> Self {
+    pub fn new(tape: Tape, data_files: Vec<String>) -> Self {
         StdDevice {
-            device: Device::new(ops, strings, data, data_files),
+            device: Device::from_tape(tape, data_files),
             last_run_result: RunResult::Pause,
         }
     }
@@ -39,6 +40,13 @@ impl StdDevice {
                     self.device.keyboard_buffer.extend_from_slice(&input);
                     self.last_run_result = RunResult::Pause;
                 }
+                RunResult::InputRequested => {
+                    match read_byte().expect("Error reading input (stdin)") {
+                        Some(byte) => self.device.push_input(&[byte]),
+                        None => self.device.end_input(),
+                    }
+                    self.last_run_result = RunResult::Pause;
+                }
             }
 
             let mut msgs = vec![];
@@ -34,7 +34,7 @@ pub fn fit_in_lines(parts: Vec<String>, max_width: usize) -> Vec<String> {
 }
 
 pub fn breaklines(mut line: String, max_width: usize) -> Vec<String> {
-    return if line.chars().count() < max_width {
+    if line.chars().count() < max_width {
         vec![line]
     } else {
         let mut output = vec![];
@@ -59,7 +59,7 @@ pub fn breaklines(mut line: String, max_width: usize) -> Vec<String> {
             }
         }
         output
-    };
+    }
 }
 
 fn index_of_first_non_alphanumeric_before(str: &str, start: usize) -> Option<usize> {
@@ -271,14 +271,14 @@ mod test {
 
         #[test]
         fn test_index_of_first_before() {
-            let list = vec![1, 2, 3];
+            let list = [1, 2, 3];
             let result1 = index_of_first_before(&mut list.iter(), |num| num == &2, 0);
             let result2 = index_of_first_before(&mut list.iter(), |num| num == &2, 2);
 
             assert_eq!(result1, None);
             assert_eq!(result2, Some(1));
 
-            let list = vec!['a', 'k', 'p', 'n'];
+            let list = ['a', 'k', 'p', 'n'];
             let result1 = index_of_first_before(&mut list.iter(), |chr| chr == &'n', 0);
             let result2 = index_of_first_before(&mut list.iter(), |chr| chr == &'p', 2);
             let result3 = index_of_first_before(&mut list.iter(), |chr| chr == &'p', 3);
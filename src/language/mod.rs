@@ -1,5 +1,6 @@
+use crate::constants::{get_byte_count, is_jump_op};
 use crate::language::ops::OPS;
-use crate::language::parser::params::Param;
+use crate::language::parser::params::{Param, Parameters};
 use anyhow::{Error, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -11,15 +12,87 @@ lazy_static! {
     //finds groups of non whitespace or chars
     //eg prtc @xAF 10 label 'a' ' '
     static ref LINE_REGEX: Regex = Regex::new("'.'|(?:\\S)+").unwrap();
+    static ref OP_TABLE: Vec<OpInfo> = OPS
+        .iter()
+        .flat_map(|op| {
+            op.variants()
+                .into_iter()
+                .map(move |(opcode, params)| OpInfo {
+                    mnemonic: op.mnemonic(),
+                    opcode,
+                    params,
+                    byte_count: get_byte_count(opcode),
+                    description: op.description(),
+                    example: op.example(),
+                })
+        })
+        .collect();
+}
+
+///Describes one variant of an instruction
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OpInfo {
+    ///Upper case, e.g. `CPY`
+    pub mnemonic: &'static str,
+    pub opcode: u8,
+    ///Accepted kinds for each operand
+    pub params: Vec<Parameters>,
+    ///Including the opcode
+    pub byte_count: usize,
+    pub description: &'static str,
+    ///Shared by all variants of the mnemonic, e.g. `cpy d0 10`
+    pub example: &'static str,
+}
+
+///Returns the kinds accepted for the next operand of `mnemonic` (case insensitive) given the operands
+///already parsed, or None if no variant takes any more operands
+pub fn next_operand(mnemonic: &str, parsed: &[Param]) -> Option<Parameters> {
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    op_table()
+        .iter()
+        .filter(|info| info.mnemonic == mnemonic && info.params.len() > parsed.len())
+        .filter(|info| {
+            info.params
+                .iter()
+                .zip(parsed)
+                .all(|(kind, param)| kind.contains(param_kind(param)))
+        })
+        .map(|info| info.params[parsed.len()])
+        .reduce(|acc, kind| acc | kind)
+}
+
+fn param_kind(param: &Param) -> Parameters {
+    match param {
+        Param::Number(_) => Parameters::NUMBER,
+        Param::DataReg(_) => Parameters::DATA_REG,
+        Param::AddrReg(_) => Parameters::ADDR_REG,
+        Param::Addr(_) => Parameters::ADDRESS,
+        Param::Label(_) | Param::LabelAddr(_) => Parameters::LABEL,
+        Param::StrKey(_) => Parameters::STRING_KEY,
+        Param::DataKey(_) => Parameters::DATA_KEY,
+    }
+}
+
+///Every instruction variant, some opcodes appear more than once as they accept multiple param kinds
+pub fn op_table() -> &'static [OpInfo] {
+    &OP_TABLE
+}
+
+///Splits a BASM instruction on any whitespace (spaces or tabs), keeping char literals such as `' '` or `'\t'` whole
+pub fn split_line(input: &str) -> Vec<&str> {
+    LINE_REGEX
+        .find_iter(input)
+        .map(|cap| cap.as_str())
+        .collect()
 }
 
 ///This method converts a BASM instruction into usable parts for the assembler
 ///The line can not contain any comments or a label
 pub fn parse_line(input: &str) -> Result<(u8, Vec<Param>)> {
-    let parts = LINE_REGEX
-        .find_iter(input)
-        .map(|cap| cap.as_str())
-        .collect::<Vec<&str>>();
+    let parts = split_line(input);
+    if parts.is_empty() {
+        return Err(Error::msg("Unable to parse empty line"));
+    }
 
     for op in OPS.iter() {
         if op.matches(parts[0]) {
@@ -29,12 +102,25 @@ pub fn parse_line(input: &str) -> Result<(u8, Vec<Param>)> {
                 op.parse(&[])
             };
             return match result {
-                None => Err(Error::msg(format!(
-                    "parsing line '{}'\n{}",
+                Err(operand) => Err(Error::msg(format!(
+                    "parsing line '{}'\n{}\n{}",
                     input,
+                    operand,
                     op.error_text()
                 ))),
-                Some(params) => Ok(params),
+                Ok((opcode, params)) if is_jump_op(opcode) => {
+                    match params.iter().find_map(|param| match param {
+                        Param::LabelAddr(lbl) => Some(lbl),
+                        _ => None,
+                    }) {
+                        Some(lbl) => Err(Error::msg(format!(
+                            "parsing line '{}'\n& is only for addresses used as values, jump to {} instead",
+                            input, lbl
+                        ))),
+                        None => Ok((opcode, params)),
+                    }
+                }
+                Ok(params) => Ok(params),
             };
         }
     }
@@ -45,11 +131,28 @@ pub fn parse_line(input: &str) -> Result<(u8, Vec<Param>)> {
     )))
 }
 
+///Returns true if `text` is the mnemonic of any instruction (case insensitive)
+pub fn is_mnemonic(text: &str) -> bool {
+    OPS.iter().any(|op| op.matches(text))
+}
+
+///This method converts the operand bytes of an assembled instruction back into params
+///Addresses, labels, string keys and data keys are all returned as `Param::Addr`
+pub fn decode_params(opcode: u8, bytes: &[u8]) -> Option<Vec<Param>> {
+    OPS.iter().find_map(|op| op.decode(opcode, bytes))
+}
+
+///Returns the mnemonic (in upper case) of the instruction with `opcode`
+pub fn mnemonic(opcode: u8) -> Option<&'static str> {
+    OPS.iter().find_map(|op| op.mnemonic_for(opcode))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::constants::code::*;
-    use crate::constants::hardware::{REG_A1, REG_ACC, REG_D3};
+    use crate::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D3};
+    use crate::constants::ALL_OPS;
 
     #[test]
     #[rustfmt::skip]
@@ -66,5 +169,189 @@ mod tests {
             )
         );
         assert_eq!(parse_line("halt").unwrap(), (HALT, vec![]));
+        assert_eq!(
+            parse_line("clr acc").unwrap(),
+            (CLR_REG, vec![Param::DataReg(REG_ACC)])
+        );
+    }
+
+    #[test]
+    fn test_tabs_and_mixed_whitespace() {
+        assert_eq!(
+            parse_line("cpy\td0\t5").unwrap(),
+            parse_line("cpy d0 5").unwrap()
+        );
+        assert_eq!(
+            parse_line("cpy \t d0\t  5\t").unwrap(),
+            parse_line("cpy d0 5").unwrap()
+        );
+        assert_eq!(
+            parse_line("prtc\t'a'\t").unwrap(),
+            (PRTC_VAL, vec![Param::Number(97)])
+        );
+        assert_eq!(
+            parse_line("cpy\td0\t' '").unwrap(),
+            (CPY_REG_VAL, vec![Param::DataReg(REG_D0), Param::Number(32)])
+        );
+        assert_eq!(
+            parse_line("prtc '\t'").unwrap(),
+            (PRTC_VAL, vec![Param::Number(9)])
+        );
+        assert_eq!(split_line("\tprtc\t' '\t"), vec!["prtc", "' '"]);
+        assert!(parse_line("").is_err());
+        assert!(parse_line(" \t ").is_err());
+    }
+
+    #[test]
+    fn test_empty_lines() {
+        for line in ["", "   ", "\t", "\n"] {
+            assert_eq!(
+                parse_line(line).unwrap_err().to_string(),
+                "Unable to parse empty line",
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_text() {
+        assert_eq!(
+            parse_line("inc 10").unwrap_err().to_string(),
+            "parsing line 'inc 10'\noperand 1 (data_reg|addr_reg): got '10'\nINC supports:\nINC (data_reg|addr_reg)"
+        );
+    }
+
+    #[test]
+    fn test_error_text_names_every_operand() {
+        for op in OPS.iter() {
+            for line in op.error_text().lines().skip(1) {
+                assert!(
+                    !line.contains("  ") && !line.ends_with(' ') && !line.contains("()"),
+                    "{:?}",
+                    line
+                );
+            }
+            //Kinds accepted by several variants are combined
+            if let Some(kinds) = next_operand(op.mnemonic(), &[]) {
+                assert!(!kinds.to_string().contains("()"), "{}", op.mnemonic());
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_char_errors() {
+        for (line, chr) in [("cpy d0 'é'", "é"), ("prtc '😀'", "😀")] {
+            let error = parse_line(line).unwrap_err().to_string();
+            assert!(
+                error.contains(&format!(
+                    "char literals must be a single ASCII character; '{}' is not ASCII",
+                    chr
+                )),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_error_names_operand() {
+        let error = parse_line("ld a0 nums foo 1").unwrap_err().to_string();
+        assert!(
+            error.contains("operand 3 (data_reg|byte): got 'foo'"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_address_of_jump_target() {
+        assert_eq!(
+            parse_line("cpy a0 &loop").unwrap(),
+            (
+                CPY_AREG_ADDR,
+                vec![
+                    Param::AddrReg(REG_A0),
+                    Param::LabelAddr(String::from("loop"))
+                ]
+            )
+        );
+        assert_eq!(
+            parse_line("jmp &loop").unwrap_err().to_string(),
+            "parsing line 'jmp &loop'\n& is only for addresses used as values, jump to loop instead"
+        );
+        assert!(parse_line("djnz a1 &loop").is_err());
+        assert!(parse_line("call &loop").is_err());
+    }
+
+    #[test]
+    fn test_operand_count_errors() {
+        assert_eq!(
+            parse_line("add d0").unwrap_err().to_string(),
+            "parsing line 'add d0'\nexpected 2 operands, got 1\nADD supports:\nADD data_reg data_reg\nADD data_reg byte\nADD data_reg addr_reg"
+        );
+        let error = parse_line("inc d0 d1").unwrap_err().to_string();
+        assert!(error.contains("\nexpected 1 operand, got 2\n"), "{}", error);
+        let error = parse_line("halt 1").unwrap_err().to_string();
+        assert!(
+            error.contains("\nexpected 0 operands, got 1\n"),
+            "{}",
+            error
+        );
+        let error = parse_line("prtln a b").unwrap_err().to_string();
+        assert!(
+            error.contains("\nexpected 0 or 1 operands, got 2\n"),
+            "{}",
+            error
+        );
+        let error = parse_line("ld a0 nums").unwrap_err().to_string();
+        assert!(
+            error.contains("\nexpected 4 operands, got 2\n"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_op_table() {
+        let halt = op_table().iter().find(|info| info.opcode == HALT).unwrap();
+        assert_eq!(halt.mnemonic, "HALT");
+        assert!(halt.params.is_empty());
+        assert_eq!(halt.byte_count, 1);
+        assert_eq!(halt.description, "Stop program execution");
+        assert!(!halt.description.is_empty());
+        assert_eq!(halt.example, "halt");
+
+        let cpy = op_table()
+            .iter()
+            .find(|info| info.opcode == CPY_REG_REG)
+            .unwrap();
+        assert_eq!(cpy.mnemonic, "CPY");
+        assert_eq!(cpy.params, vec![Parameters::DATA_REG, Parameters::DATA_REG]);
+        assert_eq!(cpy.byte_count, 3);
+
+        for opcode in ALL_OPS.iter() {
+            assert!(
+                op_table().iter().any(|info| info.opcode == *opcode),
+                "{:02X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn test_next_operand() {
+        assert_eq!(next_operand("add", &[]), Some(Parameters::DATA_REG));
+        assert_eq!(
+            next_operand("add", &[Param::DataReg(REG_D3)]),
+            Some(Parameters::DATA_REG | Parameters::NUMBER | Parameters::ADDR_REG)
+        );
+        assert_eq!(
+            next_operand("add", &[Param::DataReg(REG_D3), Param::Number(1)]),
+            None
+        );
+        assert_eq!(next_operand("ADD", &[Param::Number(1)]), None);
+        assert_eq!(next_operand("halt", &[]), None);
+        assert_eq!(next_operand("unknown", &[]), None);
     }
 }
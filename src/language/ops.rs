@@ -3,164 +3,254 @@ use crate::language::parser::ops::Op;
 use lazy_static::lazy_static;
 
 //To add new operation the following files must be updated:
-//language/ops.rs
+//language/ops.rs (including `with_docs`)
 //constants.rs
 //decompiler/mod.rs
 //device/internal.rs
 //language.md
 
 lazy_static! {
-    pub static ref OPS: [Op; 47] = [
+    pub static ref OPS: [Op; 70] = [
         //CPY reg reg, reg val, areg areg, areg label|addr, areg reg reg, reg reg areg, reg areg
         //Copy value from 2nd param to 1st
-        Op::new_reg_complex("CPY", CPY_REG_REG, CPY_REG_VAL, CPY_AREG_AREG, CPY_AREG_ADDR, CPY_AREG_REG_REG, CPY_REG_REG_AREG, CPY_REG_AREG),
+        Op::new_reg_complex("CPY", CPY_REG_REG, CPY_REG_VAL, CPY_AREG_AREG, CPY_AREG_ADDR, CPY_AREG_REG_REG, CPY_REG_REG_AREG, CPY_REG_AREG).with_docs("Copy value from 2nd param to 1st", "cpy d0 10"),
         //ADD reg reg|val|addr_reg
         //Add 1st and 2nd params and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("ADD", ADD_REG_REG, ADD_REG_VAL, ADD_REG_AREG),
+        Op::new_reg_val("ADD", ADD_REG_REG, ADD_REG_VAL, ADD_REG_AREG).with_docs("Add 1st and 2nd params and store in ACC", "add d0 d1"),
         //ADD reg reg|val|addr_reg
         //Subtract 2nd param from 1st and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("SUB", SUB_REG_REG, SUB_REG_VAL, SUB_REG_AREG),
+        Op::new_reg_val("SUB", SUB_REG_REG, SUB_REG_VAL, SUB_REG_AREG).with_docs("Subtract 2nd param from 1st and store in ACC", "sub d0 1"),
         //CMP reg reg, reg val, areg areg, areg label|addr, areg reg reg, reg reg areg, reg areg
         //Compare values in 1st and 2nd params, store result in ACC (0 = Equal, 1 = Lesser, 2 = Greater)
-        Op::new_reg_complex("CMP", CMP_REG_REG, CMP_REG_VAL, CMP_AREG_AREG, CMP_AREG_ADDR, CMP_AREG_REG_REG, CMP_REG_REG_AREG, CMP_REG_AREG),
+        Op::new_reg_complex("CMP", CMP_REG_REG, CMP_REG_VAL, CMP_AREG_AREG, CMP_AREG_ADDR, CMP_AREG_REG_REG, CMP_REG_REG_AREG, CMP_REG_AREG).with_docs("Compare 1st and 2nd params, store result in ACC (0 = Equal, 1 = Lesser, 2 = Greater)", "cmp d0 10"),
         //LD areg data_key (reg reg, reg val, val reg, val val)
         //Load address of indexed data (params 2 to 4) into 1st param
-        Op::new_data("LD", LD_AREG_DATA_REG_REG, LD_AREG_DATA_REG_VAL, LD_AREG_DATA_VAL_REG, LD_AREG_DATA_VAL_VAL),
+        Op::new_data("LD", LD_AREG_DATA_REG_REG, LD_AREG_DATA_REG_VAL, LD_AREG_DATA_VAL_REG, LD_AREG_DATA_VAL_VAL).with_docs("Load address of indexed data into 1st param", "ld a0 nums 1 0"),
         //JMP addr|lbl|addr_reg
         //Jump to instruction at 1st param
-        Op::new_jmp("JMP", JMP_ADDR, JMP_AREG),
+        Op::new_jmp("JMP", JMP_ADDR, JMP_AREG).with_docs("Jump to 1st param", "jmp loop"),
         //JE addr|lbl|addr_reg
         //Jump to instruction at 1st param if ACC == 0 (Equal)
-        Op::new_jmp("JE", JE_ADDR, JE_AREG),
+        Op::new_jmp("JE", JE_ADDR, JE_AREG).with_docs("Jump to 1st param if ACC == 0 (Equal)", "je done"),
         //JNE addr|lbl|addr_reg
         //Jump to instruction at 1st param if ACC != 0 (Equal)
-        Op::new_jmp("JNE", JNE_ADDR, JNE_AREG),
+        Op::new_jmp("JNE", JNE_ADDR, JNE_AREG).with_docs("Jump to 1st param if ACC != 0 (Equal)", "jne loop"),
         //JG addr|lbl|addr_reg
         //Jump to instruction at 1st param if ACC == 2 (Greater)
-        Op::new_jmp("JG", JG_ADDR, JG_AREG),
+        Op::new_jmp("JG", JG_ADDR, JG_AREG).with_docs("Jump to 1st param if ACC == 2 (Greater)", "jg bigger"),
         //JL addr|lbl|addr_reg
         //Jump to instruction at 1st param if ACC == 1 (Lesser)
-        Op::new_jmp("JL", JL_ADDR, JL_AREG),
+        Op::new_jmp("JL", JL_ADDR, JL_AREG).with_docs("Jump to 1st param if ACC == 1 (Lesser)", "jl smaller"),
         //OVER addr|lbl|addr_reg
         //Jump to instruction at 1st param if overflow flag is set
-        Op::new_jmp("OVER", OVER_ADDR, OVER_AREG),
+        Op::new_jmp("OVER", OVER_ADDR, OVER_AREG).with_docs("Jump to 1st param if overflow flag is set", "over overflowed"),
         //NOVER addr|lbl|addr_reg
         //Jump to instruction at 1st param if overflow flag is not set
-        Op::new_jmp("NOVER", NOVER_ADDR, NOVER_AREG),
+        Op::new_jmp("NOVER", NOVER_ADDR, NOVER_AREG).with_docs("Jump to 1st param if overflow flag is not set", "nover ok"),
+        //DJNZ addr_reg addr|lbl
+        //Decrement 1st param (wrapping) and jump to 2nd param if it isn't 0
+        Op::new_areg_jmp("DJNZ", DJNZ_AREG_ADDR).with_docs("Decrement 1st param and jump to 2nd param if it isn't 0", "djnz a0 loop"),
         //HALT
         //Stop program execution
-        Op::new_none("HALT", HALT),
+        Op::new_none("HALT", HALT).with_docs("Stop program execution", "halt"),
         //FOPEN reg|val
         //Opens input/data file <num> or crashes, saves length to [D0][D1][D2][D3]
-        Op::new_regval("FOPEN", FOPEN_REG, FOPEN_VAL),
+        Op::new_regval("FOPEN", FOPEN_REG, FOPEN_VAL).with_docs("Open file <num> and save length to D0-D3", "fopen 0"),
         //NOP
         //Does nothing
-        Op::new_none("NOP", NOP),
+        Op::new_none("NOP", NOP).with_docs("Does nothing", "nop"),
         //RET
         //Return from subroutine
-        Op::new_none("RET", RET),
+        Op::new_none("RET", RET).with_docs("Return from subroutine", "ret"),
         //FSEEK reg|val
         //Move file <num> cursor to [D0][D1][D2][D3]
-        Op::new_regval("FSEEK", FSEEK_REG, FSEEK_VAL),
+        Op::new_regval("FSEEK", FSEEK_REG, FSEEK_VAL).with_docs("Move file <num> cursor to D0-D3", "fseek 0"),
         //SWP reg reg, areg areg
         //Swaps contents of 1st param and 2nd param
-        Op::new_either_reg_reg("SWP", SWP_REG_REG, SWP_AREG_AREG),
+        Op::new_either_reg_reg("SWP", SWP_REG_REG, SWP_AREG_AREG).with_docs("Swap contents of 1st and 2nd params", "swp d0 d1"),
         //INC reg|addr_reg
         //Increment 1st param by 1
-        Op::new_single_reg("INC", INC_REG),
+        Op::new_single_reg("INC", INC_REG).with_docs("Increment 1st param by 1", "inc d0"),
         //DEC reg|addr_reg
         //Decrement 1st param by 1
-        Op::new_single_reg("DEC", DEC_REG),
+        Op::new_single_reg("DEC", DEC_REG).with_docs("Decrement 1st param by 1", "dec a0"),
         //MEMR addr|addr_reg
         //Read byte at 1st param in memory and store in ACC
-        Op::new_mem("MEMR", MEMR_ADDR, MEMR_AREG),
+        Op::new_mem("MEMR", MEMR_ADDR, MEMR_AREG).with_docs("Read byte at 1st param in memory and store in ACC", "memr @100"),
         //MEMW addr|addr_reg
         //Write byte in ACC and write to byte at 1st param in memory
-        Op::new_mem("MEMW", MEMW_ADDR, MEMW_AREG),
+        Op::new_mem("MEMW", MEMW_ADDR, MEMW_AREG).with_docs("Write byte in ACC to 1st param in memory", "memw a0"),
+        //LDA reg addr
+        //Read byte at 2nd param in memory and store in 1st param
+        Op::new_reg_addr("LDA", LDA_REG_ADDR).with_docs("Read byte at 2nd param in memory and store in 1st param", "lda d0 @100"),
+        //STA addr reg
+        //Write byte in 2nd param to 1st param in memory
+        Op::new_addr_reg("STA", STA_ADDR_REG).with_docs("Write byte in 2nd param to 1st param in memory", "sta @100 d0"),
+        //LDSTR addr_reg reg key
+        //Copy string named by 3rd param to memory at 1st param, advance 1st param past it and store length in 2nd param
+        Op::new_areg_reg_string("LDSTR", LDSTR_AREG_REG_STR).with_docs("Copy string named by 3rd param to memory at 1st param, advance 1st param and store length in 2nd param", "ldstr a0 d0 greeting"),
         //FILER reg|val addr|addr_reg
         //Read ACC bytes from file <num> cursor and write to 1st param in memory, sets read byte count in ACC
-        Op::new_file_mem("FILER", FILER_REG_ADDR, FILER_REG_AREG, FILER_VAL_ADDR, FILER_VAL_AREG),
+        Op::new_file_mem("FILER", FILER_REG_ADDR, FILER_REG_AREG, FILER_VAL_ADDR, FILER_VAL_AREG).with_docs("Read ACC bytes from file to 2nd param in memory", "filer 0 @100"),
         //FILEW reg|val addr|addr_reg
         //Write ACC bytes starting at 1st param in memory to file <num> cursor, sets written byte count in ACC
-        Op::new_file_mem_value("FILEW", FILEW_REG_ADDR, FILEW_REG_AREG, FILEW_VAL_ADDR, FILEW_VAL_AREG, FILEW_REG_REG, FILEW_REG_VAL, FILEW_VAL_REG, FILEW_VAL_VAL),
+        Op::new_file_mem_value("FILEW", FILEW_REG_ADDR, FILEW_REG_AREG, FILEW_VAL_ADDR, FILEW_VAL_AREG, FILEW_REG_REG, FILEW_REG_VAL, FILEW_VAL_REG, FILEW_VAL_VAL).with_docs("Write ACC bytes from 2nd param in memory to file", "filew 0 a0"),
         //PRT reg|val|addr_reg
         //Prints value in 1st param (addr_reg must point to data)
-        Op::new_regvaldata("PRT", PRT_REG, PRT_VAL, PRT_AREG),
-        //PRTLN
-        //Prints new line
-        Op::new_none("PRTLN", PRTLN),
+        Op::new_regvaldata("PRT", PRT_REG, PRT_VAL, PRT_AREG).with_docs("Print value of 1st param", "prt d0"),
+        //PRTLN [key]
+        //Prints string named by 1st param (if set) then a new line
+        Op::new_none_or_string("PRTLN", PRTLN, PRTLN_STR).with_docs("Print string named by 1st param (if set) then a new line", "prtln"),
         //PRTC reg|val|addr_reg
         //Prints value in 1st param as ASCII (addr_reg must point to data)
-        Op::new_regvaldata("PRTC", PRTC_REG, PRTC_VAL, PRTC_AREG),
+        Op::new_regvaldata("PRTC", PRTC_REG, PRTC_VAL, PRTC_AREG).with_docs("Print value of 1st param as ASCII", "prtc 'a'"),
         //FSKIP reg|val reg|val
         //Move file <num> cursor forward by number of bytes set by 1st param
-        Op::new_regval_regval("FSKIP", FSKIP_REG_REG, FSKIP_REG_VAL, FSKIP_VAL_REG, FSKIP_VAL_VAL),
+        Op::new_regval_regval("FSKIP", FSKIP_REG_REG, FSKIP_REG_VAL, FSKIP_VAL_REG, FSKIP_VAL_VAL).with_docs("Move file cursor forward by 2nd param bytes", "fskip 0 d0"),
         //PRTS key
         //Prints string named by 1st param
-        Op::new_string("PRTS", PRTS_STR),
+        Op::new_string("PRTS", PRTS_STR).with_docs("Print string named by 1st param", "prts greeting"),
         //PRTD addr_reg
         //Prints ACC bytes from data starting at by 1st param
-        Op::new_areg("PRTD", PRTD_AREG),
+        Op::new_areg("PRTD", PRTD_AREG).with_docs("Print ACC bytes from data starting at 1st param", "prtd a0"),
         //CALL addr|lbl|addr_reg
         //Jump to 1st param, setup stack to allow RET
-        Op::new_jmp("CALL", CALL_ADDR, CALL_AREG),
+        Op::new_jmp("CALL", CALL_ADDR, CALL_AREG).with_docs("Jump to 1st param, setup stack to allow RET", "call func"),
         //PUSH addr_reg|reg|val
         //Push 1st param in to stack
-        Op::new_addrregval("PUSH", PUSH_REG, PUSH_VAL),
+        Op::new_addrregval("PUSH", PUSH_REG, PUSH_VAL).with_docs("Push 1st param on to stack", "push d0"),
         //POP addr_reg|reg
         //Pop value from stack to 1st param
-        Op::new_single_reg("POP", POP_REG),
+        Op::new_single_reg("POP", POP_REG).with_docs("Pop value from stack to 1st param", "pop d0"),
         //ARG addr_reg|reg reg|val
         //Read from value from stack 2nd param bytes before the FP and save to 1st param
-        Op::new_addrreg_regval("ARG", ARG_REG_REG, ARG_REG_VAL),
+        Op::new_addrreg_regval("ARG", ARG_REG_REG, ARG_REG_VAL).with_docs("Read value from stack 2nd param bytes before the FP into 1st param", "arg d0 1"),
         //IPOLL addr_reg|addr
         //Jump to 1st param if at least one char can be read from keyboard
-        Op::new_jmp("IPOLL", IPOLL_ADDR, IPOLL_AREG),
+        Op::new_jmp("IPOLL", IPOLL_ADDR, IPOLL_AREG).with_docs("Jump to 1st param if at least one char can be read from keyboard", "ipoll key_pressed"),
         //FCHK reg|val addr_reg|addr
         //Jump to 1st param if input file <num> is available
-        Op::new_regval_jmp("FCHK", FCHK_REG_ADDR, FCHK_REG_AREG, FCHK_VAL_ADDR, FCHK_VAL_AREG),
+        Op::new_regval_jmp("FCHK", FCHK_REG_ADDR, FCHK_REG_AREG, FCHK_VAL_ADDR, FCHK_VAL_AREG).with_docs("Jump to 2nd param if input file <num> is available", "fchk 0 has_file"),
         //MEMP addr_reg|addr
         //Print ACC chars from 1st param in memory or data
-        Op::new_mem("MEMP", MEMP_ADDR, MEMP_AREG),
+        Op::new_mem("MEMP", MEMP_ADDR, MEMP_AREG).with_docs("Print ACC chars from 1st param in memory", "memp @100"),
         //RSTR addr_reg|addr
         //Read up to chars keyboard (until return is pressed or 255 entered) starting at 1st param in memory
-        Op::new_mem("RSTR", RSTR_ADDR, RSTR_AREG),
+        Op::new_mem("RSTR", RSTR_ADDR, RSTR_AREG).with_docs("Read chars from keyboard into memory starting at 1st param", "rstr a0"),
         //RCHR reg
         //Read one char from keyboard into 1st param
-        Op::new_single_reg("RCHR", RCHR_REG),
+        Op::new_single_reg("RCHR", RCHR_REG).with_docs("Read one char from keyboard into 1st param", "rchr d0"),
+        //INPUT reg
+        //Read one byte from the input source (or stdin) into 1st param, sets EOF flag at the end
+        Op::new_single_reg("INPUT", INPUT_REG).with_docs("Read one byte from input into 1st param, sets EOF flag at the end", "input d0"),
         //RAND reg
         //Generate a pseudorandom number and put in 1st param
-        Op::new_single_reg("RAND", RAND_REG),
+        Op::new_single_reg("RAND", RAND_REG).with_docs("Generate a pseudorandom number and put in 1st param", "rand d0"),
         //SEED reg
         //Set the seed for the rng
-        Op::new_single_reg("SEED", SEED_REG),
+        Op::new_single_reg("SEED", SEED_REG).with_docs("Set the seed for the rng", "seed d0"),
         //TIME
         //Populates D0 with seconds, D1 with minutes, D2 with hours
-        Op::new_none("TIME", TIME),
+        Op::new_none("TIME", TIME).with_docs("Populate D0 with seconds, D1 with minutes, D2 with hours", "time"),
+        //RDTICK reg|addr_reg
+        //Read the tick counter into 1st param (only the low byte for reg)
+        Op::new_reg_areg("RDTICK", RDTICK_REG, RDTICK_AREG).with_docs("Read the tick counter into 1st param (only the low byte for reg)", "rdtick a0"),
         //AND reg reg|val|addr_reg
         //and bits of 1st and 2nd params and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("AND", AND_REG_REG, AND_REG_VAL, AND_REG_AREG),
+        Op::new_reg_val("AND", AND_REG_REG, AND_REG_VAL, AND_REG_AREG).with_docs("And bits of 1st and 2nd params and store in ACC", "and d0 b00001111"),
         //OR reg reg|val|addr_reg
         //or bits of 1st and 2nd params and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("OR", OR_REG_REG, OR_REG_VAL, OR_REG_AREG),
+        Op::new_reg_val("OR", OR_REG_REG, OR_REG_VAL, OR_REG_AREG).with_docs("Or bits of 1st and 2nd params and store in ACC", "or d0 d1"),
         //XOR reg reg|val|addr_reg
         //xor bits of 1st and 2nd params and store in ACC (addr_reg must point to data)
-        Op::new_reg_val("XOR", XOR_REG_REG, XOR_REG_VAL, XOR_REG_AREG),
+        Op::new_reg_val("XOR", XOR_REG_REG, XOR_REG_VAL, XOR_REG_AREG).with_docs("Xor bits of 1st and 2nd params and store in ACC", "xor d0 xFF"),
         //NOT reg|addr_reg
         //not bits of 1st param (addr_reg must point to data)
-        Op::new_single_reg("NOT", NOT_REG),
+        Op::new_single_reg("NOT", NOT_REG).with_docs("Not bits of 1st param and store in ACC", "not d0"),
+        //ROL reg val
+        //rotate bits of 1st param left by 2nd param and store in ACC, overflow is set to the last bit rotated
+        Op::new_reg_num("ROL", ROL_REG_VAL).with_docs("Rotate bits of 1st param left by 2nd param and store in ACC", "rol d0 1"),
+        //ROR reg val
+        //rotate bits of 1st param right by 2nd param and store in ACC, overflow is set to the last bit rotated
+        Op::new_reg_num("ROR", ROR_REG_VAL).with_docs("Rotate bits of 1st param right by 2nd param and store in ACC", "ror d0 4"),
         //DEBUG
         //Prints dump from system
-        Op::new_none("DEBUG", DEBUG),
+        Op::new_none("DEBUG", DEBUG).with_docs("Print dump from system", "debug"),
+        //ASSERT reg val
+        //Stop with an error if 1st param doesn't equal 2nd param
+        Op::new_reg_num("ASSERT", ASSERT_REG_VAL).with_docs("Stop with an error if 1st param doesn't equal 2nd param", "assert d0 10"),
+        //DUP
+        //Push a copy of the top byte of the stack
+        Op::new_none("DUP", DUP).with_docs("Push a copy of the top byte of the stack", "dup"),
+        //SWAP
+        //Swap the top two bytes of the stack
+        Op::new_none("SWAP", SWAP).with_docs("Swap the top two bytes of the stack", "swap"),
+        //RDSP addr_reg
+        //Copy the stack pointer into 1st param
+        Op::new_areg("RDSP", RDSP_AREG).with_docs("Copy the stack pointer into 1st param", "rdsp a0"),
+        //CLR reg
+        //Set 1st param to 0 and clear overflow flag
+        Op::new_single_reg("CLR", CLR_REG).with_docs("Set 1st param to 0 and clear overflow flag", "clr d1"),
+        //NEG reg
+        //Negate 1st param (as i8) and store in ACC
+        Op::new_single_reg("NEG", NEG_REG).with_docs("Negate 1st param (as i8) and store in ACC", "neg d0"),
+        //ABS reg
+        //Absolute value of 1st param (as i8) and store in ACC
+        Op::new_single_reg("ABS", ABS_REG).with_docs("Absolute value of 1st param (as i8) and store in ACC", "abs d0"),
+        //MIN reg reg
+        //Store the smaller of 1st and 2nd params in ACC
+        Op::new_reg_reg("MIN", MIN_REG_REG).with_docs("Store the smaller of 1st and 2nd params in ACC", "min d0 d1"),
+        //MAX reg reg
+        //Store the larger of 1st and 2nd params in ACC
+        Op::new_reg_reg("MAX", MAX_REG_REG).with_docs("Store the larger of 1st and 2nd params in ACC", "max d0 d1"),
+        //SMIN reg reg
+        //Store the smaller of 1st and 2nd params (as i8) in ACC
+        Op::new_reg_reg("SMIN", SMIN_REG_REG).with_docs("Store the smaller of 1st and 2nd params (as i8) in ACC", "smin d0 d1"),
+        //SMAX reg reg
+        //Store the larger of 1st and 2nd params (as i8) in ACC
+        Op::new_reg_reg("SMAX", SMAX_REG_REG).with_docs("Store the larger of 1st and 2nd params (as i8) in ACC", "smax d0 d1"),
+        //LDF reg val
+        //Load byte at FP - 2nd param into 1st param
+        Op::new_reg_num("LDF", LDF_REG_VAL).with_docs("Load byte at FP - 2nd param into 1st param", "ldf d0 1"),
+        //STF reg val
+        //Store 1st param in byte at FP - 2nd param
+        Op::new_reg_num("STF", STF_REG_VAL).with_docs("Store 1st param in byte at FP - 2nd param", "stf d0 1"),
+        //ENTER val
+        //Push FP, set FP to SP and reserve 1st param bytes for locals
+        Op::new_num("ENTER", ENTER_VAL).with_docs("Push FP, set FP to SP and reserve 1st param bytes for locals", "enter 4"),
+        //LEAVE
+        //Set SP to FP and pop FP, undoing ENTER
+        Op::new_none("LEAVE", LEAVE).with_docs("Set SP to FP and pop FP, undoing ENTER", "leave"),
     ];
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::language::parse_line;
 
     #[test]
     fn check_for_missing_ops() {
         assert_eq!(MNEMONICS.len(), OPS.len())
     }
+
+    #[test]
+    fn check_for_missing_descriptions() {
+        for op in OPS.iter() {
+            assert!(!op.description().is_empty(), "{}", op.mnemonic());
+        }
+    }
+
+    #[test]
+    fn check_examples_parse() {
+        for op in OPS.iter() {
+            let example = op.example();
+            assert!(
+                parse_line(example).is_ok(),
+                "{}: {}",
+                op.mnemonic(),
+                example
+            );
+        }
+    }
 }
@@ -1,10 +1,14 @@
-use crate::language::parser::params::{Param, Parameters};
-use anyhow::{Error, Result};
+use crate::language::parser::params::{char_literal_error, Param, Parameters};
+use anyhow::Result;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
 pub struct Op {
     mnemonic: &'static str,
+    ///One line description, for use by external tools
+    description: &'static str,
+    ///Short usage example, labels and keys are placeholders
+    example: &'static str,
     variants: Vec<OpVariant>,
 }
 
@@ -16,25 +20,110 @@ impl Op {
     pub fn error_text(&self) -> String {
         let mut output = format!("{} supports:", self.mnemonic);
         for variant in self.variants.iter() {
-            output.push_str(&format!("\n{} {}", self.mnemonic, variant))
+            output.push_str(format!("\n{} {}", self.mnemonic, variant).trim_end())
         }
         output
     }
 
-    pub fn parse(&self, parts: &[&str]) -> Option<(u8, Vec<Param>)> {
+    ///If no variant matches the error is `expected N operands, got M` when no variant takes that many operands,
+    ///otherwise it describes the operand that got furthest before failing, e.g. `operand 3 (data_reg|byte): got 'foo'`
+    pub fn parse(&self, parts: &[&str]) -> Result<(u8, Vec<Param>), String> {
+        let mut counts: Vec<usize> = self
+            .variants
+            .iter()
+            .map(|variant| variant.params.len())
+            .collect();
+        counts.sort_unstable();
+        counts.dedup();
+        if !counts.contains(&parts.len()) {
+            return Err(arity_error(&counts, parts.len()));
+        }
+
+        let mut furthest: Option<(usize, Vec<String>)> = None;
         for variant in self.variants.iter() {
-            if let Ok(params) = variant.parse(parts) {
-                return Some((variant.opcode, params));
+            match variant.parse(parts) {
+                Ok(params) => return Ok((variant.opcode, params)),
+                Err(Some(idx)) => {
+                    let expected = variant.params[idx]
+                        .to_string()
+                        .trim_start_matches('(')
+                        .trim_end_matches(')')
+                        .to_string();
+                    match &mut furthest {
+                        Some((furthest_idx, kinds)) if *furthest_idx == idx => {
+                            if !kinds.contains(&expected) {
+                                kinds.push(expected);
+                            }
+                        }
+                        Some((furthest_idx, _)) if *furthest_idx > idx => {}
+                        _ => furthest = Some((idx, vec![expected])),
+                    }
+                }
+                Err(None) => {}
             }
         }
-        None
+        Err(match furthest {
+            Some((idx, kinds)) => match char_literal_error(parts[idx]) {
+                Some(err) => format!("operand {} ({}): {}", idx + 1, kinds.join("|"), err),
+                None => format!(
+                    "operand {} ({}): got '{}'",
+                    idx + 1,
+                    kinds.join("|"),
+                    parts[idx]
+                ),
+            },
+            None => arity_error(&counts, parts.len()),
+        })
+    }
+
+    pub fn decode(&self, opcode: u8, bytes: &[u8]) -> Option<Vec<Param>> {
+        self.variants
+            .iter()
+            .find(|variant| variant.opcode == opcode)
+            .and_then(|variant| variant.decode(bytes))
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        self.mnemonic
+    }
+
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    pub fn example(&self) -> &'static str {
+        self.example
+    }
+
+    ///Returns the opcode and params for each variant
+    pub fn variants(&self) -> Vec<(u8, Vec<Parameters>)> {
+        self.variants
+            .iter()
+            .map(|variant| (variant.opcode, variant.params.clone()))
+            .collect()
+    }
+
+    pub fn mnemonic_for(&self, opcode: u8) -> Option<&'static str> {
+        if self.variants.iter().any(|variant| variant.opcode == opcode) {
+            Some(self.mnemonic)
+        } else {
+            None
+        }
     }
 }
 
 impl Op {
+    pub fn with_docs(mut self, description: &'static str, example: &'static str) -> Self {
+        self.description = description;
+        self.example = example;
+        self
+    }
+
     pub fn new_none(mnemonic: &'static str, opcode: u8) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![OpVariant::new(opcode, vec![])],
         }
     }
@@ -42,13 +131,45 @@ impl Op {
     pub fn new_string(mnemonic: &'static str, opcode: u8) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![OpVariant::new(opcode, vec![Parameters::STRING_KEY])],
         }
     }
 
+    pub fn new_areg_reg_string(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![
+                    Parameters::ADDR_REG,
+                    Parameters::DATA_REG,
+                    Parameters::STRING_KEY,
+                ],
+            )],
+        }
+    }
+
+    pub fn new_none_or_string(mnemonic: &'static str, opcode_none: u8, opcode_str: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![
+                OpVariant::new(opcode_none, vec![]),
+                OpVariant::new(opcode_str, vec![Parameters::STRING_KEY]),
+            ],
+        }
+    }
+
     pub fn new_areg(mnemonic: &'static str, opcode: u8) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![OpVariant::new(opcode, vec![Parameters::ADDR_REG])],
         }
     }
@@ -61,6 +182,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(opcode_reg, vec![Parameters::DATA_REG]),
                 OpVariant::new(opcode_val, vec![Parameters::NUMBER]),
@@ -72,6 +195,8 @@ impl Op {
     pub fn new_regval(mnemonic: &'static str, opcode_reg: u8, opcode_val: u8) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(opcode_reg, vec![Parameters::DATA_REG]),
                 OpVariant::new(opcode_val, vec![Parameters::NUMBER]),
@@ -79,9 +204,23 @@ impl Op {
         }
     }
 
+    pub fn new_reg_num(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::DATA_REG, Parameters::NUMBER],
+            )],
+        }
+    }
+
     pub fn new_addrregval(mnemonic: &'static str, opcode_reg: u8, opcode_val: u8) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(opcode_reg, vec![Parameters::REGISTERS]),
                 OpVariant::new(opcode_val, vec![Parameters::NUMBER]),
@@ -96,6 +235,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_regreg,
@@ -109,9 +250,68 @@ impl Op {
         }
     }
 
+    pub fn new_reg_areg(mnemonic: &'static str, opcode_reg: u8, opcode_areg: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![
+                OpVariant::new(opcode_reg, vec![Parameters::DATA_REG]),
+                OpVariant::new(opcode_areg, vec![Parameters::ADDR_REG]),
+            ],
+        }
+    }
+
+    pub fn new_reg_reg(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::DATA_REG, Parameters::DATA_REG],
+            )],
+        }
+    }
+
+    pub fn new_reg_addr(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::DATA_REG, Parameters::ADDRESS],
+            )],
+        }
+    }
+
+    pub fn new_addr_reg(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::ADDRESS, Parameters::DATA_REG],
+            )],
+        }
+    }
+
+    pub fn new_num(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![OpVariant::new(opcode, vec![Parameters::NUMBER])],
+        }
+    }
+
     pub fn new_single_reg(mnemonic: &'static str, opcode: u8) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![OpVariant::new(opcode, vec![Parameters::REGISTERS])],
         }
     }
@@ -125,6 +325,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_reg_addr,
@@ -160,6 +362,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_reg_addr,
@@ -197,6 +401,8 @@ impl Op {
     pub fn new_mem(mnemonic: &'static str, opcode_addr: u8, opcode_addr_reg: u8) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(opcode_addr, vec![Parameters::ADDRESS]),
                 OpVariant::new(opcode_addr_reg, vec![Parameters::ADDR_REG]),
@@ -213,6 +419,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_reg_reg,
@@ -239,6 +447,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_reg_reg,
@@ -263,6 +473,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_reg_reg,
@@ -289,6 +501,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_dreg_dreg,
@@ -339,6 +553,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_areg_data_reg_reg,
@@ -383,6 +599,8 @@ impl Op {
     pub fn new_jmp(mnemonic: &'static str, opcode_addr: u8, opcode_addr_reg: u8) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(opcode_addr_reg, vec![Parameters::ADDR_REG]),
                 OpVariant::new(opcode_addr, vec![Parameters::ADDRESSES]),
@@ -390,6 +608,18 @@ impl Op {
         }
     }
 
+    pub fn new_areg_jmp(mnemonic: &'static str, opcode: u8) -> Self {
+        Op {
+            mnemonic,
+            description: "",
+            example: "",
+            variants: vec![OpVariant::new(
+                opcode,
+                vec![Parameters::ADDR_REG, Parameters::ADDRESSES],
+            )],
+        }
+    }
+
     pub fn new_regval_jmp(
         mnemonic: &'static str,
         opcode_reg_addr: u8,
@@ -399,6 +629,8 @@ impl Op {
     ) -> Self {
         Op {
             mnemonic,
+            description: "",
+            example: "",
             variants: vec![
                 OpVariant::new(
                     opcode_reg_addr_reg,
@@ -426,21 +658,47 @@ struct OpVariant {
     params: Vec<Parameters>,
 }
 
+///`counts` must be sorted, e.g. `expected 0 or 1 operands, got 2`
+fn arity_error(counts: &[usize], given: usize) -> String {
+    let expected = match counts.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!(
+            "{} or {}",
+            rest.iter()
+                .map(|count| count.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+            last
+        ),
+        None => String::from("0"),
+    };
+    let plural = if counts == [1] { "" } else { "s" };
+    format!("expected {} operand{}, got {}", expected, plural, given)
+}
+
 impl OpVariant {
-    #[allow(clippy::len_zero)]
-    fn parse(&self, input: &[&str]) -> Result<Vec<Param>> {
+    ///Errors with the index of the operand that couldn't be parsed, or None if the number of operands is wrong
+    fn parse(&self, input: &[&str]) -> Result<Vec<Param>, Option<usize>> {
+        if input.len() != self.params.len() {
+            return Err(None);
+        }
+        self.params
+            .iter()
+            .zip(input)
+            .enumerate()
+            .map(|(idx, (param, text))| param.parse(text).map_err(|_| Some(idx)))
+            .collect()
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<Vec<Param>> {
         let mut output = vec![];
-        if input.len() > self.params.len() {
-            return Err(Error::msg("Too many operands"));
-        }
-        for (idx, param) in self.params.iter().enumerate() {
-            if input.len() > idx {
-                output.push(param.parse(input[idx])?);
-            } else {
-                return Err(Error::msg("Missing operands"));
-            }
+        let mut idx = 0;
+        for param in self.params.iter() {
+            let (value, count) = param.decode(&bytes[idx.min(bytes.len())..])?;
+            output.push(value);
+            idx += count;
         }
-        Ok(output)
+        Some(output)
     }
 }
 
@@ -3,6 +3,8 @@ use anyhow::{Error, Result};
 use serde::Serialize;
 use std::fmt;
 use std::fmt::{Display, Formatter};
+use std::num::IntErrorKind;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub enum Param {
@@ -11,12 +13,59 @@ pub enum Param {
     AddrReg(u8),
     Addr(u16),
     Label(String),
+    ///`&label`, the byte address of a label used as a value
+    LabelAddr(String),
     StrKey(String),
     DataKey(String),
 }
 
+impl Display for Param {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Param::Number(num) => write!(f, "{}", num),
+            Param::DataReg(reg) | Param::AddrReg(reg) => {
+                write!(f, "{}", reg_name(*reg).to_ascii_lowercase())
+            }
+            Param::Addr(addr) => write!(f, "@{}", addr),
+            Param::Label(text) | Param::StrKey(text) | Param::DataKey(text) => {
+                write!(f, "{}", text)
+            }
+            Param::LabelAddr(text) => write!(f, "&{}", text),
+        }
+    }
+}
+
+///Parses any param without knowing the op
+///Keys can't be told apart from labels without the rest of the program so names are always returned as `Param::Label`
+impl FromStr for Param {
+    type Err = Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(Error::msg("Empty param"));
+        }
+        if let Ok(reg) = Parameters::REGISTERS.parse(input) {
+            return Ok(reg);
+        }
+        if input.starts_with('@') {
+            return parse_addr(input);
+        }
+        if let Ok(num) = parse_number(input) {
+            return Ok(num);
+        }
+        if let Some(err) = char_literal_error(input) {
+            return Err(Error::msg(err));
+        }
+        if input.starts_with(|chr: char| chr.is_ascii_digit() || chr == '\'') {
+            return Err(Error::msg(format!("Invalid number: {}", input)));
+        }
+        Ok(parse_label(input))
+    }
+}
+
 bitflags! {
-    pub(super) struct Parameters: u32 {
+    pub struct Parameters: u32 {
         const NUMBER =    0b00000001;
         const ADDRESS =   0b00000010;
         const DATA_REG =  0b00000100;
@@ -41,7 +90,27 @@ impl Display for Parameters {
             Parameters::STRING_KEY => write!(f, "text_key"),
             Parameters::ADDRESSES => write!(f, "(label|address)"),
             Parameters::REGISTERS => write!(f, "(data_reg|addr_reg)"),
-            _ => write!(f, ""),
+            //Any other combination (e.g. from `next_operand`) lists each kind
+            _ => {
+                let names = [
+                    Parameters::NUMBER,
+                    Parameters::ADDRESS,
+                    Parameters::DATA_REG,
+                    Parameters::ADDR_REG,
+                    Parameters::LABEL,
+                    Parameters::STRING_KEY,
+                    Parameters::DATA_KEY,
+                ]
+                .iter()
+                .filter(|kind| self.contains(**kind))
+                .map(|kind| kind.to_string())
+                .collect::<Vec<String>>();
+                if names.is_empty() {
+                    write!(f, "none")
+                } else {
+                    write!(f, "({})", names.join("|"))
+                }
+            }
         }
     }
 }
@@ -54,7 +123,7 @@ impl Parameters {
             Parameters::DATA_REG => parse_data_reg(input),
             Parameters::ADDR_REG => parse_addr_reg(input),
             Parameters::ADDRESS => parse_addr(input),
-            Parameters::LABEL => Ok(Param::Label(input.to_string())),
+            Parameters::LABEL => Ok(parse_label(input)),
             Parameters::DATA_KEY => Ok(Param::DataKey(input.to_string())),
             Parameters::STRING_KEY => Ok(Param::StrKey(input.to_string())),
             Parameters::REGISTERS => {
@@ -75,7 +144,7 @@ impl Parameters {
                 if let Ok(addr) = parse_addr(input) {
                     Ok(addr)
                 } else {
-                    Ok(Param::Label(input.to_string()))
+                    Ok(parse_label(input))
                 }
             }
             _ => panic!("Unhandled param: {:?}", self),
@@ -83,6 +152,36 @@ impl Parameters {
     }
 }
 
+impl Parameters {
+    /// Converts the start of `bytes` back into a param, returning it and the number of bytes used
+    /// Addresses and keys are all returned as `Param::Addr` as the names are lost during assembly
+    pub(super) fn decode(&self, bytes: &[u8]) -> Option<(Param, usize)> {
+        match *self {
+            Parameters::NUMBER => bytes.first().map(|num| (Param::Number(*num), 1)),
+            Parameters::DATA_REG => bytes.first().map(|reg| (Param::DataReg(*reg), 1)),
+            Parameters::ADDR_REG => bytes.first().map(|reg| (Param::AddrReg(*reg), 1)),
+            Parameters::REGISTERS => bytes.first().map(|reg| match *reg {
+                REG_A0 | REG_A1 => (Param::AddrReg(*reg), 1),
+                _ => (Param::DataReg(*reg), 1),
+            }),
+            _ => {
+                if bytes.len() >= 2 {
+                    Some((Param::Addr(u16::from_be_bytes([bytes[0], bytes[1]])), 2))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+fn parse_label(input: &str) -> Param {
+    match input.strip_prefix('&') {
+        Some(lbl) => Param::LabelAddr(lbl.to_string()),
+        None => Param::Label(input.to_string()),
+    }
+}
+
 fn strip_trailing_comment(input: &str) -> &str {
     let parts = input.split('#').collect::<Vec<&str>>();
     parts[0].trim()
@@ -110,13 +209,30 @@ fn parse_addr_reg(input: &str) -> Result<Param> {
     }
 }
 
-fn parse_number(input: &str) -> Result<Param> {
+pub(crate) fn parse_number(input: &str) -> Result<Param> {
+    if let Some(expr) = strip_parens(input) {
+        let value = eval_expr(expr)
+            .map_err(|err| Error::msg(format!("Error parsing number {}: {}", input, err)))?;
+        return if (0..=u8::MAX as i64).contains(&value) {
+            Ok(Param::Number(value as u8))
+        } else {
+            Err(Error::msg(format!(
+                "Error parsing number {}: result {} is out of range 0-255",
+                input, value
+            )))
+        };
+    }
     let num = if input.starts_with('x') {
         let hex = input.chars().skip(1).collect::<String>();
         u8::from_str_radix(&hex, 16)
     } else if input.starts_with('b') {
-        if input.len() == 9 {
-            let bin = input.chars().skip(1).collect::<String>();
+        //Bits can be separated by `_`, e.g. b1111_0000, so count digits rather than the input length
+        let bin = input
+            .chars()
+            .skip(1)
+            .filter(|chr| *chr != '_')
+            .collect::<String>();
+        if bin.len() == 8 {
             u8::from_str_radix(&bin, 2)
         } else {
             return Err(Error::msg(format!(
@@ -124,13 +240,10 @@ fn parse_number(input: &str) -> Result<Param> {
                 input
             )));
         }
+    } else if let Some(err) = char_literal_error(input) {
+        return Err(Error::msg(err));
     } else if input.len() == 3 && input.starts_with('\'') && input.ends_with('\'') {
-        let chr = input.chars().nth(1).unwrap();
-        if chr.is_ascii() {
-            Ok(chr as u8)
-        } else {
-            return Err(Error::msg(format!("Invalid char: {}", input)));
-        }
+        Ok(input.as_bytes()[1])
     } else {
         input.parse::<u8>()
     };
@@ -143,26 +256,134 @@ fn parse_number(input: &str) -> Result<Param> {
     }
 }
 
-fn parse_addr(input: &str) -> Result<Param> {
-    if !input.starts_with('@') {
-        return Err(Error::msg("Address must start with @"));
+///Error for a quoted single char that isn't ASCII, e.g. `'é'`, None for anything else
+///Chars are counted rather than bytes as non ASCII chars are more than one byte
+pub(crate) fn char_literal_error(input: &str) -> Option<String> {
+    let inner = input.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = inner.chars();
+    match (chars.next(), chars.next()) {
+        (Some(chr), None) if !chr.is_ascii() => Some(format!(
+            "char literals must be a single ASCII character; '{}' is not ASCII",
+            chr
+        )),
+        _ => None,
     }
-    let input = input.chars().skip(1).collect::<String>();
-    let num = if input.starts_with('x') {
-        let hex = input.chars().skip(1).collect::<String>();
-        u16::from_str_radix(&hex, 16)
+}
+
+pub(crate) fn parse_addr(input: &str) -> Result<Param> {
+    let digits = match input.strip_prefix('@') {
+        Some(digits) => digits,
+        None => {
+            return Err(Error::msg(format!(
+                "Invalid address {}: addresses must start with @",
+                input
+            )))
+        }
+    };
+    if let Some(expr) = strip_parens(digits) {
+        let value = eval_expr(expr)
+            .map_err(|err| Error::msg(format!("Invalid address {}: {}", input, err)))?;
+        return if (0..=u16::MAX as i64).contains(&value) {
+            Ok(Param::Addr(value as u16))
+        } else {
+            Err(Error::msg(format!(
+                "Invalid address {}: result {} is out of range, max is @65535 (@xFFFF)",
+                input, value
+            )))
+        };
+    }
+    let num = if let Some(hex) = digits.strip_prefix('x') {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(bin) = digits.strip_prefix('b') {
+        u16::from_str_radix(bin, 2)
     } else {
-        input.parse::<u16>()
+        digits.parse::<u16>()
     };
     match num {
         Ok(num) => Ok(Param::Addr(num)),
-        Err(err) => Err(Error::msg(format!(
-            "Error parsing number {}: {}",
-            input, err
+        Err(err) if err.kind() == &IntErrorKind::PosOverflow => Err(Error::msg(format!(
+            "Invalid address {}: max is @65535 (@xFFFF)",
+            input
+        ))),
+        Err(_) => Err(Error::msg(format!(
+            "Invalid address {}: addresses must start with @ followed by 0-65535 or x0-xFFFF",
+            input
         ))),
     }
 }
 
+fn strip_parens(input: &str) -> Option<&str> {
+    input
+        .strip_prefix('(')
+        .and_then(|expr| expr.strip_suffix(')'))
+}
+
+///Evaluates `+`, `-` and `*` over number literals and addresses, `*` is done before `+` and `-`
+///The result isn't range checked, that's left to the caller
+fn eval_expr(expr: &str) -> Result<i64> {
+    let mut total = 0_i64;
+    let mut sign = 1_i64;
+    let mut product: Option<i64> = None;
+    let mut chars = expr.char_indices().peekable();
+    let mut start = 0;
+    loop {
+        //char literals can contain operators so they need skipping
+        if expr[start..].starts_with('\'') {
+            chars.nth(2);
+        }
+        let end = loop {
+            match chars.next() {
+                Some((idx, '+')) | Some((idx, '-')) | Some((idx, '*')) if idx > start => {
+                    break Some(idx)
+                }
+                Some(_) => {}
+                None => break None,
+            }
+        };
+        let term = parse_term(&expr[start..end.unwrap_or(expr.len())])?;
+        product = Some(match product {
+            Some(value) => value
+                .checked_mul(term)
+                .ok_or_else(|| Error::msg("expression overflowed"))?,
+            None => term,
+        });
+        let op = end.map(|idx| &expr[idx..idx + 1]);
+        if op != Some("*") {
+            total = product
+                .take()
+                .and_then(|value| total.checked_add(sign * value))
+                .ok_or_else(|| Error::msg("expression overflowed"))?;
+            sign = if op == Some("-") { -1 } else { 1 };
+        }
+        match end {
+            Some(idx) => start = idx + 1,
+            None => return Ok(total),
+        }
+    }
+}
+
+fn parse_term(term: &str) -> Result<i64> {
+    let digits = term.strip_prefix('@').unwrap_or(term);
+    let value = if let Some(hex) = digits.strip_prefix('x') {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = digits.strip_prefix('b') {
+        i64::from_str_radix(bin, 2).ok()
+    } else if digits.len() == 3 && digits.starts_with('\'') && digits.ends_with('\'') {
+        digits
+            .chars()
+            .nth(1)
+            .filter(|chr| chr.is_ascii())
+            .map(|chr| chr as i64)
+    } else if digits.chars().all(|chr| chr.is_ascii_digit()) {
+        digits.parse::<i64>().ok()
+    } else {
+        None
+    };
+    value
+        .filter(|value| *value <= u16::MAX as i64)
+        .ok_or_else(|| Error::msg(format!("invalid term '{}' in expression", term)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -188,11 +409,45 @@ mod tests {
         assert_eq!(parse_addr("@xFFFF").unwrap(), Param::Addr(65535));
         assert!(parse_addr("@x1FFFF").is_err());
         assert!(parse_addr("@1x2").is_err());
+        assert_eq!(parse_addr("@b0").unwrap(), Param::Addr(0));
+        assert_eq!(parse_addr("@b101").unwrap(), Param::Addr(5));
+        assert_eq!(parse_addr("@b0000000100000000").unwrap(), Param::Addr(256));
+        assert_eq!(
+            parse_addr("@b1111111111111111").unwrap(),
+            Param::Addr(65535)
+        );
+        assert_eq!(
+            parse_addr("@b10000000000000000").unwrap_err().to_string(),
+            "Invalid address @b10000000000000000: max is @65535 (@xFFFF)"
+        );
+        assert!(parse_addr("@b").is_err());
+        assert!(parse_addr("@b102").is_err());
         assert!(parse_addr("a0").is_err());
         assert!(parse_addr("@x2p").is_err());
         assert!(parse_addr("@x").is_err());
     }
 
+    #[test]
+    fn test_address_errors() {
+        let message = |input: &str| parse_addr(input).unwrap_err().to_string();
+        assert_eq!(
+            message("@70000"),
+            "Invalid address @70000: max is @65535 (@xFFFF)"
+        );
+        assert_eq!(
+            message("@x1FFFF"),
+            "Invalid address @x1FFFF: max is @65535 (@xFFFF)"
+        );
+        assert_eq!(
+            message("10"),
+            "Invalid address 10: addresses must start with @"
+        );
+        assert_eq!(
+            message("@x2p"),
+            "Invalid address @x2p: addresses must start with @ followed by 0-65535 or x0-xFFFF"
+        );
+    }
+
     #[test]
     fn test_number_parsing() {
         assert_eq!(parse_number("10").unwrap(), Param::Number(10));
@@ -214,12 +469,109 @@ mod tests {
         assert_eq!(parse_number("'}'").unwrap(), Param::Number(125));
         assert!(parse_number("'s '").is_err());
         assert!(parse_number("''").is_err());
+        for chr in ["é", "😀"] {
+            let literal = format!("'{}'", chr);
+            let expected = format!(
+                "char literals must be a single ASCII character; '{}' is not ASCII",
+                chr
+            );
+            assert_eq!(parse_number(&literal).unwrap_err().to_string(), expected);
+            assert_eq!(literal.parse::<Param>().unwrap_err().to_string(), expected);
+        }
         assert_eq!(parse_number("b00000000").unwrap(), Param::Number(0));
         assert_eq!(parse_number("b11111111").unwrap(), Param::Number(255));
         assert_eq!(parse_number("b00001111").unwrap(), Param::Number(15));
         assert!(parse_number("b0").is_err());
         assert!(parse_number("b1").is_err());
         assert!(parse_number("b101010100111").is_err());
+        assert_eq!(parse_number("b1111_0000").unwrap(), Param::Number(240));
+        assert_eq!(parse_number("b11_11_00_01").unwrap(), Param::Number(241));
+        assert!(parse_number("b1111_000").is_err());
+        assert!(parse_number("b1111_0000_1").is_err());
+        assert!(parse_number("b1111000").is_err());
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(parse_number("(2+3)").unwrap(), Param::Number(5));
+        assert_eq!(parse_number("(2+3*4)").unwrap(), Param::Number(14));
+        assert_eq!(parse_number("(xF*2-b1)").unwrap(), Param::Number(29));
+        assert_eq!(parse_number("('a'+1)").unwrap(), Param::Number(98));
+        assert_eq!(parse_number("('+'-'*')").unwrap(), Param::Number(1));
+        assert_eq!(
+            parse_number("(10-12)").unwrap_err().to_string(),
+            "Error parsing number (10-12): result -2 is out of range 0-255"
+        );
+        assert_eq!(
+            parse_number("(200+56)").unwrap_err().to_string(),
+            "Error parsing number (200+56): result 256 is out of range 0-255"
+        );
+        assert_eq!(
+            parse_number("(2+d0)").unwrap_err().to_string(),
+            "Error parsing number (2+d0): invalid term 'd0' in expression"
+        );
+        assert!(parse_number("(2+)").is_err());
+        assert!(parse_number("()").is_err());
+
+        assert_eq!(parse_addr("@(x100+4)").unwrap(), Param::Addr(260));
+        assert_eq!(parse_addr("@(@x100+4)").unwrap(), Param::Addr(260));
+        assert_eq!(parse_addr("@(256*255)").unwrap(), Param::Addr(65280));
+        assert_eq!(
+            parse_addr("@(65535*65535*65535*65535)")
+                .unwrap_err()
+                .to_string(),
+            "Invalid address @(65535*65535*65535*65535): expression overflowed"
+        );
+        assert_eq!(
+            parse_addr("@(xFFFF+1)").unwrap_err().to_string(),
+            "Invalid address @(xFFFF+1): result 65536 is out of range, max is @65535 (@xFFFF)"
+        );
+    }
+
+    #[test]
+    fn test_display_and_from_str() {
+        let params = [
+            (Param::Number(0), "0"),
+            (Param::Number(255), "255"),
+            (Param::DataReg(REG_D0), "d0"),
+            (Param::DataReg(REG_D3), "d3"),
+            (Param::DataReg(REG_ACC), "acc"),
+            (Param::AddrReg(REG_A0), "a0"),
+            (Param::AddrReg(REG_A1), "a1"),
+            (Param::Addr(0), "@0"),
+            (Param::Addr(65535), "@65535"),
+            (Param::Label(String::from("start")), "start"),
+            (Param::LabelAddr(String::from("start")), "&start"),
+        ];
+        for (param, text) in params {
+            assert_eq!(param.to_string(), text);
+            assert_eq!(text.parse::<Param>().unwrap(), param, "{}", text);
+        }
+        assert_eq!(Param::StrKey(String::from("msg")).to_string(), "msg");
+        assert_eq!(Param::DataKey(String::from("arr")).to_string(), "arr");
+
+        assert_eq!("xFF".parse::<Param>().unwrap(), Param::Number(255));
+        assert_eq!("' '".parse::<Param>().unwrap(), Param::Number(32));
+        assert_eq!("@x10".parse::<Param>().unwrap(), Param::Addr(16));
+        assert_eq!("ACC".parse::<Param>().unwrap(), Param::DataReg(REG_ACC));
+        assert!("256".parse::<Param>().is_err());
+        assert!("@70000".parse::<Param>().is_err());
+        assert!("".parse::<Param>().is_err());
+    }
+
+    #[test]
+    fn test_parameters_display() {
+        assert_eq!(Parameters::STRING_KEY.to_string(), "text_key");
+        assert_eq!(Parameters::ADDRESSES.to_string(), "(label|address)");
+        assert_eq!(
+            (Parameters::DATA_REG | Parameters::NUMBER | Parameters::ADDR_REG).to_string(),
+            "(byte|data_reg|addr_reg)"
+        );
+        assert_eq!(
+            (Parameters::ADDRESSES | Parameters::STRING_KEY | Parameters::DATA_KEY).to_string(),
+            "(address|label|text_key|data_key)"
+        );
+        assert_eq!(Parameters::empty().to_string(), "none");
     }
 
     #[test]
@@ -1,20 +1,32 @@
-#![feature(destructuring_assignment)]
+//Without the std feature only the VM core (`constants` and `device::internals`) is built
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+#[cfg(feature = "std")]
 #[macro_use]
 extern crate bitflags;
 
-use anyhow::Result;
+#[cfg(feature = "std")]
+use anyhow::{Error, Result};
+#[cfg(feature = "std")]
 use clap::{crate_authors, crate_name, crate_version, App, AppSettings, Arg, SubCommand, Values};
+#[cfg(feature = "std")]
 use git_version::git_version;
 
+#[cfg(feature = "std")]
 pub mod assembler;
+#[cfg(feature = "std")]
 pub mod common;
 pub mod constants;
+#[cfg(feature = "std")]
 pub mod decompiler;
 pub mod device;
+#[cfg(feature = "std")]
 pub mod language;
+#[cfg(feature = "std")]
 pub mod tape_reader;
 
+#[cfg(feature = "std")]
 pub fn run() -> Result<()> {
     let matches = App::new(crate_name!())
         .version(format!("{}-{}", crate_version!(), git_version!()).as_str())
@@ -51,16 +63,78 @@ pub fn run() -> Result<()> {
                         .short("-d")
                         .required(false)
                         .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("listing")
+                        .help("Output listing of addresses, bytes and source lines")
+                        .takes_value(false)
+                        .long("--listing")
+                        .short("-l")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("optimise")
+                        .help("Replace CALLs directly followed by RET with JMP")
+                        .takes_value(false)
+                        .long("--optimise")
+                        .short("-o")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("strict")
+                        .help("Address operands must be an @address or a defined label")
+                        .takes_value(false)
+                        .long("--strict")
+                        .short("-s")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("relocatable")
+                        .help("Include a relocation table so the tape can be loaded as an overlay")
+                        .takes_value(false)
+                        .long("--relocatable")
+                        .short("-r")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("string_warn_len")
+                        .help("Warn about strings longer than this many bytes (default 200)")
+                        .takes_value(true)
+                        .long("--string-warn-len")
+                        .required(false)
+                        .multiple(false),
+                )
+                .arg(
+                    Arg::with_name("define")
+                        .help("Include .ifdef blocks for this name, can be repeated")
+                        .takes_value(true)
+                        .long("--define")
+                        .short("-D")
+                        .number_of_values(1)
+                        .required(false)
+                        .multiple(true),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("decompile").arg(
-                Arg::with_name("file")
-                    .help("Decompile .tape")
-                    .takes_value(true)
-                    .multiple(false)
-                    .required(true),
-            ),
+            SubCommand::with_name("decompile")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Decompile .tape")
+                        .takes_value(true)
+                        .multiple(false)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("debug_file")
+                        .help("Debug info file, used to show string, data and label names")
+                        .takes_value(true)
+                        .multiple(false)
+                        .required(false),
+                ),
         )
         .subcommand(
             SubCommand::with_name("debug")
@@ -84,6 +158,14 @@ pub fn run() -> Result<()> {
                         .takes_value(true)
                         .multiple(true)
                         .required(false),
+                )
+                .arg(
+                    Arg::with_name("input_source")
+                        .help("File read by INPUT, without it INPUT is always at the end of input")
+                        .takes_value(true)
+                        .long("--input-source")
+                        .required(false)
+                        .multiple(false),
                 ),
         )
         .arg(
@@ -127,15 +209,38 @@ pub fn run() -> Result<()> {
             matches.value_of("tape").unwrap(),
             matches.value_of("debug_file").unwrap(),
             validate(convert(matches.values_of("input"))),
+            matches.value_of("input_source"),
         )?;
     } else if let Some(matches) = matches.subcommand_matches("assemble") {
+        let string_warn_len = match matches.value_of("string_warn_len") {
+            Some(len) => len
+                .parse()
+                .map_err(|_| Error::msg(format!("Invalid string warning length: {}", len)))?,
+            None => assembler::lint::DEFAULT_STRING_WARN_LEN,
+        };
+        let options = assembler::AssembleOptions {
+            keep_whitespace: false,
+            strict: matches.is_present("strict"),
+            optimise: matches.is_present("optimise"),
+            defines: convert(matches.values_of("define"))
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            string_warn_len,
+            relocatable: matches.is_present("relocatable"),
+        };
         assembler::start(
             matches.value_of("file").unwrap(),
             matches.is_present("build_debug"),
             matches.is_present("debug"),
+            matches.is_present("listing"),
+            &options,
         )?;
     } else if let Some(matches) = matches.subcommand_matches("decompile") {
-        decompiler::start(matches.value_of("file").unwrap())?;
+        decompiler::start(
+            matches.value_of("file").unwrap(),
+            matches.value_of("debug_file"),
+        )?;
     }
 
     crossterm::terminal::disable_raw_mode()?;
@@ -143,7 +248,8 @@ pub fn run() -> Result<()> {
     Ok(())
 }
 
-fn convert(values: Option<Values>) -> Vec<&str> {
+#[cfg(feature = "std")]
+fn convert(values: Option<Values<'_>>) -> Vec<&str> {
     if let Some(values) = values {
         values.collect()
     } else {
@@ -151,6 +257,7 @@ fn convert(values: Option<Values>) -> Vec<&str> {
     }
 }
 
+#[cfg(feature = "std")]
 fn validate(files: Vec<&str>) -> Vec<&str> {
     for file in files.iter() {
         if !std::path::Path::new(file).is_file() {
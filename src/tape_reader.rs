@@ -1,71 +1,736 @@
-use crate::common::read_bytes;
+use crate::common::{crc32, read_bytes};
+use crate::constants::hardware::{MAX_DATA_BYTES, MAX_STRING_BYTES, MAX_STRING_LEN, RAM_SIZE};
 use crate::constants::system::*;
+use crate::constants::Address;
 use anyhow::{Context, Error, Result};
+use std::convert::TryFrom;
+use std::ops::Range;
 
 pub struct Tape {
     pub name: String,
     pub version: String,
+    ///Byte address of the first op to run
+    pub entry: u16,
     pub ops: Vec<u8>,
     pub strings: Vec<u8>,
     pub data: Vec<u8>,
+    ///Only in tapes assembled as relocatable
+    pub relocations: Option<Vec<Relocation>>,
 }
 
-pub fn read_tape(path: &str) -> Result<Tape> {
-    let mut idx = 0;
-    let mut bytes = read_bytes(path)?;
-    if get_byte(&mut bytes, &mut idx, "header")? != TAPE_HEADER_1
-        || get_byte(&mut bytes, &mut idx, "header")? != TAPE_HEADER_2
-    {
+///A tape with the strings section split into each string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub name: String,
+    pub version: String,
+    ///Byte address of the first op to run
+    pub entry: u16,
+    pub ops: Vec<u8>,
+    ///In the order they appear in the tape
+    pub strings: Vec<String>,
+    pub data: Vec<u8>,
+    ///Only in tapes assembled as relocatable
+    pub relocations: Option<Vec<Relocation>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Ops,
+    Strings,
+    Data,
+}
+
+///An address written by the assembler, it has to be moved if the tape is loaded after other tapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    ///Section the address is stored in, ops or data
+    pub location: Section,
+    ///Section the address points into, label addresses are in the ops
+    pub target: Section,
+    ///Offsets in `location` of the address bytes, the low byte is after the high byte
+    ///except in `.jumptable` data where the high and low bytes are in separate arrays
+    pub high: u16,
+    pub low: u16,
+}
+
+impl Relocation {
+    ///Relocation for an address stored big endian at `offset`
+    pub fn new(location: Section, target: Section, offset: u16) -> Self {
+        Relocation {
+            location,
+            target,
+            high: offset,
+            low: offset + 1,
+        }
+    }
+
+    ///Each relocation is 5 bytes in the tape, the sections (location in the high nibble and target in the low)
+    ///then the offsets of the high and low bytes
+    fn to_bytes(self) -> [u8; 5] {
+        let [high1, high2] = self.high.to_be_bytes();
+        let [low1, low2] = self.low.to_be_bytes();
+        let sections = (section_id(self.location) << 4) | section_id(self.target);
+        [sections, high1, high2, low1, low2]
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let section = |id: u8| match id {
+            0 => Ok(Section::Ops),
+            1 => Ok(Section::Strings),
+            2 => Ok(Section::Data),
+            _ => Err(Error::msg(format!("Invalid relocation section {}", id))),
+        };
+        let location = section(bytes[0] >> 4)?;
+        if location == Section::Strings {
+            return Err(Error::msg("Relocations can't be in the strings section"));
+        }
+        Ok(Relocation {
+            location,
+            target: section(bytes[0] & 0x0F)?,
+            high: u16::from_be_bytes([bytes[1], bytes[2]]),
+            low: u16::from_be_bytes([bytes[3], bytes[4]]),
+        })
+    }
+}
+
+fn section_id(section: Section) -> u8 {
+    match section {
+        Section::Ops => 0,
+        Section::Strings => 1,
+        Section::Data => 2,
+    }
+}
+
+///Address ranges of a program loaded into memory
+///Ops, strings and data are placed in that order from @0 and the stack grows down from the end of memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub ops: Range<Address>,
+    pub strings: Range<Address>,
+    pub data: Range<Address>,
+    ///Memory between the end of the data and the stack, empty if they overlap
+    pub free: Range<Address>,
+    ///Memory used by the stack, from the stack pointer to the end of memory
+    pub stack: Range<Address>,
+}
+
+impl MemoryMap {
+    ///Errors if the program overlaps the stack
+    pub fn check(&self) -> Result<()> {
+        if self.data.end > self.stack.start {
+            return Err(Error::msg(format!(
+                "Program ends at @{} but the stack starts at @{}",
+                self.data.end, self.stack.start
+            )));
+        }
+        Ok(())
+    }
+}
+
+///Memory map of `program` loaded into a device with `mem_size` bytes of memory and the stack pointer at `sp`
+///Errors if the sections don't fit in the address space or `sp` is past the end of memory
+pub fn memory_map(program: &Program, mem_size: Address, sp: Address) -> Result<MemoryMap> {
+    if sp > mem_size {
+        return Err(Error::msg(format!(
+            "Stack pointer @{} is past the end of memory (@{})",
+            sp, mem_size
+        )));
+    }
+    let strings = program.strings.iter().map(|string| string.len() + 1).sum();
+    let mut start: Address = 0;
+    let mut section = |name: &str, len: usize| -> Result<Range<Address>> {
+        let end = Address::try_from(start as usize + len).map_err(|_| {
+            Error::msg(format!(
+                "{} section ends at {}, past the end of the address space",
+                name,
+                start as usize + len
+            ))
+        })?;
+        let range = start..end;
+        start = end;
+        Ok(range)
+    };
+    let ops = section("Ops", program.ops.len())?;
+    let strings = section("Strings", strings)?;
+    let data = section("Data", program.data.len())?;
+    Ok(MemoryMap {
+        free: data.end.min(sp)..sp,
+        stack: sp..mem_size,
+        ops,
+        strings,
+        data,
+    })
+}
+
+impl TryFrom<&[u8]> for Program {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        decode_safe(bytes)
+    }
+}
+
+///Decodes a tape without panicking on any input, every length is checked against the remaining bytes
+pub fn decode_safe(bytes: &[u8]) -> Result<Program> {
+    let tape = decode_tape(bytes)?;
+    let mut cursor = TapeCursor {
+        bytes: &tape.strings,
+        idx: 0,
+    };
+    let mut strings = vec![];
+    while cursor.idx < cursor.bytes.len() {
+        strings.push(cursor.take_string("strings")?);
+    }
+
+    Ok(Program {
+        name: tape.name,
+        version: tape.version,
+        entry: tape.entry,
+        ops: tape.ops,
+        strings,
+        data: tape.data,
+        relocations: tape.relocations,
+    })
+}
+
+///Shared by `decode_safe` and `parse_tape`, the strings section is left as bytes
+fn decode_tape(bytes: &[u8]) -> Result<Tape> {
+    let bytes = strip_checksum(bytes)?;
+    let mut cursor = TapeCursor { bytes, idx: 0 };
+    if cursor.take(2, "header")? != [TAPE_HEADER_1, TAPE_HEADER_2] {
         return Err(Error::msg("Not a TD tape file"));
     }
-    if get_byte(&mut bytes, &mut idx, "tape version")? != PRG_VERSION {
+    let tape_version = cursor.take(1, "tape version")?[0];
+    if !matches!(
+        tape_version,
+        PRG_VERSION | PRG_VERSION_RELOCATABLE | PRG_VERSION_NO_ENTRY | PRG_VERSION_NO_CHECKSUM
+    ) {
         return Err(Error::msg("Incompatible TD version"));
     }
-    let name = read_string(&mut bytes, &mut idx, "program name")?;
-    let version = read_string(&mut bytes, &mut idx, "program version")?;
-    let pc_byte_count = u16::from_be_bytes([
-        get_byte(&mut bytes, &mut idx, "program op count")?,
-        get_byte(&mut bytes, &mut idx, "program op count")?,
-    ]) as usize;
-    let mut ops = vec![];
-    for _ in 0..pc_byte_count {
-        ops.push(get_byte(&mut bytes, &mut idx, "program")?);
-    }
-    let strings_byte_count = u16::from_be_bytes([
-        get_byte(&mut bytes, &mut idx, "string count")?,
-        get_byte(&mut bytes, &mut idx, "string count")?,
-    ]) as usize;
-    let mut strings = vec![];
-    for _ in 0..strings_byte_count {
-        strings.push(get_byte(&mut bytes, &mut idx, "strings")?);
+    let name = cursor.take_string("program name")?;
+    let version = cursor.take_string("program version")?;
+    let entry = if matches!(tape_version, PRG_VERSION | PRG_VERSION_RELOCATABLE) {
+        let entry = cursor.take(2, "entry point")?;
+        u16::from_be_bytes([entry[0], entry[1]])
+    } else {
+        0
+    };
+    let ops = cursor.take_section("program")?.to_vec();
+    let strings = cursor.take_section("strings")?.to_vec();
+    let relocations = if tape_version == PRG_VERSION_RELOCATABLE {
+        let table = cursor.take_section("relocations")?;
+        if table.len() % 5 != 0 {
+            return Err(Error::msg(format!(
+                "Relocation table is {} bytes, must be a multiple of 5",
+                table.len()
+            )));
+        }
+        Some(
+            table
+                .chunks(5)
+                .map(Relocation::from_bytes)
+                .collect::<Result<Vec<Relocation>>>()?,
+        )
+    } else {
+        None
+    };
+    let data = cursor.bytes[cursor.idx..].to_vec();
+    check_section_sizes(ops.len(), strings.len(), data.len(), RAM_SIZE)?;
+    check_entry(entry, ops.len())?;
+    if let Some(relocations) = &relocations {
+        check_relocations(relocations, ops.len(), data.len())?;
     }
 
     Ok(Tape {
         name,
         version,
+        entry,
         ops,
         strings,
-        data: bytes,
+        data,
+        relocations,
     })
 }
 
-fn read_string(bytes: &mut Vec<u8>, idx: &mut usize, name: &str) -> Result<String> {
-    let length = get_byte(bytes, idx, name)?;
-    let mut str_bytes = vec![];
-    for _ in 0..length {
-        str_bytes.push(get_byte(bytes, idx, name)?);
+///Errors if an address in the relocation table isn't inside its section
+fn check_relocations(relocations: &[Relocation], ops: usize, data: usize) -> Result<()> {
+    for relocation in relocations {
+        let len = match relocation.location {
+            Section::Data => data,
+            _ => ops,
+        };
+        if relocation.high as usize >= len || relocation.low as usize >= len {
+            return Err(Error::msg(format!(
+                "Relocation at {}/{} is outside the {:?} section, which is {} bytes",
+                relocation.high, relocation.low, relocation.location, len
+            )));
+        }
     }
-    String::from_utf8(str_bytes).context(format!("parsing {}", name))
+    Ok(())
 }
 
-fn get_byte(bytes: &mut Vec<u8>, idx: &mut usize, area: &str) -> Result<u8> {
-    *idx += 1;
-    if !bytes.is_empty() {
-        Ok(bytes.remove(0))
-    } else {
-        Err(Error::msg(format!(
-            "Unexpected EoF at byte {} when parsing {}",
-            idx, area
-        )))
+///For current version tapes checks and removes the checksum from the end, older tapes are returned as is
+fn strip_checksum(bytes: &[u8]) -> Result<&[u8]> {
+    if !matches!(
+        bytes.get(2),
+        Some(&PRG_VERSION) | Some(&PRG_VERSION_RELOCATABLE) | Some(&PRG_VERSION_NO_ENTRY)
+    ) {
+        return Ok(bytes);
+    }
+    if bytes.len() < 7 {
+        return Err(Error::msg(format!(
+            "Unexpected EoF at byte {} when parsing checksum",
+            bytes.len()
+        )));
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    let expected = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    let actual = crc32(body);
+    if expected != actual {
+        return Err(Error::msg(format!(
+            "Tape is corrupted, checksum is {:08X} but should be {:08X}",
+            actual, expected
+        )));
+    }
+    Ok(body)
+}
+
+struct TapeCursor<'a> {
+    bytes: &'a [u8],
+    idx: usize,
+}
+
+impl<'a> TapeCursor<'a> {
+    fn take(&mut self, count: usize, area: &str) -> Result<&'a [u8]> {
+        match self.bytes.get(self.idx..self.idx.saturating_add(count)) {
+            Some(taken) if taken.len() == count => {
+                self.idx += count;
+                Ok(taken)
+            }
+            _ => Err(Error::msg(format!(
+                "Unexpected EoF at byte {} when parsing {}",
+                self.bytes.len(),
+                area
+            ))),
+        }
+    }
+
+    ///Single byte length followed by UTF-8 bytes
+    fn take_string(&mut self, area: &str) -> Result<String> {
+        let length = self.take(1, area)?[0] as usize;
+        let bytes = self.take(length, area)?;
+        String::from_utf8(bytes.to_vec()).context(format!("parsing {}", area))
+    }
+
+    ///Two byte (big endian) length followed by the bytes
+    fn take_section(&mut self, area: &str) -> Result<&'a [u8]> {
+        let length = self.take(2, area)?;
+        let length = u16::from_be_bytes([length[0], length[1]]) as usize;
+        self.take(length, area)
+    }
+}
+
+impl Program {
+    ///Same layout as `generate_byte_code`
+    ///String addresses in the ops are not updated, so changing a string length moves any following strings
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let tape_version = match self.relocations {
+            Some(_) => PRG_VERSION_RELOCATABLE,
+            None => PRG_VERSION,
+        };
+        let mut output = vec![TAPE_HEADER_1, TAPE_HEADER_2, tape_version];
+        write_string(&mut output, &self.name, "Program name")?;
+        write_string(&mut output, &self.version, "Program version")?;
+        output.extend_from_slice(&self.entry.to_be_bytes());
+        write_section(&mut output, &self.ops, "Ops")?;
+        write_section(&mut output, &self.string_bytes()?, "Strings")?;
+        if let Some(relocations) = &self.relocations {
+            write_section(&mut output, &relocation_bytes(relocations), "Relocations")?;
+        }
+        output.extend_from_slice(&self.data);
+        let checksum = crc32(&output);
+        output.extend_from_slice(&checksum.to_be_bytes());
+        Ok(output)
+    }
+
+    ///Strings section as it appears in the tape, each string prefixed by its length
+    pub fn string_bytes(&self) -> Result<Vec<u8>> {
+        let mut output = vec![];
+        for string in &self.strings {
+            write_string(&mut output, string, "String")?;
+        }
+        Ok(output)
+    }
+}
+
+///Relocation table section as it appears in the tape
+pub fn relocation_bytes(relocations: &[Relocation]) -> Vec<u8> {
+    relocations
+        .iter()
+        .flat_map(|relocation| relocation.to_bytes())
+        .collect()
+}
+
+fn write_string(output: &mut Vec<u8>, value: &str, name: &str) -> Result<()> {
+    if value.len() > MAX_STRING_LEN {
+        return Err(Error::msg(format!(
+            "{} is {} bytes, max {}",
+            name,
+            value.len(),
+            MAX_STRING_LEN
+        )));
+    }
+    output.push(value.len() as u8);
+    output.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+fn write_section(output: &mut Vec<u8>, bytes: &[u8], name: &str) -> Result<()> {
+    if bytes.len() > u16::MAX as usize {
+        return Err(Error::msg(format!(
+            "{} are {} bytes, max {}",
+            name,
+            bytes.len(),
+            u16::MAX
+        )));
+    }
+    output.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    output.extend_from_slice(bytes);
+    Ok(())
+}
+
+pub fn read_tape(path: &str) -> Result<Tape> {
+    parse_tape(read_bytes(path)?)
+}
+
+pub fn parse_tape(bytes: Vec<u8>) -> Result<Tape> {
+    decode_tape(&bytes)
+}
+
+///Errors if `entry` isn't in the ops, 0 is always allowed so a tape without ops can be loaded
+fn check_entry(entry: u16, ops: usize) -> Result<()> {
+    if entry != 0 && entry as usize >= ops {
+        return Err(Error::msg(format!(
+            "Entry point @{} is outside the ops, which are {} bytes",
+            entry, ops
+        )));
+    }
+    Ok(())
+}
+
+///Errors if any section is larger than the device can address or if all of them together
+///are larger than the device's memory (`mem_size` bytes)
+///Ops and strings are limited by their u16 length but data is the rest of the tape so is unbounded
+pub fn check_section_sizes(ops: usize, strings: usize, data: usize, mem_size: usize) -> Result<()> {
+    for (name, len, limit) in [
+        ("Ops", ops, Address::MAX as usize),
+        ("Strings", strings, MAX_STRING_BYTES),
+        ("Data", data, MAX_DATA_BYTES),
+    ] {
+        if len > limit {
+            return Err(Error::msg(format!(
+                "{} section is {} bytes, device can only address {}",
+                name, len, limit
+            )));
+        }
+    }
+    let total = ops + strings + data;
+    if total > mem_size {
+        return Err(Error::msg(format!(
+            "Program is {} bytes (ops {}, strings {}, data {}), device memory is {} bytes",
+            total, ops, strings, data, mem_size
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::assembler::assemble_str;
+
+    const PROGRAM: &str =
+        "Test\n1\n.strings\nabc=foo\nempty=\n.data\ndk1=[[10,11]]\n.ops\nld a0 dk1 0 d3\nprts abc";
+
+    #[test]
+    fn test_program_from_bytes() {
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        let program = Program::try_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(program.name, "Test");
+        assert_eq!(program.version, "1");
+        assert_eq!(program.ops.len(), 9);
+        assert_eq!(program.strings, vec![String::from("foo"), String::new()]);
+        assert_eq!(program.data, vec![1, 2, 10, 11]);
+    }
+
+    #[test]
+    fn test_program_round_trip() {
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        let mut program = Program::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(program.to_bytes().unwrap(), bytes);
+
+        program.strings[0] = String::from("changed");
+        let changed = Program::try_from(program.to_bytes().unwrap().as_slice()).unwrap();
+        assert_eq!(changed, program);
+
+        program.strings[0] = "a".repeat(256);
+        assert!(program.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_truncated_program() {
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        for len in 0..(bytes.len() - 6) {
+            assert!(Program::try_from(&bytes[..len]).is_err(), "{}", len);
+            assert!(parse_tape(bytes[..len].to_vec()).is_err(), "{}", len);
+        }
+        //String claims more bytes than the strings section has
+        let mut bytes = bytes;
+        bytes[25] = 10;
+        assert!(Program::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_relocation_section() {
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        let mut program = Program::try_from(bytes.as_slice()).unwrap();
+        program.relocations = Some(vec![
+            Relocation::new(Section::Ops, Section::Data, 2),
+            Relocation {
+                location: Section::Data,
+                target: Section::Ops,
+                high: 0,
+                low: 3,
+            },
+        ]);
+        let bytes = program.to_bytes().unwrap();
+        assert_eq!(bytes[2], PRG_VERSION_RELOCATABLE);
+        assert_eq!(decode_safe(&bytes).unwrap(), program);
+        let tape = parse_tape(bytes).unwrap();
+        assert_eq!(tape.relocations, program.relocations);
+        assert_eq!(tape.data, program.data);
+
+        let error = |relocations: Vec<Relocation>| {
+            let mut program = program.clone();
+            program.relocations = Some(relocations);
+            decode_safe(&program.to_bytes().unwrap())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(vec![Relocation::new(Section::Data, Section::Ops, 3)]),
+            "Relocation at 3/4 is outside the Data section, which is 4 bytes"
+        );
+        assert_eq!(
+            error(vec![Relocation::new(Section::Strings, Section::Ops, 0)]),
+            "Relocations can't be in the strings section"
+        );
+    }
+
+    #[test]
+    fn test_entry_outside_ops() {
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        let mut program = Program::try_from(bytes.as_slice()).unwrap();
+        program.entry = 6;
+        assert_eq!(decode_safe(&program.to_bytes().unwrap()).unwrap().entry, 6);
+
+        program.entry = 9;
+        let bytes = program.to_bytes().unwrap();
+        let expected = "Entry point @9 is outside the ops, which are 9 bytes";
+        assert_eq!(decode_safe(&bytes).unwrap_err().to_string(), expected);
+        assert_eq!(parse_tape(bytes).err().unwrap().to_string(), expected);
+    }
+
+    #[test]
+    fn test_oversized_sections() {
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        let mut program = Program::try_from(bytes.as_slice()).unwrap();
+        program.data.extend(vec![0; MAX_DATA_BYTES]);
+        let oversized = program.to_bytes().unwrap();
+        let expected = format!(
+            "Data section is {} bytes, device can only address {}",
+            MAX_DATA_BYTES + 4,
+            MAX_DATA_BYTES
+        );
+        assert_eq!(decode_safe(&oversized).unwrap_err().to_string(), expected);
+        assert_eq!(parse_tape(oversized).err().unwrap().to_string(), expected);
+
+        //Every section fits but together they don't
+        program.data.truncate(MAX_DATA_BYTES);
+        let oversized = program.to_bytes().unwrap();
+        let expected = format!(
+            "Program is {} bytes (ops 9, strings 5, data {}), device memory is {} bytes",
+            MAX_DATA_BYTES + 14,
+            MAX_DATA_BYTES,
+            RAM_SIZE
+        );
+        assert_eq!(decode_safe(&oversized).unwrap_err().to_string(), expected);
+        assert_eq!(parse_tape(oversized).err().unwrap().to_string(), expected);
+
+        //Same tape on a smaller device
+        assert!(check_section_sizes(9, 5, 4, 18).is_ok());
+        assert_eq!(
+            check_section_sizes(9, 5, 4, 16).unwrap_err().to_string(),
+            "Program is 18 bytes (ops 9, strings 5, data 4), device memory is 16 bytes"
+        );
+        //Section limits come from the address space, not the memory size
+        assert_eq!(
+            check_section_sizes(MAX_STRING_BYTES + 1, 0, 0, RAM_SIZE)
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "Ops section is {} bytes, device can only address {}",
+                MAX_STRING_BYTES + 1,
+                Address::MAX
+            )
+        );
+        assert_eq!(
+            check_section_sizes(0, MAX_STRING_BYTES + 1, 0, 8)
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "Strings section is {} bytes, device can only address {}",
+                MAX_STRING_BYTES + 1,
+                MAX_STRING_BYTES
+            )
+        );
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        let program = Program::try_from(bytes.as_slice()).unwrap();
+
+        let map = memory_map(&program, RAM_SIZE as Address, RAM_SIZE as Address).unwrap();
+        assert_eq!(
+            map,
+            MemoryMap {
+                ops: 0..9,
+                strings: 9..14,
+                data: 14..18,
+                free: 18..RAM_SIZE as Address,
+                stack: RAM_SIZE as Address..RAM_SIZE as Address,
+            }
+        );
+        assert_eq!(map.strings.len(), program.string_bytes().unwrap().len());
+        assert!(map.check().is_ok());
+
+        //Stack pushed down to the data
+        let map = memory_map(&program, 32, 18).unwrap();
+        assert_eq!((map.free.clone(), map.stack.clone()), (18..18, 18..32));
+        assert!(map.check().is_ok());
+        let map = memory_map(&program, 32, 16).unwrap();
+        assert_eq!((map.free.clone(), map.stack.clone()), (16..16, 16..32));
+        assert_eq!(
+            map.check().unwrap_err().to_string(),
+            "Program ends at @18 but the stack starts at @16"
+        );
+        //Program larger than memory
+        assert_eq!(
+            memory_map(&program, 16, 16)
+                .unwrap()
+                .check()
+                .unwrap_err()
+                .to_string(),
+            "Program ends at @18 but the stack starts at @16"
+        );
+        assert_eq!(
+            memory_map(&program, 16, 20).unwrap_err().to_string(),
+            "Stack pointer @20 is past the end of memory (@16)"
+        );
+
+        let mut program = program;
+        program.data.extend(vec![0; MAX_DATA_BYTES]);
+        assert_eq!(
+            memory_map(&program, RAM_SIZE as Address, RAM_SIZE as Address)
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "Data section ends at {}, past the end of the address space",
+                MAX_DATA_BYTES + 18
+            )
+        );
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        assert_eq!(bytes[2], PRG_VERSION);
+        let body = &bytes[..bytes.len() - 4];
+        assert_eq!(&bytes[bytes.len() - 4..], &crc32(body).to_be_bytes());
+        assert!(decode_safe(&bytes).is_ok());
+        assert!(parse_tape(bytes.clone()).is_ok());
+
+        for idx in 0..bytes.len() {
+            let mut corrupted = bytes.clone();
+            corrupted[idx] ^= 0x01;
+            let err = decode_safe(&corrupted).unwrap_err().to_string();
+            //Changing the version byte makes it look like an older or unknown tape instead
+            if idx != 2 {
+                assert!(err.starts_with("Tape is corrupted"), "{}: {}", idx, err);
+                assert!(parse_tape(corrupted).is_err(), "{}", idx);
+            }
+        }
+
+        //Tapes from before the entry point and checksum were added still load
+        let mut legacy = body.to_vec();
+        legacy.drain(10..12);
+        legacy[2] = PRG_VERSION_NO_ENTRY;
+        let mut no_entry = legacy.clone();
+        no_entry.extend_from_slice(&crc32(&legacy).to_be_bytes());
+        assert_eq!(
+            decode_safe(&no_entry).unwrap(),
+            decode_safe(&bytes).unwrap()
+        );
+        assert_eq!(parse_tape(no_entry).unwrap().data, vec![1, 2, 10, 11]);
+
+        legacy[2] = PRG_VERSION_NO_CHECKSUM;
+        let program = decode_safe(&legacy).unwrap();
+        assert_eq!(program, decode_safe(&bytes).unwrap());
+        assert_eq!(parse_tape(legacy).unwrap().data, vec![1, 2, 10, 11]);
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        let (bytes, _) = assemble_str(PROGRAM, false).unwrap();
+        let header = [TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION];
+        for len in 0..(bytes.len() - 6) {
+            assert!(decode_safe(&bytes[..len]).is_err(), "{}", len);
+        }
+        assert!(decode_safe(&[0xFF; 64]).is_err());
+        assert!(decode_safe(&[TAPE_HEADER_1, TAPE_HEADER_2, 0]).is_err());
+        assert!(decode_safe(&[TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION, 255, b'a']).is_err());
+        //Invalid UTF-8 name
+        assert!(decode_safe(&[
+            TAPE_HEADER_1,
+            TAPE_HEADER_2,
+            PRG_VERSION,
+            1,
+            0xC3,
+            0,
+            0,
+            0,
+            0
+        ])
+        .is_err());
+        //Lengths of u16::MAX with nothing following
+        assert!(decode_safe(&[TAPE_HEADER_1, TAPE_HEADER_2, PRG_VERSION, 0, 0, 255, 255]).is_err());
+
+        //Random bodies may be valid so only check they don't panic
+        let mut seed: u32 = 12345;
+        for len in 0..512 {
+            let mut garbage = header.to_vec();
+            for _ in 0..len {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                garbage.push((seed >> 16) as u8 % 8);
+            }
+            let _ = decode_safe(&garbage);
+            let mut flipped = bytes.clone();
+            flipped[len % bytes.len()] ^= (seed >> 8) as u8;
+            let _ = decode_safe(&flipped);
+        }
     }
 }
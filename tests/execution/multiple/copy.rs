@@ -1,7 +1,7 @@
 use crate::{assert_no_output, assert_step_device, setup};
 use tape_device::constants::code::{
-    CPY_AREG_ADDR, CPY_AREG_AREG, CPY_AREG_REG_REG, CPY_REG_AREG, CPY_REG_REG, CPY_REG_REG_AREG,
-    CPY_REG_VAL,
+    CLR_REG, CPY_AREG_ADDR, CPY_AREG_AREG, CPY_AREG_REG_REG, CPY_REG_AREG, CPY_REG_REG,
+    CPY_REG_REG_AREG, CPY_REG_VAL, INC_REG,
 };
 use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
 use tape_device::device::Dump;
@@ -35,3 +35,22 @@ fn test_multiple_copy_ops() {
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_clear_op() {
+    let ops = vec![
+        CPY_REG_VAL, REG_D0, 255,
+        INC_REG, REG_D0,
+        CPY_REG_VAL, REG_D1, 7,
+        CLR_REG, REG_D1,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("CPY D0 255", &mut device, Dump { pc: 3, data_reg: [255, 0, 0, 0], ..Default::default() });
+    assert_step_device("INC D0", &mut device, Dump { pc: 5, overflow: true, ..Default::default() });
+    assert_step_device("CPY D1 7", &mut device, Dump { pc: 8, data_reg: [0, 7, 0, 0], overflow: true, ..Default::default() });
+    assert_step_device("CLR D1", &mut device, Dump { pc: 10, ..Default::default() });
+
+    assert_no_output(device);
+}
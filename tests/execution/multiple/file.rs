@@ -115,7 +115,7 @@ fn test_multiple_test_ops() {
 }
 
 fn setup_test_file(suffix: &str) -> String {
-    let mut path = tempdir().unwrap().into_path();
+    let mut path = tempdir().unwrap().keep();
     path.push(format!("tape-device-test-file{}.test.bin", suffix));
 
     if path.exists() {
@@ -1,6 +1,8 @@
 use crate::{assert_memory, assert_no_output, assert_step_device, setup};
-use tape_device::constants::code::{HALT, IPOLL_ADDR, IPOLL_AREG, RCHR_REG, RSTR_ADDR, RSTR_AREG};
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC};
+use tape_device::constants::code::{
+    HALT, INPUT_REG, IPOLL_ADDR, IPOLL_AREG, RCHR_REG, RSTR_ADDR, RSTR_AREG,
+};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0};
 use tape_device::device::internals::RunResult;
 use tape_device::device::Dump;
 
@@ -35,24 +37,64 @@ fn test_input() {
     device.keyboard_buffer = vec![b'H', b'i'];
     assert_step_device("RSTR A0", &mut device, Dump { pc: 7, acc: 2, ..Default::default() });
     assert_eq!(device.keyboard_buffer, Vec::<u8>::new());
-    assert_memory(&device, 0, &[b'H', b'i']);
+    assert_memory(&device, 0, b"Hi");
 
     device.addr_reg[1] = 16;
     device.keyboard_buffer = vec![b'T', b'e', b's', b't'];
     assert_step_device("IPOLL @11", &mut device, Dump { pc: 11, acc: 2, addr_reg: [0, 16], ..Default::default() });
     assert_step_device("RSTR A1", &mut device, Dump { pc: 13, acc: 4, addr_reg: [0, 16], ..Default::default() });
     assert_eq!(device.keyboard_buffer, Vec::<u8>::new());
-    assert_memory(&device, 0, &[b'H', b'i']);
-    assert_memory(&device, 16, &[b'T', b'e', b's', b't']);
+    assert_memory(&device, 0, b"Hi");
+    assert_memory(&device, 16, b"Test");
 
     device.keyboard_buffer = vec![b'F', b'i', b'n'];
     assert_step_device("IPOLL A1", &mut device, Dump { pc: 16, acc: 4, addr_reg: [0, 16], ..Default::default() });
     assert_step_device("RSTR @100", &mut device, Dump { pc: 19, acc: 3, addr_reg: [0, 16], ..Default::default() });
     assert_eq!(device.keyboard_buffer, Vec::<u8>::new());
-    assert_memory(&device, 0, &[b'H', b'i']);
-    assert_memory(&device, 16, &[b'T', b'e', b's', b't']);
-    assert_memory(&device, 100, &[b'F', b'i', b'n']);
+    assert_memory(&device, 0, b"Hi");
+    assert_memory(&device, 16, b"Test");
+    assert_memory(&device, 100, b"Fin");
 
 
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_input_requested_from_host() {
+    let ops = vec![
+        INPUT_REG, REG_D0,
+        INPUT_REG, REG_D0,
+        HALT,
+    ];
+    let mut device = setup(ops);
+
+    assert_eq!(device.step(true), RunResult::InputRequested);
+    assert_eq!(device.dump(), Dump::default());
+    device.push_input(b"a");
+    assert_step_device("INPUT D0", &mut device, Dump { pc: 2, data_reg: [97, 0, 0, 0], ..Default::default() });
+    assert_eq!(device.step(true), RunResult::InputRequested);
+    device.end_input();
+    assert_step_device("INPUT D0", &mut device, Dump { pc: 4, eof: true, ..Default::default() });
+    assert_eq!(device.step(true), RunResult::Halt);
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_input_source_eof() {
+    let ops = vec![
+        INPUT_REG, REG_D0,
+        INPUT_REG, REG_D0,
+        INPUT_REG, REG_D0,
+    ];
+    let mut device = setup(ops);
+    device.set_input_source(vec![b'a', b'b']);
+
+    assert_step_device("INPUT D0", &mut device, Dump { pc: 2, data_reg: [97, 0, 0, 0], ..Default::default() });
+    assert_step_device("INPUT D0", &mut device, Dump { pc: 4, data_reg: [98, 0, 0, 0], ..Default::default() });
+    assert_step_device("INPUT D0", &mut device, Dump { pc: 6, eof: true, ..Default::default() });
+
     assert_no_output(device);
 }
@@ -1,7 +1,10 @@
 use crate::{assert_no_output, assert_step_device, setup};
-use tape_device::constants::code::{HALT, JE_ADDR, JMP_ADDR, JMP_AREG};
+use tape_device::constants::code::{
+    CPY_AREG_ADDR, DJNZ_AREG_ADDR, HALT, INC_REG, JE_ADDR, JMP_ADDR, JMP_AREG,
+};
 use tape_device::constants::compare::EQUAL;
-use tape_device::constants::hardware::REG_A0;
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0};
+use tape_device::device::internals::RunResult;
 use tape_device::device::Dump;
 
 #[test]
@@ -24,3 +27,29 @@ fn test_multiple_jump_ops() {
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_djnz() {
+    let ops = vec![
+        CPY_AREG_ADDR, REG_A1, 1, 44,   //a1 = 300
+        INC_REG, REG_D0,                //4 loop
+        DJNZ_AREG_ADDR, REG_A1, 0, 4,   //6 djnz a1 loop
+        HALT,                           //10
+    ];
+    let mut device = setup(ops);
+    let outcome = device.run_to_end();
+    assert_eq!(outcome.result, RunResult::Halt);
+    //d0 wraps at 256
+    assert_eq!(outcome.final_dump, Dump { pc: 10, data_reg: [44, 0, 0, 0], ..Default::default() });
+    assert_eq!(outcome.cycles, 1 + 300 * 2 + 1);
+    assert_no_output(device);
+
+    let ops = vec![
+        DJNZ_AREG_ADDR, REG_A0, 0, 5,
+        HALT,
+        HALT,
+    ];
+    let mut device = setup(ops);
+    assert_step_device("DJNZ a0 from 0", &mut device, Dump { pc: 5, addr_reg: [0xFFFF, 0], ..Default::default() });
+}
@@ -1,7 +1,7 @@
 use crate::{assert_no_output, assert_step_device, setup};
 use tape_device::constants::code::{
     AND_REG_AREG, AND_REG_REG, AND_REG_VAL, NOT_REG, OR_REG_AREG, OR_REG_REG, OR_REG_VAL,
-    XOR_REG_AREG, XOR_REG_REG,
+    ROL_REG_VAL, ROR_REG_VAL, XOR_REG_AREG, XOR_REG_REG,
 };
 use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
 use tape_device::device::Dump;
@@ -39,3 +39,25 @@ fn test_multiple_logic_ops() {
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_rotate_ops() {
+    let ops = vec![
+        ROL_REG_VAL, REG_D0, 1,
+        ROR_REG_VAL, REG_D0, 1,
+        ROL_REG_VAL, REG_D1, 1,
+        ROR_REG_VAL, REG_D1, 3,
+        ROL_REG_VAL, REG_D0, 8,
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [0b10000001, 0b01000000, 0, 0];
+
+    assert_step_device("ROL D0 1", &mut device, Dump { pc: 3, acc: 0b00000011, data_reg: [0b10000001, 0b01000000, 0, 0], overflow: true, ..Default::default() });
+    assert_step_device("ROR D0 1", &mut device, Dump { pc: 6, acc: 0b11000000, data_reg: [0b10000001, 0b01000000, 0, 0], overflow: true, ..Default::default() });
+    assert_step_device("ROL D1 1", &mut device, Dump { pc: 9, acc: 0b10000000, data_reg: [0b10000001, 0b01000000, 0, 0], ..Default::default() });
+    assert_step_device("ROR D1 3", &mut device, Dump { pc: 12, acc: 0b00001000, data_reg: [0b10000001, 0b01000000, 0, 0], ..Default::default() });
+    assert_step_device("ROL D0 8", &mut device, Dump { pc: 15, acc: 0b10000001, data_reg: [0b10000001, 0b01000000, 0, 0], ..Default::default() });
+
+    assert_no_output(device);
+}
@@ -1,8 +1,11 @@
 use crate::{assert_no_output, assert_step_device, setup};
 use tape_device::constants::code::{
-    ADD_REG_AREG, ADD_REG_REG, ADD_REG_VAL, DEC_REG, INC_REG, SUB_REG_AREG, SUB_REG_REG,
+    ABS_REG, ADD_REG_AREG, ADD_REG_REG, ADD_REG_VAL, CPY_REG_VAL, DEC_REG, INC_REG, MAX_REG_REG,
+    MIN_REG_REG, NEG_REG, SMAX_REG_REG, SMIN_REG_REG, SUB_REG_AREG, SUB_REG_REG,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D3};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
+use tape_device::device::comm::Output::OutputErr;
+use tape_device::device::internals::{OverflowMode, RunResult};
 use tape_device::device::Dump;
 
 #[test]
@@ -44,3 +47,86 @@ fn test_multiple_math_ops() {
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_signed_math_ops() {
+    let ops = vec![
+        CPY_REG_VAL, REG_D0, 1,
+        CPY_REG_VAL, REG_D2, 128,
+        NEG_REG, REG_D0,
+        NEG_REG, REG_D1,
+        NEG_REG, REG_D2,
+        ABS_REG, REG_ACC,
+        ABS_REG, REG_D0,
+        ABS_REG, REG_D2,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("CPY D0 1", &mut device, Dump { pc: 3, data_reg: [1, 0, 0, 0], ..Default::default() });
+    assert_step_device("CPY D2 128", &mut device, Dump { pc: 6, data_reg: [1, 0, 128, 0], ..Default::default() });
+    assert_step_device("NEG D0", &mut device, Dump { pc: 8, acc: 255, data_reg: [1, 0, 128, 0], ..Default::default() });
+    assert_step_device("NEG D1", &mut device, Dump { pc: 10, acc: 0, data_reg: [1, 0, 128, 0], ..Default::default() });
+    assert_step_device("NEG D2", &mut device, Dump { pc: 12, acc: 128, data_reg: [1, 0, 128, 0], overflow: true, ..Default::default() });
+    assert_step_device("ABS ACC", &mut device, Dump { pc: 14, acc: 128, data_reg: [1, 0, 128, 0], overflow: true, ..Default::default() });
+    assert_step_device("ABS D0", &mut device, Dump { pc: 16, acc: 1, data_reg: [1, 0, 128, 0], ..Default::default() });
+    assert_step_device("ABS D2", &mut device, Dump { pc: 18, acc: 128, data_reg: [1, 0, 128, 0], overflow: true, ..Default::default() });
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_overflow_modes() {
+    let ops = vec![
+        INC_REG, REG_D0,
+        ADD_REG_VAL, REG_D1, 10,
+    ];
+    let mut device = setup(ops.clone());
+    device.data_reg = [255, 250, 0, 0];
+
+    assert_step_device("INC D0", &mut device, Dump { pc: 2, data_reg: [0, 250, 0, 0], overflow: true, ..Default::default() });
+    assert_step_device("ADD D1 10", &mut device, Dump { pc: 5, acc: 4, data_reg: [0, 250, 0, 0], overflow: true, ..Default::default() });
+    assert_no_output(device);
+
+    let mut device = setup(ops);
+    device.set_overflow_mode(OverflowMode::Trap);
+    device.data_reg = [255, 250, 0, 0];
+
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_eq!(device.dump(), Dump { pc: 0, data_reg: [255, 250, 0, 0], ..Default::default() });
+    assert!(device.output.iter().any(|output| matches!(output, OutputErr(msg) if msg == "Overflow trapped: INC 255")));
+
+    device.data_reg = [254, 250, 0, 0];
+    assert_step_device("INC D0", &mut device, Dump { pc: 2, data_reg: [255, 250, 0, 0], ..Default::default() });
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_eq!(device.dump(), Dump { pc: 2, data_reg: [255, 250, 0, 0], ..Default::default() });
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_min_max_ops() {
+    let ops = vec![
+        MIN_REG_REG, REG_D0, REG_D1,
+        MAX_REG_REG, REG_D0, REG_D1,
+        SMIN_REG_REG, REG_D0, REG_D1,
+        SMAX_REG_REG, REG_D0, REG_D1,
+        MIN_REG_REG, REG_D2, REG_D3,
+        MAX_REG_REG, REG_D2, REG_D3,
+        SMIN_REG_REG, REG_D2, REG_D3,
+        SMAX_REG_REG, REG_D2, REG_D3,
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [200, 5, 255, 1];
+
+    assert_step_device("MIN D0 D1", &mut device, Dump { pc: 3, acc: 5, data_reg: [200, 5, 255, 1], ..Default::default() });
+    assert_step_device("MAX D0 D1", &mut device, Dump { pc: 6, acc: 200, data_reg: [200, 5, 255, 1], ..Default::default() });
+    assert_step_device("SMIN D0 D1", &mut device, Dump { pc: 9, acc: 200, data_reg: [200, 5, 255, 1], ..Default::default() });
+    assert_step_device("SMAX D0 D1", &mut device, Dump { pc: 12, acc: 5, data_reg: [200, 5, 255, 1], ..Default::default() });
+    assert_step_device("MIN D2 D3", &mut device, Dump { pc: 15, acc: 1, data_reg: [200, 5, 255, 1], ..Default::default() });
+    assert_step_device("MAX D2 D3", &mut device, Dump { pc: 18, acc: 255, data_reg: [200, 5, 255, 1], ..Default::default() });
+    assert_step_device("SMIN D2 D3", &mut device, Dump { pc: 21, acc: 255, data_reg: [200, 5, 255, 1], ..Default::default() });
+    assert_step_device("SMAX D2 D3", &mut device, Dump { pc: 24, acc: 1, data_reg: [200, 5, 255, 1], ..Default::default() });
+
+    assert_no_output(device);
+}
@@ -1,8 +1,10 @@
 use crate::{assert_memory, assert_specific_output, assert_step_device, setup};
 use tape_device::constants::code::{
-    MEMP_ADDR, MEMP_AREG, MEMR_ADDR, MEMR_AREG, MEMW_ADDR, MEMW_AREG,
+    LDA_REG_ADDR, LDSTR_AREG_REG_STR, MEMP_ADDR, MEMP_AREG, MEMR_ADDR, MEMR_AREG, MEMW_ADDR,
+    MEMW_AREG, STA_ADDR_REG,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D1, REG_D2};
+use tape_device::device::internals::{Device, RunResult};
 use tape_device::device::Dump;
 
 #[test]
@@ -41,3 +43,90 @@ fn test_multiple_memory_ops() {
 
     assert_specific_output(device, "Hellollo\u{0}\u{0}");
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_absolute_memory_ops() {
+    let ops = vec![
+        STA_ADDR_REG, 0, 100, REG_D1,
+        LDA_REG_ADDR, REG_D2, 0, 100,
+        LDA_REG_ADDR, REG_D1, 0, 101,
+    ];
+    let mut device = setup(ops);
+    device.data_reg[1] = 42;
+
+    assert_step_device("STA @100 D1", &mut device, Dump { pc: 4, data_reg: [0, 42, 0, 0], ..Default::default() });
+    assert_memory(&device, 99, &[0, 42, 0]);
+    assert_step_device("LDA D2 @100", &mut device, Dump { pc: 8, data_reg: [0, 42, 42, 0], ..Default::default() });
+    assert_step_device("LDA D1 @101", &mut device, Dump { pc: 12, data_reg: [0, 0, 42, 0], ..Default::default() });
+    assert_eq!(device.acc, 0);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_absolute_memory_ops_at_end_of_memory() {
+    let ops = vec![
+        STA_ADDR_REG, 255, 254, REG_D1,
+        LDA_REG_ADDR, REG_D2, 255, 254,
+        STA_ADDR_REG, 255, 255, REG_D1,
+    ];
+    let mut device = setup(ops);
+    device.data_reg[1] = 42;
+
+    assert_step_device("STA @65534 D1", &mut device, Dump { pc: 4, data_reg: [0, 42, 0, 0], ..Default::default() });
+    assert_step_device("LDA D2 @65534", &mut device, Dump { pc: 8, data_reg: [0, 42, 42, 0], ..Default::default() });
+    //@65535 is past the end of memory, this errors instead of panicking
+    assert_eq!(device.step(true), RunResult::ProgError);
+
+    for ops in [
+        vec![LDA_REG_ADDR, REG_D2, 255, 255],
+        vec![MEMR_ADDR, 255, 255],
+        vec![MEMW_ADDR, 255, 255],
+        vec![MEMW_AREG, REG_A0],
+    ] {
+        let mut device = setup(ops.clone());
+        device.addr_reg[0] = 65535;
+        assert_eq!(device.step(true), RunResult::ProgError, "{:?}", ops);
+        assert_eq!(device.dump().data_reg, [0; 4], "{:?}", ops);
+    }
+}
+
+#[test]
+fn test_write_and_read_memory() {
+    let mut device = setup(vec![]);
+    device.write_memory(100, &[1, 2, 3, 4]).unwrap();
+    assert_eq!(device.read_memory(100, 4).unwrap(), &[1, 2, 3, 4]);
+    assert_eq!(device.read_memory(99, 6).unwrap(), &[0, 1, 2, 3, 4, 0]);
+    assert_memory(&device, 100, &[1, 2, 3, 4]);
+
+    device.write_memory(65533, &[5, 6]).unwrap();
+    assert_eq!(device.read_memory(65533, 2).unwrap(), &[5, 6]);
+    assert!(device.read_memory(65534, 0).unwrap().is_empty());
+
+    assert!(device.write_memory(65534, &[7, 8]).is_err());
+    assert_eq!(device.read_memory(65534, 1).unwrap(), &[6]);
+    assert!(device.read_memory(65535, 1).is_err());
+    assert!(device.read_memory(0, u16::MAX).is_ok());
+    assert!(device.read_memory(1, u16::MAX).is_err());
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_ldstr() {
+    let ops = vec![
+        LDSTR_AREG_REG_STR, REG_A1, REG_D2, 0, 3,
+        LDSTR_AREG_REG_STR, REG_A1, REG_D1, 0, 0,
+        LDSTR_AREG_REG_STR, REG_A0, REG_D1, 0, 0,
+    ];
+    let strings = vec![2, b'H', b'i', 5, b't', b'h', b'e', b'r', b'e'];
+    let mut device = Device::new(ops, strings, vec![], vec![]);
+    device.addr_reg = [65534, 100];
+
+    assert_step_device("LDSTR A1 D2 there", &mut device, Dump { pc: 5, addr_reg: [65534, 105], data_reg: [0, 0, 5, 0], ..Default::default() });
+    assert_step_device("LDSTR A1 D1 hi", &mut device, Dump { pc: 10, addr_reg: [65534, 107], data_reg: [0, 2, 5, 0], ..Default::default() });
+    assert_memory(&device, 100, b"thereHi");
+    //A0 is too close to the end of memory
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_eq!(device.dump().addr_reg, [65534, 107]);
+    assert_eq!(device.dump().data_reg, [0, 2, 5, 0]);
+}
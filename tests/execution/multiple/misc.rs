@@ -1,10 +1,33 @@
-use crate::{assert_specific_output, assert_step_device, setup};
+use crate::{assert_no_output, assert_specific_output, assert_step_device, setup};
+use std::convert::TryFrom;
+use tape_device::assembler::assemble_str;
 use tape_device::constants::code::{
-    DEBUG, HALT, NOP, RAND_REG, SEED_REG, SWP_AREG_AREG, SWP_REG_REG, TIME,
+    ASSERT_REG_VAL, DEBUG, HALT, INC_REG, NOP, RAND_REG, RDTICK_AREG, RDTICK_REG, SEED_REG,
+    SWP_AREG_AREG, SWP_REG_REG, TIME,
 };
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0, REG_D1};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
+use tape_device::device::comm::Output::OutputErr;
 use tape_device::device::internals::{Device, RunResult};
 use tape_device::device::Dump;
+use tape_device::tape_reader::Program;
+
+#[test]
+fn test_exec_line() {
+    let mut device = Device::new(vec![], vec![], vec![], vec![]);
+
+    assert_eq!(device.exec_line("cpy d0 5").unwrap(), RunResult::Pause);
+    assert_eq!(device.exec_line("inc d0").unwrap(), RunResult::Pause);
+    assert_eq!(device.data_reg[0], 6);
+    assert_eq!(device.pc, 5);
+
+    assert_eq!(device.exec_line("jmp @0").unwrap(), RunResult::Pause);
+    assert_eq!(device.exec_line("  add d0 d0  ").unwrap(), RunResult::Pause);
+    assert_eq!(device.acc, 12);
+
+    assert!(device.exec_line("prts greeting").is_err());
+    assert!(device.exec_line("cpy d9 1").is_err());
+    assert_eq!(device.exec_line("halt").unwrap(), RunResult::Halt);
+}
 
 #[test]
 #[rustfmt::skip]
@@ -54,3 +77,98 @@ fn validate(device: &mut Device) {
     assert!(dump.data_reg[2] < 24, "{}", dump.data_reg[2]);
     assert_eq!(dump.data_reg[3], 0);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_tick_ops() {
+    let ops = vec![
+        RDTICK_REG, REG_D0,
+        RDTICK_REG, REG_D1,
+        RDTICK_AREG, REG_A0,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("RDTICK D0", &mut device, Dump { pc: 2, ..Default::default() });
+    device.advance_ticks(300);
+    assert_step_device("RDTICK D1", &mut device, Dump { pc: 4, data_reg: [0, 44, 0, 0], ..Default::default() });
+    device.advance_ticks(u16::MAX);
+    assert_step_device("RDTICK A0", &mut device, Dump { pc: 6, data_reg: [0, 44, 0, 0], addr_reg: [299, 0], ..Default::default() });
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_swp_keeps_other_state() {
+    let ops = vec![
+        INC_REG, REG_D3,
+        SWP_REG_REG, REG_D0, REG_D1,
+        SWP_REG_REG, REG_ACC, REG_D2,
+        SWP_AREG_AREG, REG_A0, REG_A1,
+        SWP_REG_REG, REG_D1, REG_D1,
+    ];
+    let mut device = setup(ops);
+    device.acc = 9;
+    device.data_reg = [1, 2, 3, 255];
+    device.addr_reg = [10, 20];
+
+    assert_step_device("INC D3", &mut device, Dump { pc: 2, acc: 9, data_reg: [1, 2, 3, 0], addr_reg: [10, 20], overflow: true, ..Default::default() });
+    assert_step_device("SWP D0 D1", &mut device, Dump { pc: 5, acc: 9, data_reg: [2, 1, 3, 0], addr_reg: [10, 20], overflow: true, ..Default::default() });
+    assert_step_device("SWP ACC D2", &mut device, Dump { pc: 8, acc: 3, data_reg: [2, 1, 9, 0], addr_reg: [10, 20], overflow: true, ..Default::default() });
+    assert_step_device("SWP A0 A1", &mut device, Dump { pc: 11, acc: 3, data_reg: [2, 1, 9, 0], addr_reg: [20, 10], overflow: true, ..Default::default() });
+    assert_step_device("SWP D1 D1", &mut device, Dump { pc: 14, acc: 3, data_reg: [2, 1, 9, 0], addr_reg: [20, 10], overflow: true, ..Default::default() });
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_assert_op() {
+    let ops = vec![
+        ASSERT_REG_VAL, REG_D1, 7,
+        ASSERT_REG_VAL, REG_D1, 8,
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [0, 7, 0, 0];
+
+    assert_step_device("ASSERT D1 7", &mut device, Dump { pc: 3, data_reg: [0, 7, 0, 0], ..Default::default() });
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_eq!(device.dump().pc, 3);
+    assert!(device.output.iter().any(|output| matches!(output, OutputErr(msg) if msg == "Assertion failed: D1 is 7, expected 8")));
+}
+
+#[test]
+fn test_entry_point() {
+    let (bytes, _) = assemble_str(
+        "test\n1\n.ops\n.entry main\ndouble: add d0 d0\nret\nmain: cpy d0 3\ncall double\nhalt",
+        false,
+    )
+    .unwrap();
+    let program = Program::try_from(bytes.as_slice()).unwrap();
+    let mut device = Device::from_program(&program).unwrap();
+    assert_eq!(device.dump().pc, 4);
+
+    assert_eq!(device.step(true), RunResult::Pause);
+    assert_eq!(device.data_reg[0], 3);
+    assert_eq!(device.run_to_end().result, RunResult::Halt);
+    assert_eq!(device.acc, 6);
+}
+
+#[test]
+fn test_attach_debug() {
+    let (bytes, debug) = assemble_str(
+        "test\n1\n.ops\ncpy d1 7\nassert d1 7\n\n# comment\n  assert d1 8\nhalt",
+        false,
+    )
+    .unwrap();
+    let program = Program::try_from(bytes.as_slice()).unwrap();
+    let mut device = Device::from_program(&program).unwrap();
+    assert_eq!(device.source_line(6), None);
+    device.attach_debug(&debug);
+
+    assert_eq!(device.run_to_end().result, RunResult::ProgError);
+    assert_eq!(device.dump().pc, 6);
+    assert_eq!(device.source_line(6), Some((8, "assert d1 8")));
+    assert_eq!(device.source_line(7), None);
+    assert!(device
+        .output
+        .iter()
+        .any(|output| matches!(output, OutputErr(msg) if msg == "Line 8: assert d1 8")));
+}
@@ -1,9 +1,18 @@
-use crate::{assert_specific_output, assert_step_device, setup};
+use crate::{assert_no_output, assert_specific_output, assert_step_device, setup};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::rc::Rc;
+use tape_device::assembler::{assemble, assemble_str, AssembleOptions};
 use tape_device::constants::code::{
-    PRTC_AREG, PRTC_REG, PRTC_VAL, PRTD_AREG, PRTLN, PRTS_STR, PRT_AREG, PRT_REG, PRT_VAL,
+    CPY_REG_VAL, HALT, PRTC_AREG, PRTC_REG, PRTC_VAL, PRTD_AREG, PRTLN, PRTLN_STR, PRTS_STR,
+    PRT_AREG, PRT_REG, PRT_VAL,
 };
 use tape_device::constants::hardware::{REG_A0, REG_A1, REG_D0, REG_D2};
+use tape_device::device::comm::Output;
+use tape_device::device::internals::{Device, RunOutcome, RunResult};
 use tape_device::device::Dump;
+use tape_device::tape_reader::Program;
 
 #[test]
 #[rustfmt::skip]
@@ -45,3 +54,154 @@ fn test_multiple_print_ops() {
 
     assert_specific_output(device, "97a68F\nWorld50bbc");
 }
+
+#[derive(Clone, Default)]
+struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_output_writer() {
+    let ops = vec![
+        PRTS_STR, 0, 0,
+        PRTLN,
+    ];
+    let mut device = setup(ops);
+    device.tape_strings = vec![5, 87, 111, 114, 108, 100];
+    let writer = SharedWriter::default();
+    device.set_output_writer(Box::new(writer.clone()));
+
+    assert_step_device("PRTS", &mut device, Dump { pc: 3, ..Default::default() });
+    assert_eq!(writer.0.borrow().as_slice(), b"World");
+    assert_step_device("PRTLN", &mut device, Dump { pc: 4, ..Default::default() });
+    assert_eq!(writer.0.borrow().as_slice(), b"World\n");
+
+    assert_no_output(device);
+}
+
+#[test]
+fn test_device_from_program() {
+    let (bytes, _) = assemble_str(
+        "Strings\n1\n.strings\ngreeting=Hello\nname=World\n.data\nnums=[[4,2]]\n.ops\nprts greeting\nprtc ' '\nprts name\nld a0 nums 1 1\nprt a0\nhalt",
+        false,
+    )
+    .unwrap();
+    let program = Program::try_from(bytes.as_slice()).unwrap();
+    let mut device = Device::from_program(&program).unwrap();
+    assert_eq!(device.dump(), Dump::default());
+
+    while device.step(true) == RunResult::Pause {}
+    assert_specific_output(device, "Hello World2");
+}
+
+#[test]
+fn test_prtln_string() {
+    let (bytes, _) = assemble_str(
+        "Strings\n1\n.strings\ngreeting=Hello\n.ops\nprtln greeting\nprtln\nprts greeting\nhalt",
+        false,
+    )
+    .unwrap();
+    let program = Program::try_from(bytes.as_slice()).unwrap();
+    assert_eq!(program.ops[..3], [PRTLN_STR, 0, 0]);
+    let mut device = Device::from_program(&program).unwrap();
+
+    assert_eq!(device.run_to_end().output, b"Hello\x0A\x0AHello".to_vec());
+}
+
+fn assemble_overlay(src: &str) -> Vec<u8> {
+    let options = AssembleOptions {
+        relocatable: true,
+        ..AssembleOptions::default()
+    };
+    assemble(src, &options).unwrap().bytes
+}
+
+#[test]
+fn test_load_overlay() {
+    let (bytes, _) = assemble_str(
+        "Main\n1\n.strings\ngreeting=Hi\n.data\nnums=[[1]]\n.ops\nprts greeting\ncall @40\nprtln\nhalt",
+        false,
+    )
+    .unwrap();
+    let src = "Overlay\n1\n.strings\nname=there\n.data\nnums=[[7,8]]\n.ops\njmp start\nhalt\nstart: prts name\nld a0 nums 1 1\nprt a0\nret";
+    let overlay = assemble_overlay(src);
+    let program = Program::try_from(bytes.as_slice()).unwrap();
+    let mut device = Device::from_program(&program).unwrap();
+    assert!(device.load_overlay(&overlay, 4).is_err());
+    let (not_relocatable, _) = assemble_str(src, false).unwrap();
+    assert_eq!(
+        device
+            .load_overlay(&not_relocatable, 40)
+            .unwrap_err()
+            .to_string(),
+        "Overlay has no relocation table, assemble it with --relocatable"
+    );
+    assert_eq!(device.load_overlay(&overlay, 40).unwrap(), 40);
+
+    while device.step(true) == RunResult::Pause {}
+    assert_specific_output(device, "Hithere8\n");
+}
+
+#[test]
+fn test_load_overlay_entry() {
+    let (bytes, _) = assemble_str("Main\n1\n.ops\ncall @44\nprtln\nhalt", false).unwrap();
+    let overlay = assemble_overlay(
+        "Overlay\n1\n.strings\nname=there\n.ops\n.entry start\njmp start\nhalt\nstart: prts name\nret",
+    );
+    let program = Program::try_from(bytes.as_slice()).unwrap();
+    let mut device = Device::from_program(&program).unwrap();
+    assert_eq!(device.load_overlay(&overlay, 40).unwrap(), 44);
+
+    while device.step(true) == RunResult::Pause {}
+    assert_specific_output(device, "there\n");
+}
+
+#[test]
+fn test_load_overlay_jump_table() {
+    let (bytes, _) = assemble_str(
+        "Main\n1\n.data\nnums=[[1]]\n.ops\ncpy d0 1\ncall @40\nprtln\nhalt",
+        false,
+    )
+    .unwrap();
+    let overlay = assemble_overlay(
+        "Overlay\n1\n.strings\nzero=zero\none=one\n.ops\n.jumptable d0 first second\nret\nfirst: prts zero\nret\nsecond: prts one\nret",
+    );
+    let program = Program::try_from(bytes.as_slice()).unwrap();
+    let mut device = Device::from_program(&program).unwrap();
+    assert_eq!(device.load_overlay(&overlay, 40).unwrap(), 40);
+
+    while device.step(true) == RunResult::Pause {}
+    assert_specific_output(device, "one\n");
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_run_to_end() {
+    let ops = vec![
+        CPY_REG_VAL, REG_D0, 5,
+        PRT_REG, REG_D0,
+        PRTC_VAL, b'x',
+        HALT,
+    ];
+    let mut device = setup(ops);
+    device.output.push(Output::OutputStd(String::from("before")));
+
+    assert_eq!(
+        device.run_to_end(),
+        RunOutcome {
+            result: RunResult::Halt,
+            final_dump: Dump { pc: 7, data_reg: [5, 0, 0, 0], ..Default::default() },
+            output: b"5x".to_vec(),
+            cycles: 4,
+        }
+    );
+}
@@ -1,6 +1,9 @@
 use crate::{assert_memory, assert_no_output, assert_step_device, setup};
-use tape_device::constants::code::{CALL_ADDR, CALL_AREG, HALT, POP_REG, PUSH_REG, PUSH_VAL, RET};
-use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1};
+use tape_device::constants::code::{
+    ARG_REG_VAL, CALL_ADDR, CALL_AREG, CPY_REG_VAL, DUP, ENTER_VAL, HALT, LDF_REG_VAL, LEAVE,
+    POP_REG, PUSH_REG, PUSH_VAL, RDSP_AREG, RET, STF_REG_VAL, SWAP,
+};
+use tape_device::constants::hardware::{REG_A0, REG_A1, REG_ACC, REG_D0, REG_D1, REG_D2, REG_D3};
 use tape_device::device::internals::RunResult;
 use tape_device::device::Dump;
 
@@ -32,6 +35,44 @@ fn test_multiple_stack_ops() {
     assert_no_output(device);
 }
 
+#[test]
+#[rustfmt::skip]
+fn test_step_n_and_run_until() {
+    let ops = vec![
+        PUSH_VAL, 73,
+        PUSH_REG, REG_D1,
+        POP_REG, REG_ACC,
+        CALL_ADDR, 0, 10,
+        HALT,
+        CALL_AREG, REG_A1,
+        HALT,
+        RET
+    ];
+    let mut device = setup(ops.clone());
+    device.data_reg = [0, 32, 0, 0];
+    device.addr_reg = [0, 13];
+
+    assert_eq!(device.run_until(|dump| dump.acc == 32), RunResult::Pause);
+    assert_eq!(device.pc, 6);
+    assert_eq!(device.step_n(2), RunResult::Pause);
+    assert_eq!(device.pc, 13);
+    assert_eq!(device.step_n(100), RunResult::Halt);
+    assert_eq!(device.pc, 12);
+    assert_eq!(device.step_n(0), RunResult::Pause);
+
+    let mut device = setup(ops.clone());
+    device.breakpoints.push(4);
+    device.breakpoints.push(6);
+    assert_eq!(device.step_n(10), RunResult::Breakpoint);
+    assert_eq!(device.pc, 4);
+    assert_eq!(device.step_n(10), RunResult::Breakpoint);
+    assert_eq!(device.pc, 6);
+
+    let mut device = setup(ops);
+    device.addr_reg = [0, 13];
+    assert_eq!(device.run_until(|dump| dump.acc == 100), RunResult::Halt);
+}
+
 #[test]
 #[rustfmt::skip]
 fn test_multiple_addr_stack_ops() {
@@ -60,3 +101,174 @@ fn test_multiple_addr_stack_ops() {
 
     assert_no_output(device);
 }
+
+#[test]
+#[rustfmt::skip]
+fn test_swap_stack_op() {
+    let ops = vec![
+        PUSH_VAL, 1,
+        PUSH_VAL, 2,
+        SWAP,
+        POP_REG, REG_D0,
+        POP_REG, REG_D1,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("PUSH 1", &mut device, Dump { pc: 2, sp: 65534, ..Default::default() });
+    assert_step_device("PUSH 2", &mut device, Dump { pc: 4, sp: 65533, ..Default::default() });
+    assert_step_device("SWAP", &mut device, Dump { pc: 5, sp: 65533, ..Default::default() });
+    assert_memory(&device, 65533, &[1, 2]);
+    assert_step_device("POP D0", &mut device, Dump { pc: 7, data_reg: [1, 0, 0, 0], sp: 65534, ..Default::default() });
+    assert_step_device("POP D1", &mut device, Dump { pc: 9, data_reg: [1, 2, 0, 0], sp: 65535, ..Default::default() });
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_dup_stack_op() {
+    let ops = vec![
+        PUSH_VAL, 9,
+        DUP,
+        POP_REG, REG_D0,
+        POP_REG, REG_D1,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("PUSH 9", &mut device, Dump { pc: 2, sp: 65534, ..Default::default() });
+    assert_step_device("DUP", &mut device, Dump { pc: 3, sp: 65533, ..Default::default() });
+    assert_memory(&device, 65533, &[9, 9]);
+    assert_step_device("POP D0", &mut device, Dump { pc: 5, data_reg: [9, 0, 0, 0], sp: 65534, ..Default::default() });
+    assert_step_device("POP D1", &mut device, Dump { pc: 7, data_reg: [9, 9, 0, 0], sp: 65535, ..Default::default() });
+
+    assert_no_output(device);
+}
+
+#[test]
+fn test_stack_op_underflow() {
+    let mut device = setup(vec![DUP]);
+    assert_eq!(device.step(true), RunResult::ProgError);
+
+    let mut device = setup(vec![PUSH_VAL, 1, SWAP]);
+    device.step(true);
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_eq!(device.dump().sp, 65534);
+    assert_memory(&device, 65534, &[1]);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_frame_locals() {
+    let ops = vec![
+        CPY_REG_VAL, REG_D0, 42,
+        CALL_ADDR, 0, 7,
+        HALT,
+        //routine
+        PUSH_VAL, 0,            //7
+        STF_REG_VAL, REG_D0, 1,
+        CPY_REG_VAL, REG_D0, 0,
+        CALL_ADDR, 0, 24,
+        LDF_REG_VAL, REG_D1, 1,
+        POP_REG, REG_D3,
+        RET,
+        //inner
+        PUSH_VAL, 99,           //24
+        STF_REG_VAL, REG_D0, 1,
+        POP_REG, REG_D2,
+        RET,
+    ];
+    let mut device = setup(ops);
+
+    for _ in 0..7 {
+        device.step(true);
+    }
+    assert_eq!(device.dump().pc, 26);
+    assert_eq!(device.dump().fp, 65526);
+    assert_memory(&device, 65525, &[99, 18, 0, 251, 255, 42]);
+    device.step(true);
+    assert_memory(&device, 65525, &[0, 18, 0, 251, 255, 42]);
+
+    let mut result = RunResult::Pause;
+    while result == RunResult::Pause {
+        result = device.step(true);
+    }
+    assert_eq!(result, RunResult::Halt);
+    assert_eq!(device.dump(), Dump { pc: 6, data_reg: [0, 42, 0, 42], sp: 65535, fp: 65535, ..Default::default() });
+
+    assert_no_output(device);
+}
+
+#[test]
+fn test_frame_locals_bounds() {
+    let mut device = setup(vec![CALL_ADDR, 0, 3, LDF_REG_VAL, REG_D0, 1]);
+    device.step(true);
+    assert_eq!(device.step(true), RunResult::ProgError);
+
+    let mut device = setup(vec![PUSH_VAL, 1, STF_REG_VAL, REG_D0, 0]);
+    device.step(true);
+    assert_eq!(device.step(true), RunResult::ProgError);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_read_sp() {
+    let ops = vec![
+        RDSP_AREG, REG_A0,
+        PUSH_VAL, 1,
+        PUSH_VAL, 2,
+        RDSP_AREG, REG_A1,
+    ];
+    let mut device = setup(ops);
+
+    assert_step_device("RDSP A0", &mut device, Dump { pc: 2, addr_reg: [65535, 0], ..Default::default() });
+    assert_step_device("PUSH 1", &mut device, Dump { pc: 4, addr_reg: [65535, 0], sp: 65534, ..Default::default() });
+    assert_step_device("PUSH 2", &mut device, Dump { pc: 6, addr_reg: [65535, 0], sp: 65533, ..Default::default() });
+    assert_step_device("RDSP A1", &mut device, Dump { pc: 8, addr_reg: [65535, 65533], sp: 65533, ..Default::default() });
+    assert_eq!(device.dump().addr_reg[1], device.dump().sp);
+
+    assert_no_output(device);
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_enter_leave() {
+    let ops = vec![
+        PUSH_VAL, 9,
+        CALL_ADDR, 0, 6,
+        HALT,
+        ENTER_VAL, 4,
+        STF_REG_VAL, REG_D0, 1,
+        STF_REG_VAL, REG_D1, 4,
+        LDF_REG_VAL, REG_D2, 4,
+        ARG_REG_VAL, REG_D3, 3,
+        LEAVE,
+        RET,
+    ];
+    let mut device = setup(ops);
+    device.data_reg = [1, 2, 0, 0];
+
+    assert_step_device("PUSH 9", &mut device, Dump { pc: 2, data_reg: [1, 2, 0, 0], sp: 65534, ..Default::default() });
+    assert_step_device("CALL lbl", &mut device, Dump { pc: 6, data_reg: [1, 2, 0, 0], sp: 65530, fp: 65530, ..Default::default() });
+    assert_step_device("ENTER 4", &mut device, Dump { pc: 8, data_reg: [1, 2, 0, 0], sp: 65524, fp: 65528, ..Default::default() });
+    assert_memory(&device, 65524, &[0, 0, 0, 0, 0xFA, 0xFF]);
+    assert_step_device("STF D0 1", &mut device, Dump { pc: 11, data_reg: [1, 2, 0, 0], sp: 65524, fp: 65528, ..Default::default() });
+    assert_step_device("STF D1 4", &mut device, Dump { pc: 14, data_reg: [1, 2, 0, 0], sp: 65524, fp: 65528, ..Default::default() });
+    assert_memory(&device, 65524, &[2, 0, 0, 1]);
+    assert_step_device("LDF D2 4", &mut device, Dump { pc: 17, data_reg: [1, 2, 2, 0], sp: 65524, fp: 65528, ..Default::default() });
+    assert_step_device("ARG D3 3", &mut device, Dump { pc: 20, data_reg: [1, 2, 2, 9], sp: 65524, fp: 65528, ..Default::default() });
+    assert_step_device("LEAVE", &mut device, Dump { pc: 21, data_reg: [1, 2, 2, 9], sp: 65530, fp: 65530, ..Default::default() });
+    assert_step_device("RET", &mut device, Dump { pc: 5, data_reg: [1, 2, 2, 9], sp: 65534, ..Default::default() });
+    assert_eq!(device.step(true), RunResult::Halt);
+
+    assert_no_output(device);
+}
+
+#[test]
+fn test_leave_without_frame() {
+    let mut device = setup(vec![PUSH_VAL, 1, LEAVE]);
+    device.step(true);
+    assert_eq!(device.step(true), RunResult::ProgError);
+    assert_eq!(device.dump().sp, 65534);
+    assert_eq!(device.dump().fp, 65535);
+    assert_memory(&device, 65534, &[1]);
+}
@@ -1,4 +1,5 @@
 mod execution;
+mod no_std;
 mod parsing;
 
 extern crate tape_device;
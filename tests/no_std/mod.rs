@@ -0,0 +1,38 @@
+use crate::assert_step_device;
+use std::process::Command;
+use tape_device::constants::code::{ADD_REG_VAL, CPY_REG_VAL, HALT};
+use tape_device::constants::hardware::REG_D0;
+use tape_device::device::internals::{Device, RunResult};
+use tape_device::device::Dump;
+
+#[test]
+fn test_core_builds_without_std() {
+    let output = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--no-default-features", "--target-dir"])
+        .arg(concat!(env!("CARGO_TARGET_TMPDIR"), "/no_std"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+#[rustfmt::skip]
+fn test_step_preloaded_bytes() {
+    let bytes: &[u8] = &[
+        CPY_REG_VAL, REG_D0, 5,
+        ADD_REG_VAL, REG_D0, 3,
+        HALT
+    ];
+    let mut device = Device::new(bytes.to_vec(), vec![], vec![], vec![]);
+
+    assert_step_device("CPY D0 5", &mut device, Dump { pc: 3, data_reg: [5, 0, 0, 0], ..Default::default() });
+    assert_step_device("ADD D0 3", &mut device, Dump { pc: 6, acc: 8, data_reg: [5, 0, 0, 0], ..Default::default() });
+    assert_eq!(device.step(true), RunResult::Halt);
+    assert_eq!(device.acc, 8);
+}
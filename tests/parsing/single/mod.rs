@@ -37,6 +37,6 @@ fn test_single_invalid_instruction_model(mut model: ProgramModel, line: &str, on
             let op = &model.ops[0];
             assert_ne!(op.opcode, only_valid_op, "{}-{:?}", line, model.ops[0]);
         }
-        Err(err) => assert!(err.to_string().contains(partial_error_message), "{}: {}", line, err.to_string())
+        Err(err) => assert!(err.to_string().contains(partial_error_message), "{}: {}", line, err)
     }
 }